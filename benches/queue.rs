@@ -0,0 +1,178 @@
+// Per-frame cost of reading the visible part of the queue, for growing queue
+// lengths. Run with `cargo bench --bench queue`.
+//
+// "full clone" is what drawing used to do: copy the whole queue every frame.
+// "window" is the versioned queue: once the visible rows are loaded, a frame
+// only touches those rows, so its cost should stay flat as the queue grows.
+//
+// The playlist columns time Playlist::layout and draw against the fake
+// server from the tests. "still" redraws an unchanged screen, which reuses
+// the formatted rows; "scroll" moves by a wheel step every frame, so each
+// frame formats the visible rows again.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{
+    hint::black_box,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use common::FakeSong;
+use cursive::{
+    backends::puppet,
+    event::{Event, MouseEvent},
+    theme::Theme,
+    Printer, View, XY,
+};
+use mpcursive::{
+    meta::SongMeta,
+    mpd_util::queue::{Queue, QueueSource},
+    view::playlist::Playlist,
+};
+use mpd::{
+    song::{Id, QueuePlace},
+    Song,
+};
+
+const SCREEN_ROWS: usize = 50;
+const FRAMES: u32 = 200;
+
+fn song(i: usize) -> Song {
+    Song {
+        file: format!("artist {}/album/{:05}.flac", i / 100, i),
+        title: Some(format!("Song {}", i)),
+        artist: Some(format!("Artist {}", i / 100)),
+        duration: Some(Duration::from_secs(200)),
+        place: Some(QueuePlace {
+            id: Id(i as u32),
+            pos: i as u32,
+            prio: 0,
+        }),
+        tags: vec![
+            ("Album".into(), format!("Album {}", i / 10)),
+            ("Track".into(), format!("{}", i % 10 + 1)),
+        ],
+        ..Default::default()
+    }
+}
+
+struct Fake {
+    songs: Vec<Song>,
+}
+
+impl Fake {
+    fn new(len: usize) -> Self {
+        Self {
+            songs: (0..len).map(song).collect(),
+        }
+    }
+}
+
+impl QueueSource for Fake {
+    fn window(&mut self, range: Range<usize>) -> Result<Vec<Song>> {
+        Ok(self.songs[range].to_vec())
+    }
+
+    fn changes(&mut self, _version: u32) -> Result<Vec<usize>> {
+        Ok(vec![])
+    }
+}
+
+fn per_frame(mut frame: impl FnMut()) -> Duration {
+    frame();
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame();
+    }
+    start.elapsed() / FRAMES
+}
+
+// Layout and draw of a full-screen Playlist, as Root does every frame
+fn playlist_frames(len: usize) -> (Duration, Duration) {
+    let (fake, app) = common::server();
+    fake.state().set_queue(
+        (0..len)
+            .map(|i| {
+                let artist = format!("Artist {}", i / 100);
+                let album = format!("Album {}", i / 10);
+                let title = format!("Song {}", i);
+                let track = (i % 10 + 1).to_string();
+                FakeSong::new(
+                    &format!("{}/{}/{:05}.flac", artist, album, i),
+                    &[
+                        ("Artist", &artist),
+                        ("Album", &album),
+                        ("Title", &title),
+                        ("Track", &track),
+                    ],
+                    200.0,
+                )
+            })
+            .collect(),
+    );
+    let size = XY::new(120, SCREEN_ROWS);
+    let backend = puppet::Backend::init(Some(size));
+    let theme = Theme::default();
+    let mut playlist = Playlist::new(app);
+    let frame = |playlist: &mut Playlist| {
+        playlist.layout(size);
+        playlist.draw(&Printer::new(size, &theme, &*backend));
+    };
+    let wheel = |event| Event::Mouse {
+        offset: XY::zero(),
+        position: XY::new(0, SCREEN_ROWS / 2),
+        event,
+    };
+    // both scroll positions are fetched before timing
+    frame(&mut playlist);
+    playlist.on_event(wheel(MouseEvent::WheelDown));
+    frame(&mut playlist);
+
+    let still = per_frame(|| frame(&mut playlist));
+    let mut down = false;
+    let scroll = per_frame(|| {
+        down = !down;
+        playlist.on_event(wheel(if down {
+            MouseEvent::WheelUp
+        } else {
+            MouseEvent::WheelDown
+        }));
+        frame(&mut playlist);
+    });
+    (still, scroll)
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>14} {:>14} {:>14} {:>14}",
+        "queue", "full clone", "window", "still", "scroll"
+    );
+    for len in [1_000, 10_000, 50_000] {
+        let mut fake = Fake::new(len);
+
+        let full: Vec<SongMeta> = fake.songs.iter().cloned().map(SongMeta::from).collect();
+        let clone = per_frame(|| {
+            let q = black_box(&full).clone();
+            black_box(&q[len / 2..len / 2 + SCREEN_ROWS]);
+        });
+
+        let mut queue = Queue::new();
+        queue.sync(1, len, &mut fake).unwrap();
+        let window = per_frame(|| {
+            let rows: Vec<Arc<SongMeta>> = queue
+                .window(len / 2..len / 2 + SCREEN_ROWS, &mut fake)
+                .unwrap();
+            black_box(rows);
+        });
+
+        let (still, scroll) = playlist_frames(len);
+        println!(
+            "{:>8} {:>14?} {:>14?} {:>14?} {:>14?}",
+            len, clone, window, still, scroll
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use crate::clipboard::Clipboard;
+use crate::config::Config;
+use crate::mpd_util::{library_cache, MPD};
+use crate::notice::Notices;
+
+// Everything one running client shares: its settings and its server. Views
+// keep a clone; nothing here is global, so several can exist side by side.
+#[derive(Clone)]
+pub struct App {
+    pub config: Arc<Config>,
+    pub mpd: MPD,
+    pub clipboard: Clipboard,
+    pub notices: Notices,
+}
+
+impl App {
+    pub fn new(config: Config) -> Self {
+        let address = config.mpd.address();
+        let mpd = MPD::new(
+            &address,
+            config.mpd.password.clone(),
+            library_cache::base(&address),
+        );
+        Self {
+            config: Arc::new(config),
+            mpd,
+            clipboard: Clipboard::terminal(),
+            notices: Notices::default(),
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cursive::{
+    backend::Backend,
+    event::Event,
+    theme::{Color, ColorPair, Effect},
+    Vec2,
+};
+use image::RgbaImage;
+
+use crate::config::ArtProtocol;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    // two pixels per cell, as the colors of ▀
+    HalfBlock,
+    Sixel,
+    Kitty,
+}
+
+// Which protocol the configured choice comes down to. Terminals advertise
+// graphics support through their environment.
+pub fn detect(choice: ArtProtocol) -> Protocol {
+    match choice {
+        ArtProtocol::Auto => from_env(|k| env::var(k).ok()),
+        ArtProtocol::Halfblock => Protocol::HalfBlock,
+        ArtProtocol::Sixel => Protocol::Sixel,
+        ArtProtocol::Kitty => Protocol::Kitty,
+    }
+}
+
+pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Protocol {
+    // tmux would need every sequence wrapped in a passthrough
+    if var("TMUX").is_some() {
+        return Protocol::HalfBlock;
+    }
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    if var("KITTY_WINDOW_ID").is_some()
+        || ["xterm-kitty", "xterm-ghostty"].contains(&term.as_str())
+        || ["ghostty", "WezTerm"].contains(&program.as_str())
+    {
+        Protocol::Kitty
+    } else if term.contains("sixel")
+        || ["foot", "mlterm", "contour", "yaft"]
+            .iter()
+            .any(|t| term.starts_with(t))
+    {
+        Protocol::Sixel
+    } else {
+        Protocol::HalfBlock
+    }
+}
+
+// Pixels per cell, for sizing images. Terminals that don't report their
+// pixel size get a common guess.
+pub fn cell_size() -> Vec2 {
+    match (termion::terminal_size(), termion::terminal_size_pixels()) {
+        (Ok((cols, rows)), Ok((w, h))) if cols > 0 && rows > 0 && w > 0 && h > 0 => {
+            Vec2::new((w / cols) as usize, (h / rows) as usize)
+        }
+        _ => Vec2::new(8, 16),
+    }
+}
+
+// An image the terminal draws itself, from an escape sequence written at
+// origin (in screen cells). id changes whenever the image does.
+#[derive(Clone)]
+pub struct Placement {
+    pub origin: Vec2,
+    pub id: u32,
+    pub data: Arc<str>,
+}
+
+impl PartialEq for Placement {
+    fn eq(&self, other: &Self) -> bool {
+        self.origin == other.origin && self.id == other.id
+    }
+}
+
+#[derive(Default)]
+struct Frames {
+    next: Option<Placement>, // placed while drawing the current frame
+    shown: Option<Placement>,
+    cleared: bool,
+}
+
+// Escape sequence images can't go through cursive: it crops printed text to
+// the cells it covers, and paints over whatever it doesn't know about. Views
+// place them here while drawing instead, and the backend writes them once
+// the frame is out.
+#[derive(Clone)]
+pub struct Graphics {
+    pub protocol: Protocol,
+    frames: Arc<Mutex<Frames>>,
+}
+
+impl Graphics {
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol,
+            frames: Arc::new(Mutex::new(Frames::default())),
+        }
+    }
+
+    // Shows the image after this frame. A view that stops placing its
+    // image has it removed.
+    pub fn place(&self, placement: Placement) {
+        self.frames.lock().unwrap().next = Some(placement);
+    }
+
+    pub fn backend(&self, inner: Box<dyn Backend>) -> Box<dyn Backend> {
+        Box::new(GraphicsBackend {
+            inner,
+            graphics: self.clone(),
+        })
+    }
+
+    // What to write to the terminal after a frame
+    fn after_frame(&self) -> String {
+        let mut frames = self.frames.lock().unwrap();
+        let next = frames.next.take();
+        let mut out = String::new();
+        match self.protocol {
+            Protocol::HalfBlock => {}
+            // cursive paints over sixels with every frame
+            Protocol::Sixel => {
+                if let Some(p) = &next {
+                    out.push_str(&goto(p.origin));
+                    out.push_str(&p.data);
+                }
+            }
+            // kitty keeps images until told otherwise
+            Protocol::Kitty => {
+                if frames.cleared {
+                    out.push_str("\x1b_Ga=d,d=A,q=2\x1b\\");
+                } else if let Some(old) = frames.shown.as_ref().filter(|_| frames.shown != next) {
+                    out.push_str(&format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", old.id));
+                }
+                if let Some(p) = next
+                    .as_ref()
+                    .filter(|_| frames.cleared || frames.shown != next)
+                {
+                    out.push_str(&goto(p.origin));
+                    out.push_str(&p.data);
+                }
+            }
+        }
+        frames.shown = next;
+        frames.cleared = false;
+        if out.is_empty() {
+            return out;
+        }
+        // the cursor is left where cursive put it
+        format!("\x1b7{}\x1b8", out)
+    }
+}
+
+fn goto(pos: Vec2) -> String {
+    format!("\x1b[{};{}H", pos.y + 1, pos.x + 1)
+}
+
+struct GraphicsBackend {
+    inner: Box<dyn Backend>,
+    graphics: Graphics,
+}
+
+impl Backend for GraphicsBackend {
+    fn poll_event(&mut self) -> Option<Event> {
+        self.inner.poll_event()
+    }
+
+    fn set_title(&mut self, title: String) {
+        self.inner.set_title(title)
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+        let out = self.graphics.after_frame();
+        if !out.is_empty() {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(out.as_bytes());
+            let _ = stdout.flush();
+        }
+    }
+
+    fn has_colors(&self) -> bool {
+        self.inner.has_colors()
+    }
+
+    fn screen_size(&self) -> Vec2 {
+        self.inner.screen_size()
+    }
+
+    fn print_at(&self, pos: Vec2, text: &str) {
+        self.inner.print_at(pos, text)
+    }
+
+    fn print_at_rep(&self, pos: Vec2, repetitions: usize, text: &str) {
+        self.inner.print_at_rep(pos, repetitions, text)
+    }
+
+    fn clear(&self, color: Color) {
+        self.inner.clear(color);
+        let mut frames = self.graphics.frames.lock().unwrap();
+        frames.shown = None;
+        frames.cleared = true;
+    }
+
+    fn set_color(&self, colors: ColorPair) -> ColorPair {
+        self.inner.set_color(colors)
+    }
+
+    fn set_effect(&self, effect: Effect) {
+        self.inner.set_effect(effect)
+    }
+
+    fn unset_effect(&self, effect: Effect) {
+        self.inner.unset_effect(effect)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+// Sixel with a fixed 6x6x6 color cube, which is close enough for covers and
+// needs no palette search
+pub fn sixel(image: &RgbaImage) -> String {
+    let (w, h) = image.dimensions();
+    // P2=1: pixels left at 0 keep the background
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", w, h);
+    for i in 0..216 {
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            i / 36 * 20,
+            i / 6 % 6 * 20,
+            i % 6 * 20
+        ));
+    }
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    for top in (0..h).step_by(6) {
+        // each color in this band of six rows, with its bits per column
+        let mut bands: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for y in top..(top + 6).min(h) {
+            for x in 0..w {
+                let [r, g, b, _] = image.get_pixel(x, y).0;
+                let color = level(r) * 36 + level(g) * 6 + level(b);
+                bands.entry(color).or_insert_with(|| vec![0; w as usize])[x as usize] |=
+                    1 << (y - top);
+            }
+        }
+        for (i, (color, bits)) in bands.iter().enumerate() {
+            if i > 0 {
+                out.push('$');
+            }
+            out.push_str(&format!("#{}", color));
+            push_runs(&mut out, bits);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+// Sixel characters, run-length encoded
+fn push_runs(out: &mut String, bits: &[u8]) {
+    let mut i = 0;
+    while i < bits.len() {
+        let n = bits[i..].iter().take_while(|b| **b == bits[i]).count();
+        let c = (63 + bits[i]) as char;
+        if n > 3 {
+            out.push_str(&format!("!{}{}", n, c));
+        } else {
+            (0..n).for_each(|_| out.push(c));
+        }
+        i += n;
+    }
+}
+
+// Transmits and shows the image as raw RGBA, scaled by the terminal to
+// cells. Payloads are sent in chunks of 4096 bytes, as kitty requires.
+pub fn kitty(image: &RgbaImage, id: u32, cells: Vec2) -> String {
+    let data = STANDARD.encode(image.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=32,s={},v={},i={},c={},r={},C=1,q=2,m={};",
+                image.width(),
+                image.height(),
+                id,
+                cells.x,
+                cells.y,
+                more
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};", more));
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+    out
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use cursive::{event::Event, CbSink};
+use image::{imageops::FilterType, RgbaImage};
+use log::{log, Level};
+
+use crate::meta::SongMeta;
+use crate::mpd_util::{proto::Ack, MPD};
+
+pub mod graphics;
+
+// Albums whose covers are kept in memory
+const CACHED_ALBUMS: usize = 32;
+// Bytes per binary response while fetching
+const CHUNK_SIZE: usize = 256 * 1024;
+// Covers are shrunk to this on load, which is plenty for a terminal
+const MAX_SIZE: u32 = 512;
+// After a failed fetch, doubling with every failure in a row
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Clone)]
+pub enum Cover {
+    Loading,
+    Missing,
+    Image(Arc<RgbaImage>),
+}
+
+// Decoded covers by album. A cover that isn't known yet is fetched in the
+// background on a connection of its own, and the UI is redrawn through sink
+// once it is in.
+#[derive(Clone)]
+pub struct Covers {
+    mpd: MPD,
+    sink: CbSink,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    images: HashMap<String, Option<Arc<RgbaImage>>>,
+    order: VecDeque<String>, // oldest first
+    fetching: Option<String>,
+    // albums whose last fetch failed, by when to try again and failures
+    failed: HashMap<String, (Instant, u32)>,
+}
+
+impl State {
+    fn insert(&mut self, key: String, image: Option<Arc<RgbaImage>>) {
+        if self.images.insert(key.clone(), image).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > CACHED_ALBUMS {
+            if let Some(old) = self.order.pop_front() {
+                self.images.remove(&old);
+            }
+        }
+    }
+}
+
+impl Covers {
+    pub fn new(mpd: MPD, sink: CbSink) -> Self {
+        Self {
+            mpd,
+            sink,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    pub fn get(&self, song: &SongMeta) -> Cover {
+        let key = album_key(song);
+        let mut state = self.state.lock().unwrap();
+        match state.images.get(&key) {
+            Some(Some(image)) => return Cover::Image(image.clone()),
+            Some(None) => return Cover::Missing,
+            None => {}
+        }
+        // shown as missing until it is worth asking again
+        if state
+            .failed
+            .get(&key)
+            .is_some_and(|(at, _)| Instant::now() < *at)
+        {
+            return Cover::Missing;
+        }
+        // one at a time; a song skipped past while loading is asked for again
+        // on the next redraw
+        if state.fetching.is_none() {
+            state.fetching = Some(key.clone());
+            let covers = self.clone();
+            let file = song.file().to_string();
+            thread::Builder::new()
+                .name(String::from("cover"))
+                .spawn(move || covers.load(key, &file))
+                .expect("Failed to spawn cover thread");
+        }
+        Cover::Loading
+    }
+
+    fn load(&self, key: String, file: &str) {
+        let result = fetch(&self.mpd, file);
+        let mut state = self.state.lock().unwrap();
+        state.fetching = None;
+        match result {
+            Ok(data) => {
+                state.failed.remove(&key);
+                state.insert(key, data.and_then(|d| decode(&d, file)).map(Arc::new));
+            }
+            // not cached, so it is tried again once the delay is up
+            Err(e) => {
+                log!(Level::Warn, "Failed to fetch cover for {}: {}", file, e);
+                let now = Instant::now();
+                // failures long past say nothing about the server now
+                state
+                    .failed
+                    .retain(|_, (at, _)| now.duration_since(*at) < MAX_RETRY_DELAY);
+                let failures = state.failed.get(&key).map_or(0, |(_, n)| *n);
+                let delay = RETRY_DELAY
+                    .saturating_mul(1 << failures.min(16))
+                    .min(MAX_RETRY_DELAY);
+                state.failed.insert(key, (now + delay, failures + 1));
+            }
+        }
+        drop(state);
+        let _ = self.sink.send(Box::new(|s| s.on_event(Event::Refresh)));
+    }
+}
+
+// Songs share a cover when they share an album, or a directory if untagged
+pub fn album_key(song: &SongMeta) -> String {
+    match (&song.album, song.album_artist("; ")) {
+        (Some(album), artist) => format!("{}\n{}", artist.unwrap_or_default(), album),
+        (None, _) => {
+            let file = song.file();
+            file.rsplit_once('/').map(|(d, _)| d).unwrap_or("").into()
+        }
+    }
+}
+
+// A picture embedded in the file, else a cover file in its directory
+pub fn fetch(mpd: &MPD, file: &str) -> Result<Option<Vec<u8>>> {
+    let mut conn = mpd.open_raw()?;
+    // covers are far larger than the default chunk
+    conn.binary_limit(CHUNK_SIZE)?;
+    for command in ["readpicture", "albumart"] {
+        match conn.read_binary(command, file) {
+            Ok(Some(chunk)) => return Ok(Some(chunk.data)),
+            Ok(None) => {}
+            // no such file, or a server too old for the command
+            Err(e) if e.downcast_ref::<Ack>().is_some() => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+fn decode(data: &[u8], file: &str) -> Option<RgbaImage> {
+    match image::load_from_memory(data) {
+        Ok(image) if image.width() > MAX_SIZE || image.height() > MAX_SIZE => {
+            Some(image.thumbnail(MAX_SIZE, MAX_SIZE).to_rgba8())
+        }
+        Ok(image) => Some(image.to_rgba8()),
+        Err(e) => {
+            log!(Level::Warn, "Unreadable cover for {}: {}", file, e);
+            None
+        }
+    }
+}
+
+// image scaled to fit within width x height, keeping its aspect ratio
+pub fn fit(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let scale = f64::min(width as f64 / w as f64, height as f64 / h as f64);
+    let w = ((w as f64 * scale) as u32).clamp(1, width.max(1));
+    let h = ((h as f64 * scale) as u32).clamp(1, height.max(1));
+    image::imageops::resize(image, w, h, FilterType::Triangle)
+}
@@ -0,0 +1,42 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+// Copies through the terminal with OSC 52, which also works over ssh and
+// needs no display server. tmux passes it on with set-clipboard enabled.
+#[derive(Clone, Default)]
+pub struct Clipboard {
+    terminal: bool,
+    last: Arc<Mutex<Option<String>>>,
+}
+
+impl Clipboard {
+    // Writes to stdout; the default only remembers what was copied
+    pub fn terminal() -> Self {
+        Self {
+            terminal: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn copy(&self, text: &str) {
+        *self.last.lock().unwrap() = Some(text.into());
+        if self.terminal {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(osc52(text).as_bytes());
+            let _ = stdout.flush();
+        }
+    }
+
+    // The text copied most recently
+    pub fn last(&self) -> Option<String> {
+        self.last.lock().unwrap().clone()
+    }
+}
+
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
@@ -0,0 +1,459 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use log::{log, Level};
+use serde::Deserialize;
+
+use crate::view::layout::Layout;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub mpd: MpdConfig,
+    pub tags: TagsConfig,
+    pub status: StatusConfig,
+    pub tabs: TabsConfig,
+    pub layouts: Vec<LayoutConfig>,
+    pub art: ArtConfig,
+    pub lyrics: LyricsConfig,
+    pub visualizer: VisualizerConfig,
+    pub plays: PlaysConfig,
+    pub stickers: StickersConfig,
+    pub resume: ResumeConfig,
+    pub history: HistoryConfig,
+    pub scrobble: ScrobbleConfig,
+    pub mpris: MprisConfig,
+    pub hooks: HooksConfig,
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/mpcursive/config.toml, falling back to ~/.config
+    pub fn path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("mpcursive").join("config.toml"))
+    }
+
+    // The config file if there is one, with the environment applied
+    pub fn load() -> Self {
+        let mut config = match Config::path().map(|p| (fs::read_to_string(&p), p)) {
+            Some((Ok(s), path)) => Config::parse(&s).unwrap_or_else(|e| {
+                log!(Level::Warn, "Invalid config {}: {}", path.display(), e);
+                Config::default()
+            }),
+            _ => Config::default(),
+        };
+        config.mpd.apply_env();
+        config
+    }
+
+    pub fn parse(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+}
+
+// $XDG_DATA_HOME/mpcursive/<name>, falling back to ~/.local/share, for what
+// the client keeps between runs
+pub fn data_path(name: &str) -> Option<PathBuf> {
+    let base = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))?;
+    Some(base.join("mpcursive").join(name))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MpdConfig {
+    pub host: String,
+    pub port: u16,
+    // sent on every connection, for servers that require one
+    pub password: Option<String>,
+    // MPD's own music_directory, for files kept next to songs
+    #[serde(deserialize_with = "home_path")]
+    pub music_directory: Option<PathBuf>,
+}
+
+impl Default for MpdConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from("127.0.0.1"),
+            port: 6600,
+            password: None,
+            music_directory: None,
+        }
+    }
+}
+
+impl MpdConfig {
+    // MPD_HOST and MPD_PORT override the config, as in other MPD clients
+    fn apply_env(&mut self) {
+        if let Ok(host) = env::var("MPD_HOST") {
+            self.set_host(&host);
+        }
+        if let Some(port) = env::var("MPD_PORT").ok().and_then(|p| p.parse().ok()) {
+            self.port = port;
+        }
+    }
+
+    // A host as MPD_HOST gives it, which may start with a password as in
+    // password@host
+    pub fn set_host(&mut self, host: &str) {
+        match host.rsplit_once('@') {
+            Some((password, host)) if !password.is_empty() => {
+                self.password = Some(password.into());
+                self.host = host.into();
+            }
+            _ => self.host = host.into(),
+        }
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    // Where a song file is on this machine. Streams and songs outside the
+    // database have no place under the music directory.
+    pub fn local_path(&self, file: &str) -> Option<PathBuf> {
+        let dir = self.music_directory.as_ref()?;
+        if file.contains("://") || Path::new(file).is_absolute() {
+            return None;
+        }
+        Some(dir.join(file))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TagsConfig {
+    // Placed between the values of multi-valued tags, e.g. several artists
+    pub joiner: String,
+}
+
+impl Default for TagsConfig {
+    fn default() -> Self {
+        Self {
+            joiner: String::from("; "),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeMode {
+    Elapsed,
+    Remaining,
+}
+
+impl TimeMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            TimeMode::Elapsed => TimeMode::Remaining,
+            TimeMode::Remaining => TimeMode::Elapsed,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StatusConfig {
+    // Right-aligned on the title row. Placeholders: {time} {elapsed} {total}
+    // {remaining} {flags} {volume} {bitrate} {audio} {position}
+    pub format: String,
+    pub time_mode: TimeMode,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            format: String::from("{flags} {volume} {bitrate} {audio} {position} {time}"),
+            time_mode: TimeMode::Elapsed,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TabsConfig {
+    // Tab names shown first, in this order; unlisted tabs follow
+    pub order: Vec<String>,
+    pub disabled: Vec<String>,
+}
+
+// An extra tab showing several views at once, e.g.
+//
+// [[layouts]]
+// name = "Split"
+// key = "3"
+// layout = { split = "horizontal", ratios = [2, 1], panes = ["queue", "log"] }
+#[derive(Debug, Deserialize)]
+pub struct LayoutConfig {
+    pub name: String,
+    pub key: Option<char>,
+    #[serde(default = "LayoutConfig::default_icon")]
+    pub icon: String,
+    pub layout: Layout,
+}
+
+impl LayoutConfig {
+    fn default_icon() -> String {
+        String::from("◫")
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtProtocol {
+    // sixel or kitty graphics if the terminal is known to support them
+    #[default]
+    Auto,
+    Halfblock,
+    Sixel,
+    Kitty,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ArtConfig {
+    pub protocol: ArtProtocol,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LyricsConfig {
+    // Holds "Artist - Title.lrc" files, for songs without lyrics of their own
+    #[serde(deserialize_with = "home_path")]
+    pub folder: Option<PathBuf>,
+}
+
+// A path with a leading ~ standing for the home directory
+fn home_path<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<PathBuf>, D::Error> {
+    let path = String::deserialize(d)?;
+    Ok(Some(match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizerMode {
+    #[default]
+    Spectrum,
+    Scope,
+    // spectrum above, scope below
+    Both,
+}
+
+impl VisualizerMode {
+    pub fn next(self) -> Self {
+        match self {
+            VisualizerMode::Spectrum => VisualizerMode::Scope,
+            VisualizerMode::Scope => VisualizerMode::Both,
+            VisualizerMode::Both => VisualizerMode::Spectrum,
+        }
+    }
+}
+
+// Reads what MPD plays from a fifo output, e.g.
+//
+// audio_output {
+//     type   "fifo"
+//     name   "visualizer"
+//     path   "/tmp/mpd.fifo"
+//     format "44100:16:2"
+// }
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct VisualizerConfig {
+    #[serde(deserialize_with = "home_path")]
+    pub fifo: Option<PathBuf>,
+    // as in the output's format: rate:bits:channels, bits being 16, 32 or f
+    pub format: String,
+    // 0 fits as many as the width allows
+    pub bars: usize,
+    // 0 follows the music exactly, towards 1 bars change ever more slowly
+    pub smoothing: f32,
+    // how fast bars drop, in heights per second
+    pub falloff: f32,
+    pub mode: VisualizerMode,
+}
+
+impl Default for VisualizerConfig {
+    fn default() -> Self {
+        Self {
+            fifo: None,
+            format: String::from("44100:16:2"),
+            bars: 0,
+            smoothing: 0.5,
+            falloff: 1.5,
+            mode: VisualizerMode::Spectrum,
+        }
+    }
+}
+
+// What counts as a play, wherever plays are kept
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PlaysConfig {
+    // a song counts as played once this percentage of it was listened to
+    pub threshold: u8,
+}
+
+impl Default for PlaysConfig {
+    fn default() -> Self {
+        Self { threshold: 50 }
+    }
+}
+
+// Ratings and play counts, kept in MPD's sticker database
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StickersConfig {
+    // adds a rating column to the queue
+    pub rating_column: bool,
+    // counts plays in a sticker per song
+    pub play_counts: bool,
+}
+
+impl Default for StickersConfig {
+    fn default() -> Self {
+        Self {
+            rating_column: false,
+            play_counts: true,
+        }
+    }
+}
+
+// Where playback stopped is remembered for songs from these directories
+// (relative to the music directory) or with these genres, e.g.
+//
+// [resume]
+// directories = ["Audiobooks", "Mixes"]
+// genres = ["Audiobook", "Podcast"]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ResumeConfig {
+    pub directories: Vec<String>,
+    pub genres: Vec<String>,
+}
+
+impl ResumeConfig {
+    pub fn enabled(&self) -> bool {
+        !self.directories.is_empty() || !self.genres.is_empty()
+    }
+}
+
+// Every play, as [plays] counts them, is recorded in a local history
+// file, one JSON object per line
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    // defaults to $XDG_DATA_HOME/mpcursive/history.jsonl
+    #[serde(deserialize_with = "home_path")]
+    pub file: Option<PathBuf>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            file: None,
+        }
+    }
+}
+
+// The client shows up on the session bus as an MPRIS player, for media keys
+// and desktop widgets
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MprisConfig {
+    pub enabled: bool,
+}
+
+impl Default for MprisConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+// Commands run through sh when playback changes, e.g.
+//
+// [hooks]
+// on_song_change = 'notify-send "$MPCURSIVE_TITLE" "$MPCURSIVE_ARTIST"'
+//
+// The song and player state come as MPCURSIVE_* variables and as JSON on
+// stdin. Hooks run one at a time and are killed after timeout seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_song_change: Option<String>,
+    pub on_play: Option<String>,
+    pub on_pause: Option<String>,
+    pub on_stop: Option<String>,
+    pub on_queue_change: Option<String>,
+    pub on_connect: Option<String>,
+    pub timeout: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_song_change: None,
+            on_play: None,
+            on_pause: None,
+            on_stop: None,
+            on_queue_change: None,
+            on_connect: None,
+            timeout: 10,
+        }
+    }
+}
+
+impl HooksConfig {
+    pub fn enabled(&self) -> bool {
+        [
+            &self.on_song_change,
+            &self.on_play,
+            &self.on_pause,
+            &self.on_stop,
+            &self.on_queue_change,
+            &self.on_connect,
+        ]
+        .iter()
+        .any(|c| c.is_some())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrobbleBackend {
+    ListenBrainz,
+    LastFm,
+    File,
+}
+
+// Where listens are submitted, e.g.
+//
+// [scrobble]
+// backend = "listenbrainz"
+// token = "..."
+//
+// Last.fm takes api_key, api_secret and session_key instead, and "file"
+// appends listens to file in ListenBrainz's import format. url overrides
+// the service's API root.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    pub backend: Option<ScrobbleBackend>,
+    pub url: Option<String>,
+    pub token: String,
+    pub api_key: String,
+    pub api_secret: String,
+    pub session_key: String,
+    #[serde(deserialize_with = "home_path")]
+    pub file: Option<PathBuf>,
+}
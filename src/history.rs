@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use log::{log, Level};
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::config::data_path;
+use crate::meta::SongMeta;
+use crate::mpd_util::stickers::unix_time;
+
+// One completed play, a line of the history file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Play {
+    // when listening started, in seconds since the epoch
+    pub time: u64,
+    pub file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    // in seconds, as are the others
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    pub listened: f64,
+}
+
+impl Play {
+    pub fn new(song: &SongMeta, joiner: &str, started: SystemTime, listened: Duration) -> Self {
+        Self {
+            time: unix_time(started),
+            file: song.file().into(),
+            title: song.title.clone().or_else(|| song.song.name.clone()),
+            artist: song.artist(joiner),
+            album_artist: song.album_artist(joiner),
+            album: song.album.clone(),
+            duration: song.duration().map(secs),
+            listened: secs(listened),
+        }
+    }
+
+    // The title, falling back to the file name
+    pub fn display_title(&self) -> &str {
+        self.title.as_deref().unwrap_or_else(|| {
+            let file = self.file.trim_end_matches('/');
+            file.rsplit('/').next().unwrap_or(file)
+        })
+    }
+
+    pub fn listened(&self) -> Duration {
+        Duration::try_from_secs_f64(self.listened).unwrap_or_default()
+    }
+}
+
+// Tenths are plenty in the file
+fn secs(d: Duration) -> f64 {
+    (d.as_secs_f64() * 10.0).round() / 10.0
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    data_path("history.jsonl")
+}
+
+// The plays recorded so far. The file is only read when they are first asked
+// for, and appended to from then on. Without a file plays are kept in memory.
+#[derive(Clone)]
+pub struct History {
+    file: Option<PathBuf>,
+    plays: Arc<Mutex<Option<Arc<Vec<Play>>>>>,
+}
+
+impl History {
+    pub fn new(file: Option<PathBuf>) -> Self {
+        Self {
+            file,
+            plays: Arc::default(),
+        }
+    }
+
+    // Oldest first. A new list is returned after every play recorded.
+    pub fn plays(&self) -> Arc<Vec<Play>> {
+        let mut plays = self.plays.lock().unwrap();
+        plays
+            .get_or_insert_with(|| {
+                let Some(file) = &self.file else {
+                    return Arc::default();
+                };
+                Arc::new(load(file).unwrap_or_else(|e| {
+                    log!(Level::Warn, "Failed to read {}: {}", file.display(), e);
+                    vec![]
+                }))
+            })
+            .clone()
+    }
+
+    pub fn record(&self, play: Play) -> Result<()> {
+        if let Some(file) = &self.file {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut line = serde_json::to_string(&play)?;
+            line.push('\n');
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)?
+                .write_all(line.as_bytes())?;
+        }
+        let mut plays = self.plays.lock().unwrap();
+        match plays.as_mut() {
+            Some(plays) => Arc::make_mut(plays).push(play),
+            // still to be read, with this one in it
+            None if self.file.is_some() => {}
+            None => *plays = Some(Arc::new(vec![play])),
+        }
+        Ok(())
+    }
+}
+
+// Lines that don't parse, e.g. one cut short by a crash, are skipped
+fn load(path: &Path) -> Result<Vec<Play>> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut plays = vec![];
+    let mut bad = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(play) => plays.push(play),
+            Err(_) => bad += 1,
+        }
+    }
+    if bad > 0 {
+        log!(
+            Level::Warn,
+            "Skipped {} unreadable lines in {}",
+            bad,
+            path.display()
+        );
+    }
+    Ok(plays)
+}
+
+// Records plays once they are over: the song changed, started over or
+// stopped, or the client exits. Plays decides what counts as a play, and
+// the time listened includes what came after it counted.
+pub struct Recorder {
+    history: History,
+    joiner: String,
+    pending: Option<Play>,
+}
+
+impl Recorder {
+    pub fn new(history: History, joiner: &str) -> Self {
+        Self {
+            history,
+            joiner: joiner.into(),
+            pending: None,
+        }
+    }
+
+    // song just counted, after listened
+    pub(crate) fn counted(&mut self, song: &SongMeta, listened: Duration) {
+        self.finish();
+        self.pending = Some(Play::new(
+            song,
+            &self.joiner,
+            SystemTime::now() - listened,
+            listened,
+        ));
+    }
+
+    // How long the current listen has lasted if it counted; None once it
+    // is over
+    pub(crate) fn listening(&mut self, listened: Option<Duration>) {
+        match (listened, &mut self.pending) {
+            (Some(listened), Some(play)) => play.listened = secs(listened),
+            _ => self.finish(),
+        }
+    }
+
+    pub(crate) fn finish(&mut self) {
+        let Some(play) = self.pending.take() else {
+            return;
+        };
+        if let Err(e) = self.history.record(play) {
+            log!(Level::Warn, "Failed to record play: {}", e);
+        }
+    }
+}
+
+// What the statistics cover: the last so many days, today included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Today,
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl Period {
+    pub fn next(self) -> Self {
+        match self {
+            Period::Today => Period::Week,
+            Period::Week => Period::Month,
+            Period::Month => Period::Year,
+            Period::Year => Period::All,
+            Period::All => Period::Today,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Period::Today => "today",
+            Period::Week => "last 7 days",
+            Period::Month => "last 30 days",
+            Period::Year => "last 365 days",
+            Period::All => "all time",
+        }
+    }
+
+    fn days(self) -> Option<i64> {
+        match self {
+            Period::Today => Some(1),
+            Period::Week => Some(7),
+            Period::Month => Some(30),
+            Period::Year => Some(365),
+            Period::All => None,
+        }
+    }
+}
+
+// A song, album or artist with what was played of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Count {
+    pub name: String,
+    pub plays: usize,
+    pub listened: Duration,
+    pub files: Vec<String>, // as first played
+}
+
+// Listening over a period, as of now in local time
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub plays: usize,
+    pub listened: Duration,
+    pub artists: Vec<Count>,
+    pub albums: Vec<Count>,
+    pub tracks: Vec<Count>,
+    pub days: Vec<(Date, Duration)>, // every day of the period, newest first
+}
+
+impl Stats {
+    pub fn new(plays: &[Play], period: Period, now: OffsetDateTime) -> Self {
+        let offset = now.offset();
+        let day = |p: &Play| {
+            OffsetDateTime::from_unix_timestamp(p.time as i64)
+                .map(|t| t.to_offset(offset).date())
+                .ok()
+        };
+        let today = now.date();
+        let first = match period.days() {
+            Some(n) => today - time::Duration::days(n - 1),
+            None => plays.iter().filter_map(day).min().unwrap_or(today),
+        };
+        let plays: Vec<&Play> = plays
+            .iter()
+            .filter(|p| day(p).is_some_and(|d| d >= first && d <= today))
+            .collect();
+
+        let mut daily: HashMap<Date, Duration> = HashMap::new();
+        for p in &plays {
+            if let Some(d) = day(p) {
+                *daily.entry(d).or_default() += p.listened();
+            }
+        }
+        let mut days = vec![];
+        let mut date = Some(today);
+        while let Some(d) = date.filter(|d| *d >= first) {
+            days.push((d, daily.get(&d).copied().unwrap_or_default()));
+            date = d.previous_day();
+        }
+
+        Self {
+            plays: plays.len(),
+            listened: plays.iter().map(|p| p.listened()).sum(),
+            artists: count(&plays, |p| p.artist.clone()),
+            albums: count(&plays, |p| {
+                let album = p.album.as_deref()?;
+                Some(match &p.album_artist {
+                    Some(a) => format!("{} · {}", album, a),
+                    None => album.into(),
+                })
+            }),
+            tracks: count(&plays, |p| {
+                Some(match &p.artist {
+                    Some(a) => format!("{} · {}", p.display_title(), a),
+                    None => p.display_title().into(),
+                })
+            }),
+            days,
+        }
+    }
+}
+
+// Most played first, then most listened to
+fn count(plays: &[&Play], key: impl Fn(&Play) -> Option<String>) -> Vec<Count> {
+    let mut counts: Vec<Count> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for p in plays {
+        let Some(name) = key(p) else {
+            continue;
+        };
+        let i = *index.entry(name.clone()).or_insert_with(|| {
+            counts.push(Count {
+                name,
+                plays: 0,
+                listened: Duration::ZERO,
+                files: vec![],
+            });
+            counts.len() - 1
+        });
+        let c = &mut counts[i];
+        c.plays += 1;
+        c.listened += p.listened();
+        if !c.files.contains(&p.file) {
+            c.files.push(p.file.clone());
+        }
+    }
+    counts.sort_by(|a, b| {
+        b.plays
+            .cmp(&a.plays)
+            .then(b.listened.cmp(&a.listened))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    counts
+}
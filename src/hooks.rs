@@ -0,0 +1,321 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{log, Level};
+use mpd::{song::Id, State, Status};
+use serde_json::{json, Value};
+
+use crate::config::HooksConfig;
+use crate::meta::{join, SongMeta};
+use crate::mpd_util::{idle::Observer, MPD};
+
+// How often a running hook is checked on
+const POLL: Duration = Duration::from_millis(20);
+// How long to wait for what a finished hook wrote to stderr, which anything
+// it left running in the background may hold open
+const STDERR_GRACE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    SongChange,
+    Play,
+    Pause,
+    Stop,
+    QueueChange,
+    Connect,
+}
+
+impl Event {
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::SongChange => "song_change",
+            Event::Play => "play",
+            Event::Pause => "pause",
+            Event::Stop => "stop",
+            Event::QueueChange => "queue_change",
+            Event::Connect => "connect",
+        }
+    }
+
+    fn command(self, cfg: &HooksConfig) -> Option<&String> {
+        match self {
+            Event::SongChange => cfg.on_song_change.as_ref(),
+            Event::Play => cfg.on_play.as_ref(),
+            Event::Pause => cfg.on_pause.as_ref(),
+            Event::Stop => cfg.on_stop.as_ref(),
+            Event::QueueChange => cfg.on_queue_change.as_ref(),
+            Event::Connect => cfg.on_connect.as_ref(),
+        }
+        .filter(|c| !c.trim().is_empty())
+    }
+}
+
+fn state_name(state: State) -> &'static str {
+    match state {
+        State::Play => "play",
+        State::Pause => "pause",
+        State::Stop => "stop",
+    }
+}
+
+// A hook to run and everything it is told
+struct Run {
+    event: Event,
+    command: String,
+    env: Vec<(String, String)>,
+    input: String,
+}
+
+impl Run {
+    fn new(
+        event: Event,
+        command: &str,
+        status: &Status,
+        elapsed: Duration,
+        song: Option<&SongMeta>,
+        joiner: &str,
+    ) -> Self {
+        let mut env = vec![
+            ("EVENT", event.name().to_string()),
+            ("STATE", state_name(status.state).to_string()),
+            ("ELAPSED", elapsed.as_secs().to_string()),
+            ("VOLUME", status.volume.to_string()),
+            ("QUEUE_LENGTH", status.queue_len.to_string()),
+        ];
+        let mut input = json!({
+            "event": event.name(),
+            "state": state_name(status.state),
+            "elapsed": elapsed.as_secs_f64(),
+            "volume": status.volume,
+            "queue_length": status.queue_len,
+            "song": Value::Null,
+        });
+        if let Some(song) = song {
+            let place = song.place();
+            let fields = [
+                ("FILE", Some(song.file().to_string())),
+                ("TITLE", Some(song.display_title())),
+                ("ARTIST", song.artist(joiner)),
+                ("ALBUM", song.album.clone()),
+                ("ALBUM_ARTIST", join(&song.album_artists, joiner)),
+                ("GENRE", song.genre(joiner)),
+                ("TRACK", song.track.as_ref().map(|t| t.raw.clone())),
+                ("DISC", song.disc.as_ref().map(|d| d.raw.clone())),
+                ("DATE", song.date.as_ref().map(|d| d.raw.clone())),
+                ("DURATION", song.duration().map(|d| d.as_secs().to_string())),
+                ("POSITION", place.map(|p| p.pos.to_string())),
+                ("ID", place.map(|p| p.id.0.to_string())),
+            ];
+            env.extend(fields.into_iter().filter_map(|(k, v)| Some((k, v?))));
+            input["song"] = json!({
+                "file": song.file(),
+                "title": song.title,
+                "artists": song.artists,
+                "album": song.album,
+                "album_artists": song.album_artists,
+                "genres": song.genres,
+                "track": song.track.as_ref().map(|t| &t.raw),
+                "disc": song.disc.as_ref().map(|d| &d.raw),
+                "date": song.date.as_ref().map(|d| &d.raw),
+                "duration": song.duration().map(|d| d.as_secs_f64()),
+                "position": place.map(|p| p.pos),
+                "id": place.map(|p| p.id.0),
+            });
+        }
+        Self {
+            event,
+            command: command.into(),
+            env: env
+                .into_iter()
+                .map(|(k, v)| (format!("MPCURSIVE_{}", k), v))
+                .collect(),
+            input: input.to_string(),
+        }
+    }
+
+    // Output goes nowhere, as the terminal belongs to the UI; stderr is
+    // kept for reporting failures. The hook gets a process group of its own,
+    // so a timeout stops whatever it started too.
+    fn execute(&self, timeout: Duration) -> Result<()> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()?;
+        // small enough for the pipe; a hook that doesn't read it loses nothing
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(self.input.as_bytes());
+        }
+        let (send, errors) = mpsc::channel();
+        if let Some(mut stderr) = child.stderr.take() {
+            thread::spawn(move || {
+                let mut text = String::new();
+                let _ = stderr.read_to_string(&mut text);
+                let _ = send.send(text);
+            });
+        }
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                // the group is named after the shell, which is still unreaped
+                unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+                child.wait()?;
+                bail!("timed out after {}s", timeout.as_secs_f64());
+            }
+            thread::sleep(POLL);
+        };
+        if !status.success() {
+            let text = errors.recv_timeout(STDERR_GRACE).unwrap_or_default();
+            bail!("{}: {}", status, text.trim());
+        }
+        Ok(())
+    }
+}
+
+// Runs waiting for the hooks thread. One still waiting when the same
+// event fires again is dropped, as it would report a state already gone.
+#[derive(Default)]
+struct Waiting {
+    runs: VecDeque<Run>,
+    closed: bool,
+}
+
+#[derive(Clone, Default)]
+struct Runs(Arc<(Mutex<Waiting>, Condvar)>);
+
+impl Runs {
+    fn push(&self, run: Run) {
+        let (waiting, ready) = &*self.0;
+        let mut waiting = waiting.lock().unwrap();
+        waiting.runs.retain(|r| r.event != run.event);
+        waiting.runs.push_back(run);
+        ready.notify_one();
+    }
+
+    // What is still waiting runs, then the thread ends
+    fn close(&self) {
+        let (waiting, ready) = &*self.0;
+        waiting.lock().unwrap().closed = true;
+        ready.notify_one();
+    }
+
+    fn next(&self) -> Option<Run> {
+        let (waiting, ready) = &*self.0;
+        let mut waiting = waiting.lock().unwrap();
+        loop {
+            if let Some(run) = waiting.runs.pop_front() {
+                return Some(run);
+            }
+            if waiting.closed {
+                return None;
+            }
+            waiting = ready.wait(waiting).unwrap();
+        }
+    }
+}
+
+// Runs hooks one at a time and in order, away from the idle thread
+fn spawn(timeout: Duration) -> Runs {
+    let runs = Runs::default();
+    let waiting = runs.clone();
+    thread::Builder::new()
+        .name(String::from("hooks"))
+        .spawn(move || {
+            while let Some(run) = waiting.next() {
+                if let Err(e) = run.execute(timeout) {
+                    log!(Level::Warn, "Hook on_{} failed: {}", run.event.name(), e);
+                }
+            }
+        })
+        .expect("Failed to spawn hooks thread");
+    runs
+}
+
+// Runs the configured commands when the song, the playback state or the
+// queue change, and whenever MPD is reached
+pub struct Hooks {
+    cfg: HooksConfig,
+    joiner: String,
+    runs: Runs,
+    song: Option<Id>,
+    state: Option<State>,
+    queue_version: Option<u32>,
+}
+
+impl Hooks {
+    pub fn new(cfg: &HooksConfig, joiner: &str, timeout: Duration) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            joiner: joiner.into(),
+            runs: spawn(timeout),
+            song: None,
+            state: None,
+            queue_version: None,
+        }
+    }
+
+    fn fire(&self, event: Event, status: &Status, song: Option<&SongMeta>, mpd: &MPD) {
+        let Some(command) = event.command(&self.cfg) else {
+            return;
+        };
+        let elapsed = mpd.elapsed().unwrap_or_default();
+        let run = Run::new(event, command, status, elapsed, song, &self.joiner);
+        self.runs.push(run);
+    }
+}
+
+impl Observer for Hooks {
+    fn observe(&mut self, mpd: &MPD) {
+        let Some(status) = mpd.status() else {
+            return;
+        };
+        let song = mpd.now_playing();
+        let id = song.as_ref().and_then(|s| s.place()).map(|p| p.id);
+        if status.state != State::Stop && id.is_some() && id != self.song {
+            self.song = id;
+            self.fire(Event::SongChange, &status, song.as_deref(), mpd);
+        }
+        // a client started while stopped has nothing to report
+        let last = self.state.replace(status.state);
+        if last != Some(status.state) && (last.is_some() || status.state != State::Stop) {
+            let event = match status.state {
+                State::Play => Event::Play,
+                State::Pause => Event::Pause,
+                State::Stop => Event::Stop,
+            };
+            self.fire(event, &status, song.as_deref(), mpd);
+        }
+        let last = self.queue_version.replace(status.queue_version);
+        if last.is_some_and(|v| v != status.queue_version) {
+            self.fire(Event::QueueChange, &status, song.as_deref(), mpd);
+        }
+    }
+
+    fn connected(&mut self, mpd: &MPD) {
+        if let Some(status) = mpd.status() {
+            let song = mpd.now_playing();
+            self.fire(Event::Connect, &status, song.as_deref(), mpd);
+        }
+    }
+}
+
+impl Drop for Hooks {
+    fn drop(&mut self) {
+        self.runs.close();
+    }
+}
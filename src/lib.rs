@@ -1,20 +1,17 @@
-#![feature(const_maybe_uninit_zeroed)]
-
-use std::mem::MaybeUninit;
-
-use cursive::Cursive;
-
-static mut SIV: MaybeUninit<Cursive> = MaybeUninit::zeroed();
-
+pub mod app;
+pub mod art;
+pub mod clipboard;
+pub mod config;
+pub mod history;
+pub mod hooks;
+pub mod library;
+pub mod lyrics;
+pub mod meta;
 pub mod mpd_util;
+pub mod mpris;
+pub mod notice;
+pub mod plays;
+pub mod resume;
+pub mod scrobble;
 pub mod view;
-
-pub fn init() {
-    unsafe {
-        SIV.write(Cursive::new());
-    }
-}
-
-pub fn global_cursive() -> &'static mut Cursive {
-    unsafe { SIV.as_mut_ptr().as_mut().unwrap() }
-}
+pub mod visualizer;
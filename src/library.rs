@@ -0,0 +1,295 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::meta::SongMeta;
+use crate::mpd_util::stickers::{Ratings, MAX_RATING};
+
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+// Every song in the MPD database, indexed by artist. A song with several
+// artists is listed under each of them.
+#[derive(Debug, Default)]
+pub struct Library {
+    songs: Vec<SongMeta>,
+    artists: Vec<(String, Vec<usize>)>, // sorted, with song indices
+}
+
+impl Library {
+    pub fn new(songs: Vec<SongMeta>) -> Self {
+        let mut index: HashMap<&str, (String, Vec<usize>)> = HashMap::new();
+        for (i, song) in songs.iter().enumerate() {
+            let mut artists = song.all_artists();
+            if artists.is_empty() {
+                artists.push(UNKNOWN_ARTIST);
+            }
+            for artist in artists {
+                let entry = index
+                    .entry(artist)
+                    .or_insert_with(|| (artist.to_lowercase(), vec![]));
+                entry.1.push(i);
+                // ArtistSort only applies when it belongs to this artist alone
+                if let (Some(sort), [only]) = (&song.artist_sort, song.artists.as_slice()) {
+                    if only == artist {
+                        entry.0 = sort.to_lowercase();
+                    }
+                }
+            }
+        }
+
+        let mut artists: Vec<(String, String, Vec<usize>)> = index
+            .into_iter()
+            .map(|(name, (sort, mut ids))| {
+                ids.sort_by_cached_key(|i| album_order(&songs[*i]));
+                (sort, name.to_string(), ids)
+            })
+            .collect();
+        artists.sort();
+        Self {
+            artists: artists.into_iter().map(|(_, n, ids)| (n, ids)).collect(),
+            songs,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    pub fn songs(&self) -> &[SongMeta] {
+        &self.songs
+    }
+
+    pub fn artists(&self) -> impl Iterator<Item = &str> {
+        self.artists.iter().map(|(name, _)| name.as_str())
+    }
+
+    // Indices into songs() listed under artist, in album and track order
+    pub fn artist_song_ids(&self, artist: &str) -> &[usize] {
+        self.artists
+            .iter()
+            .find(|(name, _)| name == artist)
+            .map(|(_, ids)| ids.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn artist_songs(&self, artist: &str) -> Vec<&SongMeta> {
+        self.artist_song_ids(artist)
+            .iter()
+            .map(|i| &self.songs[*i])
+            .collect()
+    }
+
+    // Artists with at least one song matching the filter
+    pub fn filtered_artists(&self, filter: &Filter) -> Vec<&str> {
+        if filter.is_empty() {
+            return self.artists().collect();
+        }
+        self.artists
+            .iter()
+            .filter(|(_, ids)| ids.iter().any(|i| filter.matches(&self.songs[*i])))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    pub fn search(&self, filter: &Filter) -> Vec<&SongMeta> {
+        self.songs.iter().filter(|s| filter.matches(s)).collect()
+    }
+}
+
+fn album_order(song: &SongMeta) -> (Option<i32>, String, u32, u32, String) {
+    (
+        song.year(),
+        song.album_sort_key().unwrap_or_default().to_lowercase(),
+        song.disc.as_ref().and_then(|d| d.number).unwrap_or(0),
+        song.track.as_ref().and_then(|t| t.number).unwrap_or(0),
+        song.display_title().to_lowercase(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Any,
+    Artist,
+    AlbumArtist,
+    Album,
+    Title,
+    Genre,
+    Composer,
+    Performer,
+    File,
+    Rating,
+}
+
+impl Field {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_lowercase().as_str() {
+            "artist" => Field::Artist,
+            "albumartist" => Field::AlbumArtist,
+            "album" => Field::Album,
+            "title" => Field::Title,
+            "genre" => Field::Genre,
+            "composer" => Field::Composer,
+            "performer" => Field::Performer,
+            "file" => Field::File,
+            _ => return None,
+        })
+    }
+
+    fn values<'a>(&self, song: &'a SongMeta) -> Vec<&'a str> {
+        let strs = |v: &'a [String]| v.iter().map(String::as_str).collect::<Vec<_>>();
+        match self {
+            Field::Any => [
+                Field::Artist,
+                Field::AlbumArtist,
+                Field::Album,
+                Field::Title,
+                Field::Genre,
+                Field::Composer,
+                Field::Performer,
+            ]
+            .iter()
+            .flat_map(|f| f.values(song))
+            .collect(),
+            Field::Artist => strs(&song.artists),
+            Field::AlbumArtist => strs(&song.album_artists),
+            Field::Album => song.album.as_deref().into_iter().collect(),
+            Field::Title => song.title.as_deref().into_iter().collect(),
+            Field::Genre => strs(&song.genres),
+            Field::Composer => strs(&song.composers),
+            Field::Performer => strs(&song.performers),
+            Field::File => vec![song.file()],
+            // compared as a number, see Term::matches
+            Field::Rating => vec![],
+        }
+    }
+}
+
+// How a term's value is compared. Text fields only contain; ratings take
+// the others, e.g. "rating>=4".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Contains,
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    // The operator a term starts with, and the rest of it
+    fn split(s: &str) -> Option<(Self, &str)> {
+        [
+            (">=", Op::Ge),
+            ("<=", Op::Le),
+            (">", Op::Gt),
+            ("<", Op::Lt),
+            ("=", Op::Eq),
+            (":", Op::Eq),
+        ]
+        .into_iter()
+        .find_map(|(prefix, op)| Some((op, s.strip_prefix(prefix)?)))
+    }
+
+    fn compare(&self, a: u8, b: u8) -> bool {
+        match self {
+            Op::Contains | Op::Eq => a == b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub field: Field,
+    pub op: Op,
+    pub value: String, // lowercase
+}
+
+impl Term {
+    fn parse(t: &str) -> Self {
+        if let Some(term) = Term::rating(t) {
+            return term;
+        }
+        match t
+            .split_once(':')
+            .and_then(|(f, v)| Some((Field::parse(f)?, v)))
+        {
+            Some((field, value)) => Term {
+                field,
+                op: Op::Contains,
+                value: value.to_lowercase(),
+            },
+            None => Term {
+                field: Field::Any,
+                op: Op::Contains,
+                value: t.to_lowercase(),
+            },
+        }
+    }
+
+    // "rating" followed by an operator and a number of stars
+    fn rating(t: &str) -> Option<Self> {
+        let name = t.get(..6)?;
+        if !name.eq_ignore_ascii_case("rating") {
+            return None;
+        }
+        let (op, value) = Op::split(&t[6..])?;
+        let stars: u8 = value.parse().ok().filter(|s| *s <= MAX_RATING)?;
+        Some(Term {
+            field: Field::Rating,
+            op,
+            value: stars.to_string(),
+        })
+    }
+
+    fn matches(&self, song: &SongMeta, ratings: Option<&Ratings>) -> bool {
+        if self.field == Field::Rating {
+            // unrated songs count as 0 stars
+            let rating = ratings.and_then(|r| r.get(song.file())).unwrap_or(0);
+            return self.op.compare(rating, self.value.parse().unwrap_or(0));
+        }
+        self.field
+            .values(song)
+            .iter()
+            .any(|v| v.to_lowercase().contains(&self.value))
+    }
+}
+
+// Whitespace separated terms that must all match, e.g. "genre:jazz miles".
+// A term matches a multi-valued tag if any of its values contains it.
+// Rating terms need the ratings, see with_ratings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub terms: Vec<Term>,
+    ratings: Option<Arc<Ratings>>,
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Self {
+        Self {
+            terms: s.split_whitespace().map(Term::parse).collect(),
+            ratings: None,
+        }
+    }
+
+    pub fn with_ratings(mut self, ratings: Arc<Ratings>) -> Self {
+        self.ratings = Some(ratings);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, song: &SongMeta) -> bool {
+        self.terms
+            .iter()
+            .all(|t| t.matches(song, self.ratings.as_deref()))
+    }
+}
@@ -0,0 +1,255 @@
+use std::{fmt::Write as _, fs, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use log::{log, Level};
+
+use crate::config::Config;
+use crate::meta::SongMeta;
+use crate::mpd_util::MPD;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+// Where lyrics were found, which decides where edits are saved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    File(PathBuf),
+    Embedded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lyrics {
+    pub lines: Vec<Line>,
+    // LRC header tags such as [ar:...], written back on save
+    pub tags: Vec<(String, String)>,
+    pub source: Source,
+}
+
+impl Lyrics {
+    // Plain text, or LRC with [mm:ss.xx] stamps. A line may carry several
+    // stamps, for a chorus that repeats; [offset:ms] shifts them all.
+    // Header tags are only read from files with stamps, so a plain text
+    // section like [Chorus: Singer] stays a line.
+    pub fn parse(text: &str, source: Source) -> Self {
+        let stamped = text.lines().any(|raw| {
+            raw.strip_prefix('[')
+                .and_then(|r| r.split_once(']'))
+                .is_some_and(|(tag, _)| parse_time(tag).is_some())
+        });
+        let mut lines = vec![];
+        let mut tags = vec![];
+        let mut offset = 0i64;
+        let mut repeated = false;
+        for raw in text.lines() {
+            let mut rest = raw.trim_end_matches('\r');
+            let mut times = vec![];
+            while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+                match parse_time(tag) {
+                    Some(t) => times.push(t),
+                    None => match tag.split_once(':') {
+                        Some(("offset", ms)) if stamped => offset = ms.trim().parse().unwrap_or(0),
+                        Some((k, v)) if stamped && times.is_empty() && is_tag_name(k) => {
+                            tags.push((k.into(), v.trim().into()))
+                        }
+                        _ => break,
+                    },
+                }
+                rest = after;
+            }
+            if times.is_empty() {
+                // header tags take the whole line
+                if rest.is_empty() && raw.starts_with('[') {
+                    continue;
+                }
+                lines.push(Line {
+                    time: None,
+                    text: rest.into(),
+                });
+                continue;
+            }
+            repeated |= times.len() > 1;
+            for t in times {
+                // a positive offset shows lyrics sooner
+                let ms = t.as_millis() as i64 - offset;
+                lines.push(Line {
+                    time: Some(Duration::from_millis(ms.max(0) as u64)),
+                    text: rest.trim().into(),
+                });
+            }
+        }
+        if repeated {
+            // unstamped lines stay after the stamped line they followed
+            let mut last = None;
+            let keys: Vec<_> = lines
+                .iter()
+                .map(|l| {
+                    last = l.time.or(last);
+                    last
+                })
+                .collect();
+            let mut keyed: Vec<_> = keys.into_iter().zip(lines).collect();
+            keyed.sort_by_key(|(key, _)| *key);
+            lines = keyed.into_iter().map(|(_, l)| l).collect();
+        }
+        // trailing blank lines in plain text files
+        while lines
+            .last()
+            .is_some_and(|l| l.time.is_none() && l.text.is_empty())
+        {
+            lines.pop();
+        }
+        Self {
+            lines,
+            tags,
+            source,
+        }
+    }
+
+    pub fn synced(&self) -> bool {
+        self.lines.iter().any(|l| l.time.is_some())
+    }
+
+    // The line being sung at elapsed: the last stamped one at or before it
+    pub fn current(&self, elapsed: Duration) -> Option<usize> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.time.is_some_and(|t| t <= elapsed))
+            .max_by_key(|(i, l)| (l.time, *i))
+            .map(|(i, _)| i)
+    }
+
+    pub fn to_lrc(&self) -> String {
+        let mut out = String::new();
+        for (k, v) in &self.tags {
+            let _ = writeln!(out, "[{}:{}]", k, v);
+        }
+        for line in &self.lines {
+            match line.time {
+                Some(t) => {
+                    let _ = writeln!(out, "{}{}", format_time(t), line.text);
+                }
+                None => {
+                    let _ = writeln!(out, "{}", line.text);
+                }
+            }
+        }
+        out
+    }
+}
+
+// The ID tags LRC defines, offset aside
+fn is_tag_name(k: &str) -> bool {
+    matches!(k, "ar" | "ti" | "al" | "au" | "by" | "length" | "re" | "ve")
+}
+
+// mm:ss, mm:ss.xx or mm:ss.xxx
+fn parse_time(tag: &str) -> Option<Duration> {
+    let (min, sec) = tag.split_once(':')?;
+    if min.is_empty() || !min.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (whole, frac) = sec.split_once('.').unwrap_or((sec, ""));
+    if whole.len() != 2
+        || !whole
+            .chars()
+            .chain(frac.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let ms = match frac.len() {
+        0 => 0,
+        1 => frac.parse::<u64>().ok()? * 100,
+        2 => frac.parse::<u64>().ok()? * 10,
+        _ => frac[..3].parse().ok()?,
+    };
+    let secs = min.parse::<u64>().ok()? * 60 + whole.parse::<u64>().ok()?;
+    Some(Duration::from_millis(secs * 1000 + ms))
+}
+
+// [mm:ss.xx], the stamp most players read
+pub fn format_time(t: Duration) -> String {
+    let cs = t.as_millis() / 10;
+    format!("[{:02}:{:02}.{:02}]", cs / 6000, cs / 100 % 60, cs % 100)
+}
+
+// Lyrics for a song from the first place that has them: a .lrc or .txt next
+// to the file, the file's own tags, then the lyrics folder
+pub fn find(config: &Config, mpd: &MPD, song: &SongMeta) -> Option<Lyrics> {
+    let read = |path: PathBuf| {
+        let text = fs::read_to_string(&path).ok()?;
+        Some(Lyrics::parse(&text, Source::File(path)))
+    };
+    if let Some(path) = beside(config, song) {
+        let found = ["lrc", "txt"]
+            .into_iter()
+            .find_map(|ext| read(path.with_extension(ext)));
+        if found.is_some() {
+            return found;
+        }
+    }
+    if let Some(found) = embedded(mpd, song) {
+        return Some(found);
+    }
+    let name = folder_name(song)?;
+    let folder = config.lyrics.folder.as_ref()?;
+    ["lrc", "txt"]
+        .into_iter()
+        .find_map(|ext| read(folder.join(format!("{}.{}", name, ext))))
+}
+
+// Plain tracks in the music directory; streams have no file to sit next to
+fn beside(config: &Config, song: &SongMeta) -> Option<PathBuf> {
+    config.mpd.local_path(song.file())
+}
+
+fn embedded(mpd: &MPD, song: &SongMeta) -> Option<Lyrics> {
+    let comments = match mpd.with_raw(|c| c.comments(song.file())) {
+        Ok(c) => c,
+        Err(e) => {
+            log!(Level::Debug, "No comments for {}: {}", song.file(), e);
+            return None;
+        }
+    };
+    ["LYRICS", "UNSYNCEDLYRICS"].iter().find_map(|key| {
+        let text: Vec<&str> = comments
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .collect();
+        if text.is_empty() {
+            return None;
+        }
+        Some(Lyrics::parse(&text.join("\n"), Source::Embedded))
+    })
+}
+
+// "Artist - Title" with anything that can't go in a file name replaced
+fn folder_name(song: &SongMeta) -> Option<String> {
+    let name = format!("{} - {}", song.artists.first()?, song.title.as_ref()?);
+    Some(name.replace(['/', '\\', '\0'], "_"))
+}
+
+// Writes lyrics as LRC: over the .lrc they came from, else next to the song,
+// else into the lyrics folder. Returns where they went.
+pub fn save(config: &Config, song: &SongMeta, lyrics: &Lyrics) -> Result<PathBuf> {
+    let path = match &lyrics.source {
+        Source::File(p) if p.extension().is_some_and(|e| e == "lrc") => Some(p.clone()),
+        _ => None,
+    }
+    .or_else(|| beside(config, song).map(|p| p.with_extension("lrc")))
+    .or_else(|| {
+        let folder = config.lyrics.folder.as_ref()?;
+        Some(folder.join(format!("{}.lrc", folder_name(song)?)))
+    })
+    .ok_or_else(|| anyhow!("Nowhere to save lyrics; set mpd.music_directory or lyrics.folder"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, lyrics.to_lrc())?;
+    Ok(path)
+}
@@ -1,22 +1,34 @@
 #![allow(unused)]
 
 use std::fs;
-use std::time::Instant;
+use std::io;
+use std::time::{Duration, Instant};
 
 use cursive::views::ResizedView;
 use cursive::{Cursive, CursiveExt};
 use flexi_logger::Logger;
 use log::{log, Level};
+use time::UtcOffset;
 
-use mpcursive::view::root::Root;
-use mpcursive::{global_cursive, mpd_util::*};
+use mpcursive::app::App;
+use mpcursive::art::graphics::{self, Graphics};
+use mpcursive::config::{self, Config};
+use mpcursive::history::{self, History, Recorder};
+use mpcursive::hooks::Hooks;
+use mpcursive::mpd_util::*;
+use mpcursive::mpris::Mpris;
+use mpcursive::plays::Plays;
+use mpcursive::resume::Resumer;
+use mpcursive::scrobble::{self, backend, Queue, Scrobbler};
+use mpcursive::view::{
+    art::AlbumArt, lyrics::LyricsView, root::Root, stats::StatsView, visualizer::VisualizerView,
+};
+use mpcursive::visualizer::{self, Format};
 
 fn main() {
-    mpcursive::init();
-    let mut siv = global_cursive();
-
-    siv.set_fps(30);
-    siv.set_autorefresh(true);
+    // only known for sure while this is the only thread
+    let offset = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    let mut siv = Cursive::new();
 
     Logger::try_with_env_or_str("debug,cursive=info")
         .expect("Couldn't create logger")
@@ -30,12 +42,104 @@ fn main() {
         .start()
         .expect("Failed to initialize logger");
 
-    siv.add_fullscreen_layer(ResizedView::with_full_screen(Root::new()));
+    // loaded once the logger is up, so a bad config file gets reported
+    let app = App::new(Config::load());
+    let mut observers: Vec<Box<dyn idle::Observer>> = vec![];
+    let cfg = &app.config.history;
+    let history = match cfg.enabled {
+        true => History::new(cfg.file.clone().or_else(history::default_path)),
+        false => History::new(None),
+    };
+    // one count for the stickers and the history alike
+    let mut plays = Plays::new(app.config.plays.threshold);
+    if app.config.stickers.play_counts {
+        plays = plays.with_stickers();
+    }
+    if cfg.enabled {
+        plays = plays.with_history(Recorder::new(history.clone(), &app.config.tags.joiner));
+    }
+    if app.config.stickers.play_counts || cfg.enabled {
+        observers.push(Box::new(plays));
+    }
+    match backend::from_config(&app.config.scrobble) {
+        Ok(Some(backend)) => {
+            let queue = Queue::open(config::data_path("scrobbles.json"));
+            scrobble::spawn(queue.clone(), backend, scrobble::RETRY);
+            observers.push(Box::new(Scrobbler::new(queue, &app.config.tags.joiner)));
+        }
+        Ok(None) => {}
+        Err(e) => log!(Level::Warn, "Scrobbling disabled: {}", e),
+    }
+    if app.config.mpris.enabled {
+        match Mpris::start(app.clone(), None) {
+            Ok(mpris) => observers.push(Box::new(mpris)),
+            Err(e) => log!(Level::Warn, "MPRIS disabled: {}", e),
+        }
+    }
+    let cfg = &app.config.hooks;
+    if cfg.enabled() {
+        observers.push(Box::new(Hooks::new(
+            cfg,
+            &app.config.tags.joiner,
+            Duration::from_secs(cfg.timeout),
+        )));
+    }
+    if app.config.resume.enabled() {
+        observers.push(Box::new(Resumer::new(app.clone())));
+    }
+    let observers = idle::spawn(app.mpd.clone(), siv.cb_sink().clone(), observers);
+    let graphics = Graphics::new(graphics::detect(app.config.art.protocol));
+    let mut root = Root::new(app.clone());
+    root.register(
+        "Art",
+        '4',
+        "▣",
+        AlbumArt::new(app.clone(), siv.cb_sink().clone(), graphics.clone()),
+    );
+    root.register(
+        "Lyrics",
+        '5',
+        "♪",
+        LyricsView::new(app.clone(), siv.cb_sink().clone()),
+    );
+    let cfg = &app.config.visualizer;
+    let pcm = match (&cfg.fifo, Format::parse(&cfg.format)) {
+        (Some(fifo), Ok(format)) => Some(visualizer::spawn(
+            fifo.clone(),
+            format,
+            siv.cb_sink().clone(),
+        )),
+        (Some(_), Err(e)) => {
+            log!(Level::Warn, "Visualizer disabled: {}", e);
+            None
+        }
+        (None, _) => None,
+    };
+    root.register(
+        "Visualizer",
+        '6',
+        "▆",
+        VisualizerView::new(app.clone(), pcm),
+    );
+    root.register(
+        "Stats",
+        '7',
+        "◷",
+        StatsView::new(app.clone(), history, offset),
+    );
+    siv.add_fullscreen_layer(ResizedView::with_full_screen(root));
 
     siv.load_toml(fs::read_to_string("themes/dark.toml").unwrap().as_str())
         .unwrap();
 
     log!(Level::Debug, "Starting run");
-    siv.run_termion().unwrap();
+    // images that cursive can't draw are written by the backend after each frame
+    siv.try_run_with(|| {
+        let backend = cursive::backends::termion::Backend::init()?;
+        Ok::<_, io::Error>(graphics.backend(backend))
+    })
+    .unwrap();
+    observers.close(&app.mpd);
+    app.mpd.save_queue();
     log!(Level::Debug, "End");
 }
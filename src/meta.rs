@@ -0,0 +1,282 @@
+use std::{fmt, time::Duration};
+
+use mpd::{song::QueuePlace, Song};
+
+// Track or disc position, e.g. "3", "3/12" or vinyl-style "A1"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub raw: String,
+    pub number: Option<u32>,
+    pub total: Option<u32>,
+}
+
+impl Position {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return None;
+        }
+        let mut parts = raw.splitn(2, '/');
+        let number = parts.next().and_then(|n| n.trim().parse().ok());
+        let total = parts.next().and_then(|t| t.trim().parse().ok());
+        Some(Self {
+            raw: raw.into(),
+            number,
+            total,
+        })
+    }
+}
+
+impl fmt::Display for Position {
+    // Zero-padded so track numbers line up in columns
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.number {
+            Some(n) => write!(f, "{:02}", n),
+            None => f.write_str(&self.raw),
+        }
+    }
+}
+
+// Release date as tagged. Only the parts that could be parsed are set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Date {
+    pub raw: String,
+    pub year: Option<i32>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+}
+
+impl Date {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return None;
+        }
+        let mut date = Self {
+            raw: raw.into(),
+            year: None,
+            month: None,
+            day: None,
+        };
+
+        // ISO-8601 prefix: YYYY, YYYY-MM, YYYY-MM-DD, possibly followed by a time
+        let iso: Vec<&str> = raw
+            .split(['T', ' '])
+            .next()
+            .unwrap_or("")
+            .split('-')
+            .collect();
+        if iso[0].len() == 4 && iso[0].chars().all(|c| c.is_ascii_digit()) {
+            date.year = iso[0].parse().ok();
+            date.month = iso
+                .get(1)
+                .and_then(|m| m.parse().ok())
+                .filter(|m| (1..=12).contains(m));
+            date.day = iso
+                .get(2)
+                .and_then(|d| d.parse().ok())
+                .filter(|d| (1..=31).contains(d));
+            if date.month.is_none() {
+                date.day = None;
+            }
+            return Some(date);
+        }
+
+        // Anything else ("14.03.2019", "March 2019", "(P) 1999"): first run of four digits
+        date.year = raw
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|s| s.len() == 4)
+            .and_then(|y| y.parse().ok());
+        Some(date)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MusicBrainzIds {
+    pub recording: Option<String>,
+    pub release_track: Option<String>,
+    pub release: Option<String>,
+    pub artists: Vec<String>,
+    pub album_artists: Vec<String>,
+    pub work: Option<String>,
+}
+
+// Typed view of a song's tags, built once when the song is fetched and shared
+// by every view.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SongMeta {
+    pub song: Song,
+
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub album_artists: Vec<String>,
+    pub album: Option<String>,
+    pub genres: Vec<String>,
+    pub composers: Vec<String>,
+    pub performers: Vec<String>,
+    pub track: Option<Position>,
+    pub disc: Option<Position>,
+    pub date: Option<Date>,
+    pub original_date: Option<Date>,
+    pub musicbrainz: MusicBrainzIds,
+
+    pub artist_sort: Option<String>,
+    pub album_artist_sort: Option<String>,
+    pub album_sort: Option<String>,
+}
+
+impl SongMeta {
+    pub fn new(song: Song) -> Self {
+        let all = |key: &str| -> Vec<String> {
+            let mut values: Vec<String> = vec![];
+            for (k, v) in &song.tags {
+                let v = v.trim();
+                if k.eq_ignore_ascii_case(key) && !v.is_empty() && !values.iter().any(|x| x == v) {
+                    values.push(v.into());
+                }
+            }
+            values
+        };
+        let first = |key: &str| all(key).into_iter().next();
+
+        // the mpd crate moves Artist and Title out of the tag list
+        let mut artists = all("Artist");
+        if let Some(a) = song
+            .artist
+            .as_deref()
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+        {
+            if !artists.iter().any(|x| x == a) {
+                artists.insert(0, a.into());
+            }
+        }
+        let title = song
+            .title
+            .as_deref()
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .or_else(|| first("Title"));
+
+        Self {
+            title,
+            artists,
+            album_artists: all("AlbumArtist"),
+            album: first("Album"),
+            genres: all("Genre"),
+            composers: all("Composer"),
+            performers: all("Performer"),
+            track: first("Track").and_then(|t| Position::parse(&t)),
+            disc: first("Disc").and_then(|d| Position::parse(&d)),
+            date: first("Date").and_then(|d| Date::parse(&d)),
+            original_date: first("OriginalDate").and_then(|d| Date::parse(&d)),
+            musicbrainz: MusicBrainzIds {
+                recording: first("MUSICBRAINZ_TRACKID"),
+                release_track: first("MUSICBRAINZ_RELEASETRACKID"),
+                release: first("MUSICBRAINZ_ALBUMID"),
+                artists: all("MUSICBRAINZ_ARTISTID"),
+                album_artists: all("MUSICBRAINZ_ALBUMARTISTID"),
+                work: first("MUSICBRAINZ_WORKID"),
+            },
+            artist_sort: first("ArtistSort"),
+            album_artist_sort: first("AlbumArtistSort"),
+            album_sort: first("AlbumSort"),
+            song,
+        }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.song.file
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.song.duration
+    }
+
+    pub fn place(&self) -> Option<QueuePlace> {
+        self.song.place
+    }
+
+    // Title, then stream name, then the file name
+    pub fn display_title(&self) -> String {
+        self.title
+            .clone()
+            .or_else(|| self.song.name.clone())
+            .unwrap_or_else(|| {
+                let file = self.song.file.trim_end_matches('/');
+                file.rsplit('/').next().unwrap_or(file).into()
+            })
+    }
+
+    // Multi-valued tags are rendered with joiner between values, normally
+    // the configured tags.joiner
+    pub fn artist(&self, joiner: &str) -> Option<String> {
+        join(&self.artists, joiner)
+    }
+
+    // AlbumArtist, falling back to Artist
+    pub fn album_artist(&self, joiner: &str) -> Option<String> {
+        join(&self.album_artists, joiner).or_else(|| self.artist(joiner))
+    }
+
+    pub fn genre(&self, joiner: &str) -> Option<String> {
+        join(&self.genres, joiner)
+    }
+
+    // Every artist the song should be listed under: album artists and
+    // track artists, without duplicates
+    pub fn all_artists(&self) -> Vec<&str> {
+        let mut out: Vec<&str> = vec![];
+        for a in self.album_artists.iter().chain(&self.artists) {
+            if !out.contains(&a.as_str()) {
+                out.push(a);
+            }
+        }
+        out
+    }
+
+    pub fn year(&self) -> Option<i32> {
+        self.date.as_ref().and_then(|d| d.year)
+    }
+
+    pub fn artist_sort_key(&self) -> Option<String> {
+        self.artist_sort
+            .clone()
+            .or_else(|| self.artist(SORT_JOINER))
+    }
+
+    pub fn album_artist_sort_key(&self) -> Option<String> {
+        self.album_artist_sort
+            .clone()
+            .or_else(|| join(&self.album_artists, SORT_JOINER))
+            .or_else(|| self.artist_sort_key())
+    }
+
+    pub fn album_sort_key(&self) -> Option<String> {
+        self.album_sort.clone().or_else(|| self.album.clone())
+    }
+}
+
+// Sort keys don't depend on how tags are displayed
+const SORT_JOINER: &str = "; ";
+
+pub fn join(values: &[String], joiner: &str) -> Option<String> {
+    match values {
+        [] => None,
+        [one] => Some(one.clone()),
+        _ => Some(values.join(joiner)),
+    }
+}
+
+impl From<Song> for SongMeta {
+    fn from(song: Song) -> Self {
+        SongMeta::new(song)
+    }
+}
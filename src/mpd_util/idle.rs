@@ -0,0 +1,170 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use cursive::{event::Event, CbSink, Cursive};
+use log::{log, Level};
+use mpd::{idle::Subsystem, Client, Idle, State};
+
+use super::{library_cache, MPD};
+
+// How often the progress bar advances while playing
+const PLAYING_TICK: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Follows playback, e.g. to count plays. Observers are called after every
+// change MPD announces to the player, volume, options or queue and on every
+// tick while playing, one at a time.
+pub trait Observer: Send {
+    fn observe(&mut self, mpd: &MPD);
+
+    // MPD was reached, at start or after the connection was lost
+    fn connected(&mut self, _mpd: &MPD) {}
+
+    // The client is about to exit
+    fn close(&mut self, _mpd: &MPD) {}
+}
+
+// The observers spawn runs, for closing them on exit
+#[derive(Clone)]
+pub struct Observers(Arc<Mutex<Vec<Box<dyn Observer>>>>);
+
+impl Observers {
+    fn observe(&self, mpd: &MPD) {
+        self.0
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .for_each(|o| o.observe(mpd));
+    }
+
+    fn connected(&self, mpd: &MPD) {
+        self.0
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .for_each(|o| o.connected(mpd));
+    }
+
+    pub fn close(&self, mpd: &MPD) {
+        self.0.lock().unwrap().iter_mut().for_each(|o| o.close(mpd));
+    }
+}
+
+// Redraws are driven by MPD rather than a frame rate: one thread waits on
+// `idle` and refreshes the UI when something changes, another ticks only
+// while a song is playing. Between them they also open the connections the
+// UI uses, see MPD::connect.
+pub fn spawn(mpd: MPD, sink: CbSink, observers: Vec<Box<dyn Observer>>) -> Observers {
+    // the cached library is browsable even if MPD can't be reached
+    library_cache::refresh(&mpd, sink.clone());
+    let observers = Observers(Arc::new(Mutex::new(observers)));
+    let playing = Arc::new(AtomicBool::new(false));
+    let idle_playing = playing.clone();
+    let idle_sink = sink.clone();
+    let idle_mpd = mpd.clone();
+    let idle_observers = observers.clone();
+    thread::Builder::new()
+        .name(String::from("idle"))
+        .spawn(move || loop {
+            match watch(&idle_mpd, &idle_sink, &idle_playing, &idle_observers) {
+                Ok(()) => return,
+                Err(e) => log!(Level::Warn, "Lost idle connection: {}", e),
+            }
+            thread::sleep(RECONNECT_DELAY);
+        })
+        .expect("Failed to spawn idle thread");
+    let tick_observers = observers.clone();
+    thread::Builder::new()
+        .name(String::from("tick"))
+        .spawn(move || loop {
+            thread::sleep(PLAYING_TICK);
+            // connections the UI found broken are reopened here, off its thread
+            let reconnected = mpd.connect().unwrap_or(false);
+            if reconnected {
+                replay(&mpd);
+            }
+            mpd.keep_alive();
+            let playing = playing.load(Ordering::Relaxed);
+            if playing {
+                tick_observers.observe(&mpd);
+            } else if !reconnected {
+                continue;
+            }
+            if sink.send(Box::new(refresh)).is_err() {
+                return;
+            }
+        })
+        .expect("Failed to spawn tick thread");
+    observers
+}
+
+fn refresh(siv: &mut Cursive) {
+    siv.on_event(Event::Refresh);
+}
+
+fn replay(mpd: &MPD) {
+    if let Err(e) = mpd.replay_pending() {
+        log!(Level::Warn, "Failed to replay pending operations: {}", e);
+    }
+}
+
+// Returns Ok once the UI has gone away
+fn watch(mpd: &MPD, sink: &CbSink, playing: &AtomicBool, observers: &Observers) -> Result<()> {
+    let mut client = mpd.open_client()?;
+    mpd.connect()?;
+    library_cache::refresh(mpd, sink.clone());
+    replay(mpd);
+    mpd.invalidate();
+    mpd.invalidate_stickers();
+    observers.connected(mpd);
+    if sink.send(Box::new(refresh)).is_err() {
+        return Ok(());
+    }
+    let result = follow(&mut client, mpd, sink, playing, observers);
+    if result.is_err() {
+        mpd.disconnect();
+        // for a client started before the server is back
+        mpd.save_queue();
+        let _ = sink.send(Box::new(refresh));
+    }
+    result
+}
+
+fn follow(
+    client: &mut Client,
+    mpd: &MPD,
+    sink: &CbSink,
+    playing: &AtomicBool,
+    observers: &Observers,
+) -> Result<()> {
+    loop {
+        playing.store(client.status()?.state == State::Play, Ordering::Relaxed);
+        let changed = client.wait(&[])?;
+        mpd.invalidate();
+        if changed.contains(&Subsystem::Database) {
+            library_cache::refresh(mpd, sink.clone());
+        }
+        if changed.contains(&Subsystem::Sticker) {
+            mpd.invalidate_stickers();
+        }
+        let followed = [
+            Subsystem::Player,
+            Subsystem::Mixer,
+            Subsystem::Options,
+            Subsystem::Queue,
+        ];
+        if followed.iter().any(|s| changed.contains(s)) {
+            observers.observe(mpd);
+        }
+        if sink.send(Box::new(refresh)).is_err() {
+            return Ok(());
+        }
+    }
+}
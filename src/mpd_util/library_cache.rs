@@ -0,0 +1,273 @@
+use std::{
+    env, fs,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    thread,
+};
+
+use anyhow::{bail, Result};
+use cursive::{event::Event, CbSink};
+use log::{log, Level};
+use mpd::Song;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{proto, proto::Connection, MPD};
+use crate::library::Library;
+use crate::meta::SongMeta;
+
+// Bump when the file layout changes, so old caches are rebuilt
+const FORMAT: u32 = 1;
+
+// The database as of db_update, as written to disk
+#[derive(Serialize, Deserialize)]
+pub struct CacheFile {
+    format: u32,
+    pub server: String,
+    pub db_update: i64,
+    pub songs: Vec<Song>,
+}
+
+impl CacheFile {
+    pub fn new(server: String, db_update: i64, songs: Vec<Song>) -> Self {
+        Self {
+            format: FORMAT,
+            server,
+            db_update,
+            songs,
+        }
+    }
+
+    // The cache at path, unless it is missing, unreadable, or was written
+    // for another server or in another format
+    pub fn load(path: &Path, server: &str) -> Option<Self> {
+        load::<Self>(path).filter(|f| f.format == FORMAT && f.server == server)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save(path, self)
+    }
+}
+
+// The queue as far as it was fetched, as written to disk
+#[derive(Serialize, Deserialize)]
+struct QueueFile {
+    format: u32,
+    server: String,
+    version: u32,
+    songs: Vec<Option<Song>>,
+}
+
+// $XDG_CACHE_HOME/mpcursive/<server>, falling back to ~/.cache. Each
+// cache file adds its own suffix to it.
+pub fn base(server: &str) -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    let name: String = server
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Some(base.join("mpcursive").join(name))
+}
+
+fn suffixed(base: &Path, suffix: &str) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+// The library as of one database update, <base>-<db_update>.bin
+pub fn library_path(base: &Path, db_update: i64) -> PathBuf {
+    suffixed(base, &format!("-{}.bin", db_update))
+}
+
+// Every library written for base, by database update time
+fn library_files(base: &Path) -> Vec<(i64, PathBuf)> {
+    let (Some(dir), Some(name)) = (base.parent(), base.file_name()) else {
+        return vec![];
+    };
+    let prefix = format!("{}-", name.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| {
+            let path = e.ok()?.path();
+            let db_update = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .strip_suffix(".bin")?
+                .parse()
+                .ok()?;
+            Some((db_update, path))
+        })
+        .collect()
+}
+
+// Brings the library up to date in the background: the on-disk copy is
+// published first, then the database is only listed again if its update
+// time changed. The UI is redrawn through sink whenever a library is ready.
+// Asked again while running, the refresh goes round once more when done,
+// as the database may have changed after it was read.
+pub fn refresh(mpd: &MPD, sink: CbSink) {
+    let shared = &mpd.shared;
+    shared.library_dirty.store(true, Ordering::Release);
+    if shared.refreshing.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let mpd = mpd.clone();
+    thread::Builder::new()
+        .name(String::from("library"))
+        .spawn(move || loop {
+            let shared = &mpd.shared;
+            shared.library_dirty.store(false, Ordering::Release);
+            if let Err(e) = update(&mpd, &sink) {
+                log!(Level::Warn, "Failed to update library: {}", e);
+            }
+            shared.refreshing.store(false, Ordering::Release);
+            // a refresh asked for from here on starts a thread of its own
+            if !shared.library_dirty.load(Ordering::Acquire)
+                || shared.refreshing.swap(true, Ordering::AcqRel)
+            {
+                return;
+            }
+        })
+        .expect("Failed to spawn library thread");
+}
+
+fn update(mpd: &MPD, sink: &CbSink) -> Result<()> {
+    let server = mpd.address().to_string();
+    let base = mpd.shared.cache_base.as_deref();
+
+    // until the server answers, the newest database seen is the best guess
+    if mpd.library().is_none() {
+        let newest = base.and_then(|b| library_files(b).into_iter().max());
+        if let Some(file) = newest.and_then(|(_, p)| CacheFile::load(&p, &server)) {
+            log!(Level::Info, "Loaded {} songs from cache", file.songs.len());
+            publish(mpd, file.songs, file.db_update, sink);
+        }
+    }
+
+    let mut conn = mpd.open_raw()?;
+    let db_update = conn
+        .command("stats", &[])?
+        .into_iter()
+        .find(|(k, _)| k == "db_update")
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    if mpd.shared.cache.read().unwrap().library_db_update == Some(db_update) {
+        return Ok(());
+    }
+
+    log!(Level::Info, "Database changed, listing library");
+    let songs = proto::songs_from_pairs(conn.command("listallinfo", &[])?);
+    let file = CacheFile::new(server, db_update, songs);
+    if let Some(base) = base {
+        let path = library_path(base, db_update);
+        match file.save(&path) {
+            // older databases of this server are of no further use
+            Ok(()) => {
+                for (_, old) in library_files(base)
+                    .into_iter()
+                    .filter(|(d, _)| *d != db_update)
+                {
+                    let _ = fs::remove_file(old);
+                }
+            }
+            Err(e) => log!(Level::Warn, "Failed to write {}: {}", path.display(), e),
+        }
+    }
+    publish(mpd, file.songs, db_update, sink);
+    Ok(())
+}
+
+fn publish(mpd: &MPD, songs: Vec<Song>, db_update: i64, sink: &CbSink) {
+    let library = Library::new(songs.into_iter().map(SongMeta::from).collect());
+    {
+        let mut cache = mpd.cache();
+        cache.library = Some(Arc::new(library));
+        cache.library_db_update = Some(db_update);
+    }
+    // the UI may already be gone
+    let _ = sink.send(Box::new(|s| s.on_event(Event::Refresh)));
+}
+
+// Next to the library, as <base>.queue.bin
+fn queue_path(mpd: &MPD) -> Option<PathBuf> {
+    let base = mpd.shared.cache_base.as_ref()?;
+    Some(suffixed(base, ".queue.bin"))
+}
+
+// A queue already synced this run is newer than anything on disk, and a
+// restored one is what is on disk already
+pub(super) fn save_queue(mpd: &MPD) -> Result<()> {
+    let Some(path) = queue_path(mpd) else {
+        return Ok(());
+    };
+    let file = {
+        let queue = mpd.shared.queue.lock().unwrap();
+        let Some(version) = queue.version().filter(|_| !queue.restored()) else {
+            return Ok(());
+        };
+        QueueFile {
+            format: FORMAT,
+            server: mpd.address().to_string(),
+            version,
+            songs: queue
+                .songs()
+                .iter()
+                .map(|s| s.as_ref().map(|s| s.song.clone()))
+                .collect(),
+        }
+    };
+    save(&path, &file)
+}
+
+pub(super) fn load_queue(mpd: &MPD) -> Option<(u32, Vec<Option<Arc<SongMeta>>>)> {
+    let file = load::<QueueFile>(&queue_path(mpd)?)?;
+    if file.format != FORMAT || file.server != mpd.address() {
+        return None;
+    }
+    log!(
+        Level::Info,
+        "Loaded queue of {} songs from cache",
+        file.songs.len()
+    );
+    let songs = file
+        .songs
+        .into_iter()
+        .map(|s| s.map(|s| Arc::new(SongMeta::from(s))))
+        .collect();
+    Some((file.version, songs))
+}
+
+fn load<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let file = fs::File::open(path).ok()?;
+    match bincode::deserialize_from(BufReader::new(file)) {
+        Ok(f) => Some(f),
+        Err(e) => {
+            log!(Level::Warn, "Ignoring cache {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Written to a temporary file first so a crash never leaves a torn cache
+fn save(path: &Path, file: &impl Serialize) -> Result<()> {
+    let Some(dir) = path.parent() else {
+        bail!("No cache directory");
+    };
+    fs::create_dir_all(dir)?;
+    let tmp = path.with_extension("bin.tmp");
+    bincode::serialize_into(BufWriter::new(fs::File::create(&tmp)?), file)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
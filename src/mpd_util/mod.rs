@@ -0,0 +1,674 @@
+#![allow(unused)]
+
+use anyhow::{bail, Result};
+use log::{log, Level};
+use mpd::{song::Id, Client, Song, State, Status};
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, RwLock, RwLockWriteGuard,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::library::Library;
+use crate::meta::SongMeta;
+
+pub mod idle;
+pub mod library_cache;
+pub mod proto;
+pub mod queue;
+pub mod stickers;
+
+use proto::Connection;
+use queue::{Queue, QueueSource};
+use stickers::Ratings;
+
+// Connections unused for this long are pinged, as MPD closes idle clients
+// after a minute by default
+const KEEPALIVE: Duration = Duration::from_secs(30);
+
+// A connection for commands from the UI. It is never opened where it is
+// used: the idle and tick threads open it, see MPD::connect, so nothing on
+// the UI thread waits for a server that can't be reached.
+struct Link<C> {
+    conn: Option<C>,
+    used: Instant,
+}
+
+impl<C> Link<C> {
+    fn new() -> Self {
+        Self {
+            conn: None,
+            used: Instant::now(),
+        }
+    }
+
+    // Opens the connection unless it is open already. Returns whether it
+    // was opened.
+    fn open(link: &Mutex<Self>, connect: impl FnOnce() -> Result<C>) -> Result<bool> {
+        if link.lock().unwrap().conn.is_some() {
+            return Ok(false);
+        }
+        // connected without holding the link, which the UI may be waiting on
+        let conn = connect()?;
+        let mut link = link.lock().unwrap();
+        link.conn.get_or_insert(conn);
+        link.used = Instant::now();
+        Ok(true)
+    }
+
+    // Runs f on the connection, dropping it if broken(error) says so
+    fn call<T, E: Into<anyhow::Error>>(
+        &mut self,
+        f: impl FnOnce(&mut C) -> std::result::Result<T, E>,
+        broken: fn(&E) -> bool,
+    ) -> Result<T> {
+        let Some(conn) = self.conn.as_mut() else {
+            bail!("Not connected to MPD");
+        };
+        let result = f(conn);
+        self.used = Instant::now();
+        if matches!(&result, Err(e) if broken(e)) {
+            self.conn = None;
+        }
+        result.map_err(Into::into)
+    }
+}
+
+// Changes made while offline, replayed in order once MPD is back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pending {
+    Add(Vec<String>),
+}
+
+impl Pending {
+    // Applies what is left of the operation. Files are dropped as they are
+    // added, so after a failure only the rest remains to be tried again.
+    fn apply(&mut self, conn: &mut Connection) -> Result<()> {
+        match self {
+            Pending::Add(files) => {
+                let mut added = 0;
+                let result = files.iter().try_for_each(|file| {
+                    conn.command("add", &[file])?;
+                    added += 1;
+                    Ok(())
+                });
+                files.drain(..added);
+                result
+            }
+        }
+    }
+}
+
+struct Cache {
+    status: CacheItem<Status>,
+    // filled in the background by library_cache
+    library: Option<Arc<Library>>,
+    library_db_update: Option<i64>,
+    pending: Vec<Pending>,
+    // reloaded on first use after a sticker change
+    ratings: Option<Arc<Ratings>>,
+    ratings_invalid: bool,
+}
+
+impl Cache {
+    fn new() -> Self {
+        Self {
+            status: CacheItem::new(Duration::from_millis(1000), String::from("Status")),
+            library: None,
+            library_db_update: None,
+            pending: vec![],
+            ratings: None,
+            ratings_invalid: false,
+        }
+    }
+}
+
+// A fetched value with a time to live. When fetching fails the last value
+// is kept, marked stale, so the UI can keep showing it while offline.
+struct CacheItem<T>
+where
+    T: Clone,
+{
+    data: Option<T>,
+    fetched: Instant,
+    ttl: Duration,
+    debug_name: String,
+    invalid: bool,
+    stale: bool,
+}
+
+impl<T> CacheItem<T>
+where
+    T: Clone,
+{
+    fn new(ttl: Duration, debug_name: String) -> Self {
+        Self {
+            data: None,
+            fetched: Instant::now(),
+            ttl,
+            debug_name,
+            invalid: false,
+            stale: false,
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.invalid || Instant::now().duration_since(self.fetched) > self.ttl
+    }
+
+    fn get(&self) -> Option<&T> {
+        if self.expired() {
+            None
+        } else {
+            self.data.as_ref()
+        }
+    }
+
+    fn update(&mut self, fetch: impl FnOnce() -> Option<T>) {
+        if let Some(d) = fetch() {
+            self.data = Some(d);
+            self.fetched = Instant::now();
+            self.invalid = false;
+            self.stale = false;
+        } else if !self.stale {
+            log!(
+                Level::Warn,
+                "Failed to update cache for {}",
+                self.debug_name
+            );
+            self.stale = true;
+        }
+    }
+
+    // Forces a refetch on next use, keeping the old value in case that fails
+    fn invalidate(&mut self) {
+        self.invalid = true;
+    }
+}
+
+// Connections and cached state for one server. Cheap to clone; clones share
+// everything, so the UI and background threads see the same cache.
+#[derive(Clone)]
+pub struct MPD {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    address: String,
+    password: Option<String>,
+    // where the library and queue are kept between runs, see library_cache
+    cache_base: Option<PathBuf>,
+    client: Mutex<Link<Client>>,
+    raw: Mutex<Link<Connection>>,
+    cache: RwLock<Cache>,
+    queue: Mutex<Queue>,
+    // the links were open as of their last use, see MPD::connect
+    online: AtomicBool,
+    refreshing: AtomicBool,
+    // a library refresh was asked for since the running one started
+    library_dirty: AtomicBool,
+    // whether the saved queue was looked for yet
+    queue_file_read: AtomicBool,
+}
+
+impl MPD {
+    pub fn new(address: &str, password: Option<String>, cache_base: Option<PathBuf>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                address: address.into(),
+                password,
+                cache_base,
+                client: Mutex::new(Link::new()),
+                raw: Mutex::new(Link::new()),
+                cache: RwLock::new(Cache::new()),
+                queue: Mutex::new(Queue::new()),
+                online: AtomicBool::new(false),
+                refreshing: AtomicBool::new(false),
+                library_dirty: AtomicBool::new(false),
+                queue_file_read: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.shared.address
+    }
+
+    // A client of its own, logged in if the server needs a password
+    pub fn open_client(&self) -> Result<Client> {
+        let mut client = Client::connect(self.address())?;
+        if let Some(password) = &self.shared.password {
+            client.login(password)?;
+        }
+        Ok(client)
+    }
+
+    // A protocol connection of its own, logged in like open_client
+    pub fn open_raw(&self) -> Result<Connection> {
+        let mut conn = Connection::connect(self.address())?;
+        if let Some(password) = &self.shared.password {
+            conn.command("password", &[password])?;
+        }
+        Ok(conn)
+    }
+
+    // Runs f on the shared client. Anything but an error response from the
+    // server means the connection is broken, so it is dropped.
+    fn with_client<T>(&self, f: impl FnOnce(&mut Client) -> mpd::error::Result<T>) -> Result<T> {
+        let mut link = self.shared.client.lock().unwrap();
+        let result = link.call(f, |e| !matches!(e, mpd::error::Error::Server(_)));
+        let lost = link.conn.is_none();
+        drop(link);
+        if lost {
+            self.disconnect();
+        }
+        result
+    }
+
+    // Runs f on the shared protocol connection, for commands the mpd crate
+    // doesn't model. Large binary payloads are better read on a connection
+    // of their own, as this one is held meanwhile.
+    pub fn with_raw<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<T> {
+        let mut link = self.shared.raw.lock().unwrap();
+        let result = link.call(f, |e| e.downcast_ref::<proto::Ack>().is_none());
+        let lost = link.conn.is_none();
+        drop(link);
+        if lost {
+            self.disconnect();
+        }
+        result
+    }
+
+    // Opens whichever connection is missing and marks the client online.
+    // Called from the idle and tick threads, and never from the UI. Returns
+    // whether anything was opened.
+    pub fn connect(&self) -> Result<bool> {
+        let opened = Link::open(&self.shared.client, || self.open_client())?
+            | Link::open(&self.shared.raw, || self.open_raw())?;
+        if opened {
+            self.invalidate();
+        }
+        self.shared.online.store(true, Ordering::Release);
+        Ok(opened)
+    }
+
+    // Closes the connections once one of them broke: the server is likely
+    // gone or restarted, so the others are of no use either
+    pub fn disconnect(&self) {
+        self.shared.online.store(false, Ordering::Release);
+        self.shared.client.lock().unwrap().conn = None;
+        self.shared.raw.lock().unwrap().conn = None;
+    }
+
+    // Pings connections that have been unused for a while, so MPD doesn't
+    // close them for being idle
+    pub(super) fn keep_alive(&self) {
+        if self.shared.client.lock().unwrap().used.elapsed() > KEEPALIVE {
+            let _ = self.with_client(|c| c.ping());
+        }
+        if self.shared.raw.lock().unwrap().used.elapsed() > KEEPALIVE {
+            let _ = self.with_raw(|raw| raw.command("ping", &[]));
+        }
+    }
+
+    fn cache(&self) -> RwLockWriteGuard<'_, Cache> {
+        self.shared.cache.write().unwrap()
+    }
+
+    // Refetches the status once it has expired. It is fetched without
+    // holding the cache, which other threads read meanwhile.
+    fn update_status(&self) {
+        if !self.shared.cache.read().unwrap().status.expired() {
+            return;
+        }
+        let fetched = self.with_client(|c| c.status()).ok();
+        self.cache().status.update(|| fetched);
+    }
+
+    // Brings the queue up to the playlist version in the current status.
+    // While offline the last synced queue is returned as is.
+    // A client started while the server is away shows the queue saved by
+    // the last run. The changes are fetched without holding the queue.
+    fn synced_queue(&self) -> Option<MutexGuard<'_, Queue>> {
+        let status = self.status().filter(|_| self.online());
+        let Some(status) = status else {
+            let mut queue = self.shared.queue.lock().unwrap();
+            if queue.version().is_none()
+                && !self.shared.queue_file_read.swap(true, Ordering::AcqRel)
+            {
+                if let Some((version, songs)) = library_cache::load_queue(self) {
+                    queue.restore(version, songs);
+                }
+            }
+            return Some(queue);
+        };
+        let (version, len) = (status.queue_version, status.queue_len as usize);
+        let base = self.shared.queue.lock().unwrap().patch_base(version);
+        let changes = match base {
+            Some(base) => match self.with_raw(|raw| raw.changes(base)) {
+                Ok(changes) => Some((base, changes)),
+                Err(e) => {
+                    log!(Level::Warn, "Failed to sync queue: {}", e);
+                    self.shared.queue.lock().unwrap().clear();
+                    return None;
+                }
+            },
+            None => None,
+        };
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.apply(version, len, changes);
+        Some(queue)
+    }
+
+    // False once a connection to the server broke, until the idle or tick
+    // thread reconnects. Everything read meanwhile is the last known state.
+    pub fn online(&self) -> bool {
+        self.shared.online.load(Ordering::Acquire)
+    }
+
+    // When the status shown was fetched, if it ever was
+    pub fn last_seen(&self) -> Option<Instant> {
+        let cache = self.shared.cache.read().unwrap();
+        cache.status.data.as_ref().map(|_| cache.status.fetched)
+    }
+
+    // Forces the next read to ask the server, e.g. after it announced a change
+    pub fn invalidate(&self) {
+        self.cache().status.invalidate();
+    }
+
+    pub fn queue_version(&self) -> Option<u32> {
+        self.synced_queue()?.version()
+    }
+
+    pub fn queue_len(&self) -> usize {
+        self.synced_queue().map(|q| q.len()).unwrap_or(0)
+    }
+
+    // Songs in range, fetching only the ones not seen yet. Offline, only
+    // the songs already fetched are available.
+    pub fn queue_window(&self, range: Range<usize>) -> Vec<Arc<SongMeta>> {
+        let Some(queue) = self.synced_queue() else {
+            return vec![];
+        };
+        let version = queue.version();
+        let mut missing = queue.missing(range.clone());
+        drop(queue);
+        while let Some(gap) = missing.take().filter(|_| self.online()) {
+            // fetched without holding the queue
+            let songs = match self.with_raw(|raw| raw.window(gap.clone())) {
+                Ok(songs) => songs,
+                Err(e) => {
+                    log!(Level::Warn, "Failed to fetch queue: {}", e);
+                    break;
+                }
+            };
+            let mut queue = self.shared.queue.lock().unwrap();
+            // synced by another thread meanwhile
+            if queue.version() != version {
+                break;
+            }
+            if let Err(e) = queue.fill(gap, songs) {
+                log!(Level::Warn, "Failed to fetch queue: {}", e);
+                break;
+            }
+            missing = queue.missing(range.clone());
+        }
+        self.shared.queue.lock().unwrap().held(range)
+    }
+
+    pub fn queue_song(&self, pos: usize) -> Option<Arc<SongMeta>> {
+        self.queue_window(pos..pos + 1).pop()
+    }
+
+    // The queue entry with id, from the songs fetched so far or else asked
+    // for alone
+    pub fn queue_song_id(&self, id: Id) -> Option<Arc<SongMeta>> {
+        let fetched = self.synced_queue().and_then(|q| q.find(id).cloned());
+        if fetched.is_some() || !self.online() {
+            return fetched;
+        }
+        let id = id.0.to_string();
+        match self.with_raw(|raw| raw.command("playlistid", &[&id])) {
+            Ok(pairs) => proto::songs_from_pairs(pairs)
+                .pop()
+                .map(|s| Arc::new(SongMeta::from(s))),
+            // no longer in the queue
+            Err(e) if e.downcast_ref::<proto::Ack>().is_some() => None,
+            Err(e) => {
+                log!(Level::Warn, "Failed to fetch queue entry {}: {}", id, e);
+                None
+            }
+        }
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.update_status();
+        self.shared.cache.read().unwrap().status.data.clone()
+    }
+
+    pub fn library(&self) -> Option<Arc<Library>> {
+        self.shared.cache.read().unwrap().library.clone()
+    }
+
+    pub fn now_playing(&self) -> Option<Arc<SongMeta>> {
+        self.queue_song(self.status()?.song?.pos as usize)
+    }
+
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.update_status();
+        let cache = self.shared.cache.read().unwrap();
+        let s = cache.status.data.as_ref()?;
+        Some(match s.state {
+            State::Play if !cache.status.stale => {
+                s.elapsed? + Instant::now().duration_since(cache.status.fetched)
+            }
+            _ => s.elapsed?,
+        })
+    }
+
+    pub fn current_time(&self) -> Option<(Duration, Duration)> {
+        self.status()?.time
+    }
+
+    pub fn set_repeat(&self, repeat: bool) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.repeat(repeat))
+    }
+
+    pub fn play_pos(&self, pos: u32) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.switch(pos))
+    }
+
+    pub fn seek(&self, pos: Duration) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.rewind(pos))
+    }
+
+    pub fn play(&self) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.play())
+    }
+
+    pub fn play_id(&self, id: Id) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.switch(id))
+    }
+
+    pub fn pause(&self, pause: bool) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.pause(pause))
+    }
+
+    // Starts playing when stopped, as pausing does nothing then
+    pub fn toggle_pause(&self) -> Result<()> {
+        match self.status().map(|s| s.state) {
+            Some(State::Stop) => self.play(),
+            _ => {
+                self.invalidate();
+                self.with_client(|c| c.toggle_pause())
+            }
+        }
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.stop())
+    }
+
+    pub fn next(&self) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.next())
+    }
+
+    pub fn previous(&self) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.prev())
+    }
+
+    // In percent
+    pub fn set_volume(&self, volume: u8) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.volume(volume.min(100) as i8))
+    }
+
+    pub fn set_random(&self, random: bool) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.random(random))
+    }
+
+    pub fn set_single(&self, single: bool) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.single(single))
+    }
+
+    // Puts file at pos in the queue, returning its id
+    pub fn insert(&self, file: &str, pos: u32) -> Result<Id> {
+        self.invalidate();
+        let reply = self.with_raw(|raw| raw.command("addid", &[file, &pos.to_string()]))?;
+        match reply.iter().find(|(k, _)| k == "Id") {
+            Some((_, id)) => Ok(Id(id.parse()?)),
+            None => bail!("No id for {}", file),
+        }
+    }
+
+    pub fn delete_id(&self, id: Id) -> Result<()> {
+        self.invalidate();
+        self.with_client(|c| c.delete(id))
+    }
+
+    // Offline, the files are kept in the pending list instead
+    pub fn add(&self, files: &[&str]) -> Result<()> {
+        let mut op = Pending::Add(files.iter().map(|f| f.to_string()).collect());
+        if !self.online() {
+            self.cache().pending.push(op);
+            return Ok(());
+        }
+        self.invalidate();
+        self.with_raw(|raw| op.apply(raw))
+    }
+
+    // Every song's star rating. Servers without a sticker database have
+    // none; while offline the last known ratings are kept.
+    pub fn ratings(&self) -> Arc<Ratings> {
+        let generation = {
+            let cache = self.shared.cache.read().unwrap();
+            match (&cache.ratings, cache.ratings_invalid) {
+                (Some(r), false) => return r.clone(),
+                (r, _) => r.as_ref().map_or(0, |r| r.generation + 1),
+            }
+        };
+        // fetched without holding the cache, which the UI reads meanwhile
+        let loaded = stickers::load_ratings(self, generation);
+        let mut cache = self.cache();
+        cache.ratings_invalid = false;
+        match loaded {
+            Ok(r) => cache.ratings = Some(Arc::new(r)),
+            Err(e) => {
+                log!(Level::Debug, "No ratings: {}", e);
+                if cache.ratings.is_none() {
+                    cache.ratings = Some(Arc::new(Ratings::new(generation, HashMap::new())));
+                }
+            }
+        }
+        cache.ratings.clone().unwrap()
+    }
+
+    // 0 removes the rating
+    pub fn set_rating(&self, file: &str, rating: u8) -> Result<()> {
+        let rating = rating.min(stickers::MAX_RATING);
+        self.invalidate_stickers();
+        self.with_raw(|raw| match rating {
+            0 => raw.delete_sticker(file, stickers::RATING),
+            r => raw.set_sticker(file, stickers::RATING, &r.to_string()),
+        })
+    }
+
+    // Counts a play and stamps the time, returning the new count
+    pub fn record_play(&self, file: &str, at: SystemTime) -> Result<u32> {
+        self.with_raw(|raw| {
+            let count = raw
+                .sticker(file, stickers::PLAY_COUNT)?
+                .and_then(|c| c.parse::<u32>().ok())
+                .unwrap_or(0)
+                + 1;
+            raw.set_sticker(file, stickers::PLAY_COUNT, &count.to_string())?;
+            raw.set_sticker(
+                file,
+                stickers::LAST_PLAYED,
+                &stickers::unix_time(at).to_string(),
+            )?;
+            Ok(count)
+        })
+    }
+
+    // After the server announced a sticker change
+    pub fn invalidate_stickers(&self) {
+        self.cache().ratings_invalid = true;
+    }
+
+    // Keeps the queue as far as it was fetched, for a client started while
+    // the server is away
+    pub fn save_queue(&self) {
+        if let Err(e) = library_cache::save_queue(self) {
+            log!(Level::Warn, "Failed to save queue: {}", e);
+        }
+    }
+
+    pub fn pending(&self) -> Vec<Pending> {
+        self.shared.cache.read().unwrap().pending.clone()
+    }
+
+    // Applies the changes made while offline. Whatever wasn't applied when
+    // something fails stays pending, in order.
+    pub fn replay_pending(&self) -> Result<()> {
+        let mut pending = std::mem::take(&mut self.cache().pending);
+        if pending.is_empty() {
+            return Ok(());
+        }
+        log!(
+            Level::Info,
+            "Replaying {} pending operations",
+            pending.len()
+        );
+        self.invalidate();
+        for i in 0..pending.len() {
+            if let Err(e) = self.with_raw(|raw| pending[i].apply(raw)) {
+                let mut cache = self.cache();
+                let rest = std::mem::take(&mut cache.pending);
+                cache.pending = pending[i..].to_vec();
+                cache.pending.extend(rest);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use mpd::{
+    song::{Id, QueuePlace},
+    Song,
+};
+
+// An error response ("ACK [code@index] {command} message") from MPD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ack {
+    pub code: u32,
+    pub index: u32,
+    pub command: String,
+    pub message: String,
+}
+
+impl Ack {
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.strip_prefix("ACK [")?;
+        let (code, rest) = rest.split_once('@')?;
+        let (index, rest) = rest.split_once("] {")?;
+        let (command, message) = rest.split_once('}')?;
+        Some(Self {
+            code: code.parse().ok()?,
+            index: index.parse().ok()?,
+            command: command.into(),
+            message: message.trim().into(),
+        })
+    }
+}
+
+impl fmt::Display for Ack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.command, self.code, self.message)
+    }
+}
+
+impl std::error::Error for Ack {}
+
+// A response carrying binary data: the pairs around it, and the data itself,
+// which is empty if the response had none
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub pairs: Vec<(String, String)>,
+    pub data: Vec<u8>,
+}
+
+// A plain text protocol connection, for commands and responses the mpd crate
+// doesn't model (or models lossily, such as repeated tags).
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    pub version: String,
+}
+
+impl Connection {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        let mut conn = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            version: String::new(),
+        };
+        let banner = conn.read_line()?;
+        conn.version = banner
+            .strip_prefix("OK MPD ")
+            .ok_or_else(|| anyhow!("Unexpected banner: {}", banner))?
+            .into();
+        Ok(conn)
+    }
+
+    // Sends a command with quoted arguments and reads the full response
+    pub fn command(&mut self, command: &str, args: &[&str]) -> Result<Vec<(String, String)>> {
+        self.send(command, args)?;
+        self.read_pairs()
+    }
+
+    // Sends a command answered with one chunk of a binary payload, such as
+    // albumart
+    pub fn command_binary(&mut self, command: &str, args: &[&str]) -> Result<Chunk> {
+        self.send(command, args)?;
+        let mut pairs = vec![];
+        let mut data = vec![];
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(Chunk { pairs, data });
+            }
+            if let Some(ack) = Ack::parse(&line) {
+                return Err(ack.into());
+            }
+            match line.split_once(": ") {
+                Some(("binary", len)) => {
+                    data = vec![0; len.parse()?];
+                    self.reader.read_exact(&mut data)?;
+                    // the chunk is followed by a newline of its own
+                    let mut newline = [0; 1];
+                    self.reader.read_exact(&mut newline)?;
+                    if newline != *b"\n" {
+                        bail!("{} bytes of {} overran its binary length", len, command);
+                    }
+                }
+                Some((k, v)) => pairs.push((k.into(), v.into())),
+                None => bail!("Malformed response line: {}", line),
+            }
+        }
+    }
+
+    // Reads the whole payload of a binary command taking a uri and an offset,
+    // one chunk after another. None if there is nothing to read, such as
+    // readpicture on a file without a picture. The pairs are the first
+    // chunk's, which describe the whole payload.
+    pub fn read_binary(&mut self, command: &str, uri: &str) -> Result<Option<Chunk>> {
+        let mut whole: Option<Chunk> = None;
+        loop {
+            let offset = whole.as_ref().map_or(0, |w| w.data.len());
+            let chunk = self.command_binary(command, &[uri, &offset.to_string()])?;
+            let size: usize = match chunk.pairs.iter().find(|(k, _)| k == "size") {
+                Some((_, v)) => v.parse()?,
+                None => return Ok(whole),
+            };
+            let whole = whole.get_or_insert_with(|| Chunk {
+                pairs: chunk.pairs,
+                data: Vec::with_capacity(size),
+            });
+            if chunk.data.is_empty() && whole.data.len() < size {
+                bail!(
+                    "{} stopped at {} of {} bytes",
+                    command,
+                    whole.data.len(),
+                    size
+                );
+            }
+            whole.data.extend(chunk.data);
+            if whole.data.len() >= size {
+                return Ok(Some(std::mem::take(whole)));
+            }
+        }
+    }
+
+    // Sets the largest chunk the server sends for binary commands, 8192 by
+    // default. Only servers from 0.22.4 on know the command; older ones keep
+    // their default.
+    pub fn binary_limit(&mut self, bytes: usize) -> Result<()> {
+        if self.at_least((0, 22, 4)) {
+            self.command("binarylimit", &[&bytes.to_string()])?;
+        }
+        Ok(())
+    }
+
+    // The audio fingerprint of a song, if the server was built with
+    // chromaprint
+    pub fn fingerprint(&mut self, uri: &str) -> Result<Option<String>> {
+        let pairs = self.command("getfingerprint", &[uri])?;
+        Ok(pairs
+            .into_iter()
+            .find(|(k, _)| k == "chromaprint")
+            .map(|(_, v)| v))
+    }
+
+    // Every tag in a song file, including those MPD doesn't support, under the
+    // names the file uses
+    pub fn comments(&mut self, uri: &str) -> Result<Vec<(String, String)>> {
+        self.command("readcomments", &[uri])
+    }
+
+    // A song sticker's value, None if the song has none by that name
+    pub fn sticker(&mut self, uri: &str, name: &str) -> Result<Option<String>> {
+        match self.command("sticker", &["get", "song", uri, name]) {
+            Ok(pairs) => Ok(sticker_values(pairs).next().map(|(_, v)| v)),
+            Err(e) if no_such(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_sticker(&mut self, uri: &str, name: &str, value: &str) -> Result<()> {
+        self.command("sticker", &["set", "song", uri, name, value])?;
+        Ok(())
+    }
+
+    // Removing a sticker that isn't there is fine
+    pub fn delete_sticker(&mut self, uri: &str, name: &str) -> Result<()> {
+        match self.command("sticker", &["delete", "song", uri, name]) {
+            Err(e) if !no_such(&e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // Every sticker on a song as name and value. Servers without a sticker
+    // database refuse the command.
+    pub fn stickers(&mut self, uri: &str) -> Result<Vec<(String, String)>> {
+        let pairs = self.command("sticker", &["list", "song", uri])?;
+        Ok(sticker_values(pairs).collect())
+    }
+
+    // Songs under base ("" for all) with the named sticker, as file and value
+    pub fn find_stickers(&mut self, base: &str, name: &str) -> Result<Vec<(String, String)>> {
+        let mut out = vec![];
+        let mut file = None;
+        for (k, v) in self.command("sticker", &["find", "song", base, name])? {
+            match k.as_str() {
+                "file" => file = Some(v),
+                "sticker" => {
+                    if let (Some(f), Some((_, value))) = (file.take(), v.split_once('=')) {
+                        out.push((f, value.into()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(out)
+    }
+
+    // Whether the server's protocol version is the given one or newer
+    pub fn at_least(&self, version: (u32, u32, u32)) -> bool {
+        let mut parts = self.version.split('.').map(|p| p.parse().unwrap_or(0));
+        let ours = (
+            parts.next().unwrap_or(0),
+            parts.next().unwrap_or(0),
+            parts.next().unwrap_or(0),
+        );
+        ours >= version
+    }
+
+    fn send(&mut self, command: &str, args: &[&str]) -> Result<()> {
+        let mut line = String::from(command);
+        for arg in args {
+            line.push(' ');
+            line.push_str(&quote(arg));
+        }
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("Connection closed");
+        }
+        Ok(line.trim_end_matches('\n').into())
+    }
+
+    fn read_pairs(&mut self) -> Result<Vec<(String, String)>> {
+        let mut pairs = vec![];
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if let Some(ack) = Ack::parse(&line) {
+                return Err(ack.into());
+            }
+            match line.split_once(": ") {
+                Some((k, v)) => pairs.push((k.into(), v.into())),
+                None => bail!("Malformed response line: {}", line),
+            }
+        }
+    }
+}
+
+// "sticker: name=value" lines as name and value
+fn sticker_values(pairs: Vec<(String, String)>) -> impl Iterator<Item = (String, String)> {
+    pairs
+        .into_iter()
+        .filter(|(k, _)| k == "sticker")
+        .filter_map(|(_, v)| {
+            v.split_once('=')
+                .map(|(name, value)| (name.into(), value.into()))
+        })
+}
+
+// MPD's "no such sticker", code 50 as for any missing object
+fn no_such(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<Ack>().is_some_and(|a| a.code == 50)
+}
+
+pub fn quote(arg: &str) -> String {
+    let mut out = String::from("\"");
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+// Builds songs from a song list response. Unlike the mpd crate this keeps
+// every value of repeated tags, including Artist and Title. Directory and
+// playlist entries are skipped.
+pub fn songs_from_pairs(pairs: Vec<(String, String)>) -> Vec<Song> {
+    let mut songs = vec![];
+    let mut current: Option<Song> = None;
+    for (k, v) in pairs {
+        match k.as_str() {
+            "file" => {
+                songs.extend(current.take());
+                current = Some(Song {
+                    file: v,
+                    ..Default::default()
+                });
+                continue;
+            }
+            "directory" | "playlist" => {
+                songs.extend(current.take());
+                continue;
+            }
+            _ => {}
+        }
+        let Some(song) = current.as_mut() else {
+            continue;
+        };
+        match k.as_str() {
+            "Title" => {
+                song.title.get_or_insert_with(|| v.clone());
+                song.tags.push((k, v));
+            }
+            "Artist" => {
+                song.artist.get_or_insert_with(|| v.clone());
+                song.tags.push((k, v));
+            }
+            "Name" => song.name = Some(v),
+            "Last-Modified" => song.last_mod = Some(v),
+            "duration" => {
+                if let Ok(d) = v.parse::<f64>() {
+                    song.duration = Some(Duration::from_secs_f64(d));
+                }
+            }
+            "Time" => {
+                if song.duration.is_none() {
+                    song.duration = v.parse().ok().map(Duration::from_secs);
+                }
+            }
+            "Range" => song.range = v.parse().ok(),
+            "Id" => {
+                song.place.get_or_insert_with(QueuePlace::default).id = Id(v.parse().unwrap_or(0))
+            }
+            "Pos" => {
+                song.place.get_or_insert_with(QueuePlace::default).pos = v.parse().unwrap_or(0)
+            }
+            "Prio" => {
+                song.place.get_or_insert_with(QueuePlace::default).prio = v.parse().unwrap_or(0)
+            }
+            _ => song.tags.push((k, v)),
+        }
+    }
+    songs.extend(current);
+    songs
+}
@@ -0,0 +1,198 @@
+use std::{ops::Range, sync::Arc};
+
+use anyhow::{bail, Result};
+use log::{log, Level};
+use mpd::{song::Id, Song};
+
+use super::proto::{self, Connection};
+use crate::meta::SongMeta;
+
+// Where queue contents come from. Implemented by the raw connection, and by
+// in-memory queues in tests and benchmarks.
+pub trait QueueSource {
+    // playlistinfo start:end
+    fn window(&mut self, range: Range<usize>) -> Result<Vec<Song>>;
+    // plchangesposid version: the position of every song added, moved or
+    // edited since version
+    fn changes(&mut self, version: u32) -> Result<Vec<usize>>;
+}
+
+impl QueueSource for Connection {
+    fn window(&mut self, range: Range<usize>) -> Result<Vec<Song>> {
+        let range = format!("{}:{}", range.start, range.end);
+        Ok(proto::songs_from_pairs(
+            self.command("playlistinfo", &[&range])?,
+        ))
+    }
+
+    // the ids that come with the positions are of no use, see Queue::sync
+    fn changes(&mut self, version: u32) -> Result<Vec<usize>> {
+        let pairs = self.command("plchangesposid", &[&version.to_string()])?;
+        pairs
+            .into_iter()
+            .filter(|(k, _)| k == "cpos")
+            .map(|(_, v)| Ok(v.parse()?))
+            .collect()
+    }
+}
+
+// The play queue as of a playlist version. Entries are only fetched when
+// something asks for them, so a huge queue costs one slot per song until
+// it is scrolled through.
+#[derive(Debug, Default)]
+pub struct Queue {
+    version: Option<u32>,
+    songs: Vec<Option<Arc<SongMeta>>>,
+    // read back from disk rather than synced, see restore
+    restored: bool,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    // The song at pos, if it has been fetched
+    pub fn get(&self, pos: usize) -> Option<&Arc<SongMeta>> {
+        self.songs.get(pos)?.as_ref()
+    }
+
+    // The song with id, if it has been fetched
+    pub fn find(&self, id: Id) -> Option<&Arc<SongMeta>> {
+        self.songs
+            .iter()
+            .flatten()
+            .find(|s| s.place().is_some_and(|p| p.id == id))
+    }
+
+    // The songs fetched so far, by position
+    pub fn songs(&self) -> &[Option<Arc<SongMeta>>] {
+        &self.songs
+    }
+
+    pub fn clear(&mut self) {
+        self.version = None;
+        self.songs.clear();
+        self.restored = false;
+    }
+
+    // A queue saved by an earlier run, to show while the server is away.
+    // Versions say nothing across server restarts, so the first sync starts
+    // over whatever the version.
+    pub fn restore(&mut self, version: u32, songs: Vec<Option<Arc<SongMeta>>>) {
+        self.version = Some(version);
+        self.songs = songs;
+        self.restored = true;
+    }
+
+    pub fn restored(&self) -> bool {
+        self.restored
+    }
+
+    // Brings the queue up to version, which has len songs. Changed positions
+    // are emptied, to be fetched again on demand. They are not filled from
+    // entries already held under the same id: an entry keeps its id through
+    // a tag edit or a priority change, so the id says nothing about what it
+    // holds now. If the changes don't add up the queue starts over empty.
+    pub fn sync(&mut self, version: u32, len: usize, source: &mut impl QueueSource) -> Result<()> {
+        let changes = match self.patch_base(version) {
+            Some(base) => Some((base, source.changes(base)?)),
+            None => None,
+        };
+        self.apply(version, len, changes);
+        Ok(())
+    }
+
+    // The version a sync to version patches from, if there is one. Without
+    // it the queue is either current or starts over.
+    pub fn patch_base(&self, version: u32) -> Option<u32> {
+        self.version.filter(|v| !self.restored && *v < version)
+    }
+
+    // The second half of sync, for callers that fetch the changes since
+    // patch_base themselves. Changes since another version than the one
+    // held by now are of no use, and the queue starts over.
+    pub fn apply(&mut self, version: u32, len: usize, changes: Option<(u32, Vec<usize>)>) {
+        if !self.restored && self.version == Some(version) {
+            return;
+        }
+        match changes {
+            Some((base, changes)) if self.patch_base(version) == Some(base) => {
+                if let Err(e) = self.patch(len, changes) {
+                    log!(Level::Info, "Refetching queue: {}", e);
+                    self.songs = vec![None; len];
+                }
+            }
+            // first sync, or the server's playlist was reset
+            _ => self.songs = vec![None; len],
+        }
+        self.version = Some(version);
+        self.restored = false;
+    }
+
+    fn patch(&mut self, len: usize, changes: Vec<usize>) -> Result<()> {
+        self.songs.resize(len, None);
+        for pos in changes {
+            if pos >= len {
+                bail!("change at {} past the end of the queue ({})", pos, len);
+            }
+            self.songs[pos] = None;
+        }
+        Ok(())
+    }
+
+    // Songs in range, fetching any that are missing in as few requests as
+    // possible
+    pub fn window(
+        &mut self,
+        range: Range<usize>,
+        source: &mut impl QueueSource,
+    ) -> Result<Vec<Arc<SongMeta>>> {
+        while let Some(missing) = self.missing(range.clone()) {
+            let songs = source.window(missing.clone())?;
+            self.fill(missing, songs)?;
+        }
+        Ok(self.held(range))
+    }
+
+    // The first run of songs in range that has yet to be fetched
+    pub fn missing(&self, range: Range<usize>) -> Option<Range<usize>> {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        let start = range.clone().find(|i| self.songs[*i].is_none())?;
+        let end = (start..range.end)
+            .find(|i| self.songs[*i].is_some())
+            .unwrap_or(range.end);
+        Some(start..end)
+    }
+
+    // Puts the songs fetched for a range given by missing in place
+    pub fn fill(&mut self, range: Range<usize>, songs: Vec<Song>) -> Result<()> {
+        if songs.len() != range.len() || range.end > self.len() {
+            // the queue changed under us; resync from scratch next time
+            self.clear();
+            bail!("Expected {} songs, got {}", range.len(), songs.len());
+        }
+        for (i, song) in songs.into_iter().enumerate() {
+            self.songs[range.start + i] = Some(Arc::new(SongMeta::from(song)));
+        }
+        Ok(())
+    }
+
+    // The songs in range fetched so far, up to the first gap
+    pub fn held(&self, range: Range<usize>) -> Vec<Arc<SongMeta>> {
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        self.songs[range].iter().map_while(|s| s.clone()).collect()
+    }
+}
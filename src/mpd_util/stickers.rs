@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use log::{log, Level};
+use mpd::song::Id;
+
+use super::MPD;
+use crate::meta::SongMeta;
+
+// Sticker names, the same as other clients use so counts carry over
+pub const RATING: &str = "rating";
+pub const PLAY_COUNT: &str = "playCount";
+pub const LAST_PLAYED: &str = "lastPlayed";
+
+pub const MAX_RATING: u8 = 5;
+
+// Longest gap between two observations that still counts as listening. The
+// tick stops while paused, so a longer one means playback was interrupted.
+const MAX_GAP: Duration = Duration::from_secs(2);
+
+// Star ratings of every rated song, loaded in one go so columns and filters
+// don't ask per song. The generation changes with every reload.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Ratings {
+    pub generation: u64,
+    files: HashMap<String, u8>,
+}
+
+impl Ratings {
+    pub fn new(generation: u64, files: HashMap<String, u8>) -> Self {
+        Self { generation, files }
+    }
+
+    pub fn get(&self, file: &str) -> Option<u8> {
+        self.files.get(file).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+// Parses a rating sticker, clamped to the star range
+pub fn parse_rating(value: &str) -> Option<u8> {
+    value.trim().parse::<u8>().ok().map(|r| r.min(MAX_RATING))
+}
+
+// e.g. "★★★☆☆"
+pub fn stars(rating: u8) -> String {
+    let rating = rating.min(MAX_RATING) as usize;
+    "★".repeat(rating) + &"☆".repeat(MAX_RATING as usize - rating)
+}
+
+// Decides when the playing song counts as played: once it has been listened
+// to for the threshold share of its length. Listening time is measured rather
+// than read off the position, so skipping ahead doesn't count, and starting
+// over, e.g. on repeat, counts again.
+pub struct PlayCounter {
+    threshold: f64,
+    most: Option<Duration>,
+    current: Option<Listen>,
+}
+
+struct Listen {
+    id: Id,
+    elapsed: Duration,
+    listened: Duration,
+    seen: Instant,
+    counted: bool,
+}
+
+impl PlayCounter {
+    // percent of a song to listen to, 1 to 100
+    pub fn new(percent: u8) -> Self {
+        Self {
+            threshold: percent.clamp(1, 100) as f64 / 100.0,
+            most: None,
+            current: None,
+        }
+    }
+
+    // Long songs count after this much listening, whatever their share
+    pub fn at_most(mut self, listened: Duration) -> Self {
+        self.most = Some(listened);
+        self
+    }
+
+    // Fed the playing song and its position; returns the file once it counts
+    pub fn listen(
+        &mut self,
+        song: Option<&SongMeta>,
+        elapsed: Duration,
+        now: Instant,
+    ) -> Option<String> {
+        let Some((song, id)) = song.and_then(|s| Some((s, s.place()?.id))) else {
+            self.current = None;
+            return None;
+        };
+        let restarted = match &self.current {
+            Some(l) => l.id != id || elapsed + MAX_GAP < l.elapsed,
+            None => true,
+        };
+        if restarted {
+            self.current = Some(Listen {
+                id,
+                elapsed,
+                listened: Duration::ZERO,
+                seen: now,
+                counted: false,
+            });
+            return None;
+        }
+        let listen = self.current.as_mut()?;
+        let gap = now.duration_since(listen.seen);
+        if gap <= MAX_GAP {
+            listen.listened += gap;
+        }
+        listen.seen = now;
+        listen.elapsed = elapsed;
+
+        let duration = song.duration()?;
+        let mut needed = duration.as_secs_f64() * self.threshold;
+        if let Some(most) = self.most {
+            needed = needed.min(most.as_secs_f64());
+        }
+        if listen.counted || song.file().contains("://") || listen.listened.as_secs_f64() < needed {
+            return None;
+        }
+        listen.counted = true;
+        Some(song.file().into())
+    }
+
+    // How long the current listen has lasted, once it counted as a play
+    pub fn counted(&self) -> Option<Duration> {
+        self.current
+            .as_ref()
+            .filter(|l| l.counted)
+            .map(|l| l.listened)
+    }
+}
+
+pub(crate) fn unix_time(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+pub(super) fn load_ratings(mpd: &MPD, generation: u64) -> Result<Ratings> {
+    let found = mpd.with_raw(|raw| raw.find_stickers("", RATING))?;
+    Ok(Ratings::new(
+        generation,
+        found
+            .into_iter()
+            .filter_map(|(file, value)| Some((file, parse_rating(&value)?)))
+            .filter(|(_, r)| *r > 0)
+            .collect(),
+    ))
+}
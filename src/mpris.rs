@@ -0,0 +1,623 @@
+use std::{
+    collections::HashMap,
+    process,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{log, Level};
+use mpd::{song::Id, State, Status};
+use zbus::{
+    blocking::{connection::Builder, Connection},
+    fdo::{self, RequestNameFlags},
+    interface,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Str, Value},
+};
+
+use crate::app::App;
+use crate::meta::SongMeta;
+use crate::mpd_util::{idle::Observer, MPD};
+
+pub const BUS_NAME: &str = "org.mpris.MediaPlayer2.mpcursive";
+pub const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const TRACK_LIST: &str = "org.mpris.MediaPlayer2.TrackList";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+// Queue entries are named by their song id
+const TRACK_PATH: &str = "/org/mpcursive/Track";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+// Every listed song has to be fetched, so a large queue is only listed
+// around the current song
+const TRACKS: usize = 100;
+
+// A position off by more than this from where playback should have got to
+// was seeked to
+const SEEK_SLACK: Duration = Duration::from_secs(2);
+
+fn track_id(id: Id) -> OwnedObjectPath {
+    ObjectPath::try_from(format!("{}/{}", TRACK_PATH, id.0))
+        .unwrap()
+        .into()
+}
+
+fn parse_track_id(path: &ObjectPath) -> Option<Id> {
+    let id = path.as_str().strip_prefix(TRACK_PATH)?.strip_prefix('/')?;
+    id.parse().ok().map(Id)
+}
+
+fn micros(d: Duration) -> i64 {
+    d.as_micros() as i64
+}
+
+fn failed(e: anyhow::Error) -> fdo::Error {
+    fdo::Error::Failed(e.to_string())
+}
+
+fn text(s: &str) -> OwnedValue {
+    Str::from(s.to_string()).into()
+}
+
+fn texts(v: &[String]) -> OwnedValue {
+    // only values holding file descriptors can't be owned
+    OwnedValue::try_from(Value::from(v.to_vec())).unwrap()
+}
+
+// file:// URLs escape everything but unreserved characters and slashes
+fn file_url(path: &str) -> String {
+    let mut url = String::from("file://");
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(b as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    url
+}
+
+// A song's MPRIS metadata, under xesam and mpris keys
+pub fn metadata(app: &App, song: &SongMeta) -> HashMap<String, OwnedValue> {
+    let mut m = HashMap::new();
+    let mut put = |key: &str, value: OwnedValue| {
+        m.insert(key.to_string(), value);
+    };
+    if let Some(place) = song.place() {
+        put("mpris:trackid", track_id(place.id).into_inner().into());
+    }
+    if let Some(d) = song.duration() {
+        put("mpris:length", micros(d).into());
+    }
+    put("xesam:title", text(&song.display_title()));
+    let lists = [
+        ("xesam:artist", &song.artists),
+        ("xesam:albumArtist", &song.album_artists),
+        ("xesam:genre", &song.genres),
+        ("xesam:composer", &song.composers),
+    ];
+    for (key, values) in lists {
+        if !values.is_empty() {
+            put(key, texts(values));
+        }
+    }
+    if let Some(album) = &song.album {
+        put("xesam:album", text(album));
+    }
+    if let Some(n) = song.track.as_ref().and_then(|t| t.number) {
+        put("xesam:trackNumber", (n as i32).into());
+    }
+    if let Some(n) = song.disc.as_ref().and_then(|d| d.number) {
+        put("xesam:discNumber", (n as i32).into());
+    }
+    if let Some(date) = &song.date {
+        put("xesam:contentCreated", text(&date.to_string()));
+    }
+    let file = song.file();
+    if file.contains("://") {
+        put("xesam:url", text(file));
+    } else if let Some(path) = app.config.mpd.local_path(file) {
+        put("xesam:url", text(&file_url(&path.to_string_lossy())));
+    }
+    m
+}
+
+// The queue ids listed in the track list and the current one
+fn tracks(mpd: &MPD) -> (Vec<OwnedObjectPath>, Option<Id>) {
+    let status = mpd.status();
+    let current = status.as_ref().and_then(|s| s.song);
+    let start = current.map_or(0, |p| (p.pos as usize).saturating_sub(TRACKS / 2));
+    let ids = mpd
+        .queue_window(start..(start + TRACKS).min(mpd.queue_len()))
+        .iter()
+        .filter_map(|s| s.place())
+        .map(|p| track_id(p.id))
+        .collect();
+    (ids, current.map(|p| p.id))
+}
+
+// org.mpris.MediaPlayer2: who is playing. The terminal can't be raised and
+// quitting is left to the user.
+pub struct Base;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Base {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "mpcursive"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec![]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+// org.mpris.MediaPlayer2.Player, MPD's playback
+pub struct Player {
+    app: App,
+}
+
+impl Player {
+    fn status(&self) -> fdo::Result<Status> {
+        self.app
+            .mpd
+            .status()
+            .ok_or_else(|| fdo::Error::Failed(String::from("Not connected to MPD")))
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.app.mpd.next().map_err(failed)
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.app.mpd.previous().map_err(failed)
+    }
+
+    fn pause(&self) -> fdo::Result<()> {
+        self.app.mpd.pause(true).map_err(failed)
+    }
+
+    fn play_pause(&self) -> fdo::Result<()> {
+        self.app.mpd.toggle_pause().map_err(failed)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        self.app.mpd.stop().map_err(failed)
+    }
+
+    fn play(&self) -> fdo::Result<()> {
+        match self.status()?.state {
+            State::Pause => self.app.mpd.pause(false),
+            _ => self.app.mpd.play(),
+        }
+        .map_err(failed)
+    }
+
+    // By offset microseconds; past the end goes to the next song
+    fn seek(&self, offset: i64) -> fdo::Result<()> {
+        let Some(song) = self.app.mpd.now_playing() else {
+            return Ok(());
+        };
+        let elapsed = micros(self.app.mpd.elapsed().unwrap_or_default());
+        let target = (elapsed + offset).max(0);
+        match song.duration() {
+            Some(d) if target > micros(d) => self.app.mpd.next(),
+            _ => self.app.mpd.seek(Duration::from_micros(target as u64)),
+        }
+        .map_err(failed)
+    }
+
+    // Ignored unless track is still the current song, as the spec asks
+    fn set_position(&self, track: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let current = self.status()?.song.map(|p| p.id);
+        if current.is_none() || parse_track_id(&track) != current || position < 0 {
+            return Ok(());
+        }
+        let song = self.app.mpd.now_playing();
+        if song
+            .and_then(|s| s.duration())
+            .is_some_and(|d| position > micros(d))
+        {
+            return Ok(());
+        }
+        self.app
+            .mpd
+            .seek(Duration::from_micros(position as u64))
+            .map_err(failed)
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(String::from(
+            "Add songs through the track list",
+        )))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        position: i64,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> fdo::Result<&str> {
+        Ok(match self.status()?.state {
+            State::Play => "Playing",
+            State::Pause => "Paused",
+            State::Stop => "Stopped",
+        })
+    }
+
+    // Single without repeat stops after the song, which MPRIS has no name for
+    #[zbus(property)]
+    fn loop_status(&self) -> fdo::Result<&str> {
+        let status = self.status()?;
+        Ok(match (status.repeat, status.single) {
+            (false, _) => "None",
+            (true, true) => "Track",
+            (true, false) => "Playlist",
+        })
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&self, status: &str) -> fdo::Result<()> {
+        let (repeat, single) = match status {
+            "None" => (false, false),
+            "Track" => (true, true),
+            "Playlist" => (true, false),
+            s => {
+                return Err(fdo::Error::InvalidArgs(format!(
+                    "Unknown loop status {}",
+                    s
+                )))
+            }
+        };
+        let mpd = &self.app.mpd;
+        mpd.set_repeat(repeat).map_err(failed)?;
+        mpd.set_single(single).map_err(failed)
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[zbus(property)]
+    fn shuffle(&self) -> fdo::Result<bool> {
+        Ok(self.status()?.random)
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, shuffle: bool) -> fdo::Result<()> {
+        self.app.mpd.set_random(shuffle).map_err(failed)
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut m = match self.app.mpd.now_playing() {
+            Some(song) => metadata(&self.app, &song),
+            None => HashMap::new(),
+        };
+        m.entry(String::from("mpris:trackid"))
+            .or_insert_with(|| ObjectPath::from_static_str_unchecked(NO_TRACK).into());
+        m
+    }
+
+    // 0 to 1; MPD reports -1 without a mixer
+    #[zbus(property)]
+    fn volume(&self) -> fdo::Result<f64> {
+        Ok(self.status()?.volume.max(0) as f64 / 100.0)
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) -> fdo::Result<()> {
+        let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+        self.app.mpd.set_volume(percent).map_err(failed)
+    }
+
+    // Changes continuously, so clients ask for it or follow Seeked
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(self.app.mpd.elapsed().unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> fdo::Result<bool> {
+        let status = self.status()?;
+        Ok(status.nextsong.is_some() || (status.repeat && status.queue_len > 0))
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> fdo::Result<bool> {
+        Ok(self.status()?.song.is_some())
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> fdo::Result<bool> {
+        Ok(self.status()?.queue_len > 0)
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> fdo::Result<bool> {
+        Ok(self.status()?.song.is_some())
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> fdo::Result<bool> {
+        Ok(self.status()?.duration.is_some())
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+// org.mpris.MediaPlayer2.TrackList, the queue
+pub struct TrackList {
+    app: App,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackList {
+    // In the order asked for; ids no longer in the queue are left out
+    fn get_tracks_metadata(
+        &self,
+        track_ids: Vec<ObjectPath<'_>>,
+    ) -> Vec<HashMap<String, OwnedValue>> {
+        track_ids
+            .iter()
+            .filter_map(|t| self.app.mpd.queue_song_id(parse_track_id(t)?))
+            .map(|s| metadata(&self.app, &s))
+            .collect()
+    }
+
+    // uri is a song in the database, a file:// URL under the music
+    // directory or a stream
+    fn add_track(
+        &self,
+        uri: &str,
+        after_track: ObjectPath<'_>,
+        set_as_current: bool,
+    ) -> fdo::Result<()> {
+        let mpd = &self.app.mpd;
+        let mut file = uri.to_string();
+        if let (Some(path), Some(dir)) = (
+            uri.strip_prefix("file://"),
+            &self.app.config.mpd.music_directory,
+        ) {
+            if let Ok(relative) = std::path::Path::new(path).strip_prefix(dir) {
+                file = relative.to_string_lossy().into();
+            }
+        }
+        let pos = parse_track_id(&after_track)
+            .and_then(|after| mpd.queue_song_id(after)?.place())
+            .map_or(0, |p| p.pos + 1);
+        let id = mpd.insert(&file, pos).map_err(failed)?;
+        if set_as_current {
+            mpd.play_id(id).map_err(failed)?;
+        }
+        Ok(())
+    }
+
+    fn remove_track(&self, track_id: ObjectPath<'_>) -> fdo::Result<()> {
+        let id = parse_track_id(&track_id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown track {}", track_id)))?;
+        self.app.mpd.delete_id(id).map_err(failed)
+    }
+
+    fn go_to(&self, track_id: ObjectPath<'_>) -> fdo::Result<()> {
+        let id = parse_track_id(&track_id)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unknown track {}", track_id)))?;
+        self.app.mpd.play_id(id).map_err(failed)
+    }
+
+    #[zbus(signal)]
+    async fn track_list_replaced(
+        emitter: &zbus::object_server::SignalEmitter<'_>,
+        tracks: Vec<OwnedObjectPath>,
+        current_track: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property(emits_changed_signal = "invalidates"))]
+    fn tracks(&self) -> Vec<OwnedObjectPath> {
+        tracks(&self.app.mpd).0
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_edit_tracks(&self) -> bool {
+        true
+    }
+}
+
+// What was last announced, to tell what changed since
+#[derive(Default)]
+struct Seen {
+    player: HashMap<&'static str, OwnedValue>,
+    queue_version: Option<u32>,
+    // the song, where it was and when, and whether it was playing
+    position: Option<(Option<Id>, Duration, Instant, bool)>,
+}
+
+// The player on the session bus. As an observer it tells clients about
+// changes made anywhere: property changes, seeks and queue edits.
+pub struct Mpris {
+    conn: Connection,
+    app: App,
+    seen: Seen,
+}
+
+impl Mpris {
+    // On the bus at address, or the session bus. If another instance holds
+    // the name, a name of its own is taken, as the spec suggests.
+    pub fn start(app: App, address: Option<&str>) -> Result<Self> {
+        let builder = match address {
+            Some(a) => Builder::address(a)?,
+            None => Builder::session()?,
+        };
+        let conn = builder
+            .serve_at(PATH, Base)?
+            .serve_at(PATH, Player { app: app.clone() })?
+            .serve_at(PATH, TrackList { app: app.clone() })?
+            .build()?;
+        match conn.request_name_with_flags(BUS_NAME, RequestNameFlags::DoNotQueue.into()) {
+            Err(zbus::Error::NameTaken) => {
+                let name = format!("{}.instance{}", BUS_NAME, process::id());
+                conn.request_name(name.as_str())?;
+                log!(Level::Info, "{} is taken, using {}", BUS_NAME, name);
+            }
+            result => {
+                result?;
+            }
+        }
+        Ok(Self {
+            conn,
+            app,
+            seen: Seen::default(),
+        })
+    }
+
+    fn player_properties(&self) -> HashMap<&'static str, OwnedValue> {
+        let player = Player {
+            app: self.app.clone(),
+        };
+        let mut props = HashMap::new();
+        let mut put = |name, value: fdo::Result<OwnedValue>| {
+            if let Ok(v) = value {
+                props.insert(name, v);
+            }
+        };
+        put("PlaybackStatus", player.playback_status().map(text));
+        put("LoopStatus", player.loop_status().map(text));
+        put("Shuffle", player.shuffle().map(Into::into));
+        put("Metadata", Ok(player.metadata().into()));
+        put("Volume", player.volume().map(Into::into));
+        put("CanGoNext", player.can_go_next().map(Into::into));
+        put("CanGoPrevious", player.can_go_previous().map(Into::into));
+        put("CanPlay", player.can_play().map(Into::into));
+        put("CanPause", player.can_pause().map(Into::into));
+        put("CanSeek", player.can_seek().map(Into::into));
+        props
+    }
+
+    fn emit<B>(&self, iface: &str, signal: &str, body: &B)
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        if let Err(e) = self
+            .conn
+            .emit_signal(None::<&str>, PATH, iface, signal, body)
+        {
+            log!(Level::Warn, "Failed to emit {}: {}", signal, e);
+        }
+    }
+
+    fn announce_properties(&mut self) {
+        let props = self.player_properties();
+        let changed: HashMap<&str, &OwnedValue> = props
+            .iter()
+            .filter(|(k, v)| self.seen.player.get(*k) != Some(*v))
+            .map(|(k, v)| (*k, v))
+            .collect();
+        if !changed.is_empty() {
+            self.emit(
+                PROPERTIES,
+                "PropertiesChanged",
+                &(PLAYER, changed, Vec::<&str>::new()),
+            );
+        }
+        self.seen.player = props;
+    }
+
+    fn announce_seek(&mut self, status: &Status) {
+        let song = status.song.map(|p| p.id);
+        let position = self.app.mpd.elapsed().unwrap_or_default();
+        let playing = status.state == State::Play;
+        let now = Instant::now();
+        if let Some((last_song, last, at, was_playing)) = self.seen.position {
+            let expected = match was_playing {
+                true => last + now.duration_since(at),
+                false => last,
+            };
+            let off = position.max(expected) - position.min(expected);
+            if last_song == song && status.state != State::Stop && off > SEEK_SLACK {
+                self.emit(PLAYER, "Seeked", &micros(position));
+            }
+        }
+        self.seen.position = Some((song, position, now, playing));
+    }
+
+    fn announce_tracks(&mut self, status: &Status) {
+        if self.seen.queue_version == Some(status.queue_version) {
+            return;
+        }
+        let first = self.seen.queue_version.is_none();
+        self.seen.queue_version = Some(status.queue_version);
+        if first {
+            return;
+        }
+        let (ids, current) = tracks(&self.app.mpd);
+        let current = current
+            .map(track_id)
+            .unwrap_or_else(|| ObjectPath::from_static_str_unchecked(NO_TRACK).into());
+        self.emit(TRACK_LIST, "TrackListReplaced", &(ids, current));
+        let changed: HashMap<&str, OwnedValue> = HashMap::new();
+        self.emit(
+            PROPERTIES,
+            "PropertiesChanged",
+            &(TRACK_LIST, changed, vec!["Tracks"]),
+        );
+    }
+}
+
+impl Observer for Mpris {
+    fn observe(&mut self, mpd: &MPD) {
+        let Some(status) = mpd.status() else {
+            return;
+        };
+        self.announce_properties();
+        self.announce_seek(&status);
+        self.announce_tracks(&status);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::mpd_util::MPD;
+
+type Action = Box<dyn FnOnce(&MPD) + Send>;
+
+struct Notice {
+    text: String,
+    until: Instant,
+    // a key that runs the action while the notice shows
+    action: Option<(char, Action)>,
+}
+
+// A short message for the status line, posted from anywhere, e.g. a
+// background thread. A newer notice replaces the one showing.
+#[derive(Clone, Default)]
+pub struct Notices {
+    current: Arc<Mutex<Option<Notice>>>,
+}
+
+impl Notices {
+    pub fn show(&self, text: &str, duration: Duration) {
+        self.post(text, duration, None);
+    }
+
+    // Shows text and runs action if key is pressed before it goes away
+    pub fn offer(
+        &self,
+        text: &str,
+        duration: Duration,
+        key: char,
+        action: impl FnOnce(&MPD) + Send + 'static,
+    ) {
+        self.post(text, duration, Some((key, Box::new(action))));
+    }
+
+    fn post(&self, text: &str, duration: Duration, action: Option<(char, Action)>) {
+        *self.current.lock().unwrap() = Some(Notice {
+            text: text.into(),
+            until: Instant::now() + duration,
+            action,
+        });
+    }
+
+    // The text showing now, if any
+    pub fn current(&self) -> Option<String> {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|n| n.until <= Instant::now()) {
+            *current = None;
+        }
+        current.as_ref().map(|n| n.text.clone())
+    }
+
+    // Runs the offered action if key is its key. The notice goes with it.
+    pub fn act(&self, key: char, mpd: &MPD) -> bool {
+        let mut current = self.current.lock().unwrap();
+        let offered = current.as_ref().is_some_and(|n| {
+            n.until > Instant::now() && matches!(n.action, Some((k, _)) if k == key)
+        });
+        if !offered {
+            return false;
+        }
+        let action = current.take().and_then(|n| n.action);
+        drop(current);
+        if let Some((_, action)) = action {
+            action(mpd);
+        }
+        true
+    }
+}
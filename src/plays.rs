@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{log, Level};
+use mpd::State;
+
+use crate::history::Recorder;
+use crate::meta::SongMeta;
+use crate::mpd_util::{idle::Observer, stickers::PlayCounter, MPD};
+
+// Counts plays once for everything that keeps them, so they all agree on
+// what was played
+pub struct Plays {
+    counter: PlayCounter,
+    stickers: bool,
+    history: Option<Recorder>,
+}
+
+impl Plays {
+    // percent of a song to listen to, see PlayCounter
+    pub fn new(threshold: u8) -> Self {
+        Self {
+            counter: PlayCounter::new(threshold),
+            stickers: false,
+            history: None,
+        }
+    }
+
+    // Counts plays in a sticker per song
+    pub fn with_stickers(mut self) -> Self {
+        self.stickers = true;
+        self
+    }
+
+    // Keeps the listening history
+    pub fn with_history(mut self, recorder: Recorder) -> Self {
+        self.history = Some(recorder);
+        self
+    }
+
+    fn counted(&mut self, mpd: &MPD, file: &str, song: Option<&SongMeta>) {
+        if self.stickers {
+            match mpd.record_play(file, SystemTime::now()) {
+                Ok(count) => log!(Level::Debug, "Played {} {} times", file, count),
+                Err(e) => log!(Level::Warn, "Failed to record play of {}: {}", file, e),
+            }
+        }
+        if let (Some(history), Some(song)) = (&mut self.history, song) {
+            history.counted(song, self.counter.counted().unwrap_or_default());
+        }
+    }
+}
+
+impl Observer for Plays {
+    fn observe(&mut self, mpd: &MPD) {
+        let Some(status) = mpd.status() else {
+            return;
+        };
+        match status.state {
+            // paused time isn't listening, and a long pause breaks the run anyway
+            State::Pause => return,
+            State::Stop => {
+                self.counter.listen(None, Duration::ZERO, Instant::now());
+            }
+            State::Play => {
+                let song = mpd.now_playing();
+                let elapsed = mpd.elapsed().unwrap_or_default();
+                if let Some(file) = self
+                    .counter
+                    .listen(song.as_deref(), elapsed, Instant::now())
+                {
+                    self.counted(mpd, &file, song.as_deref());
+                }
+            }
+        }
+        if let Some(history) = &mut self.history {
+            history.listening(self.counter.counted());
+        }
+    }
+
+    fn close(&mut self, _mpd: &MPD) {
+        if let Some(history) = &mut self.history {
+            history.finish();
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{log, Level};
+use mpd::{song::Id, State};
+
+use crate::app::App;
+use crate::config::ResumeConfig;
+use crate::meta::SongMeta;
+use crate::mpd_util::{idle::Observer, MPD};
+use crate::view::playing::format_time;
+
+// The song sticker holding the position, in seconds
+pub const POSITION: &str = "resumePosition";
+// Positions this close to either end aren't worth keeping: the song had
+// barely started, or it finished
+const MARGIN: Duration = Duration::from_secs(10);
+const NOTICE: Duration = Duration::from_secs(10);
+// A song first seen this close to its start was just started, rather than
+// playing before the client looked
+const STARTING: Duration = Duration::from_secs(3);
+pub const START_OVER: char = 'R';
+
+// Whether the config asks to remember where song stopped
+pub fn resumable(config: &ResumeConfig, song: &SongMeta) -> bool {
+    let file = song.file();
+    config.directories.iter().any(|d| {
+        let d = d.trim_matches('/');
+        !d.is_empty() && file.starts_with(d) && file[d.len()..].starts_with('/')
+    }) || song
+        .genres
+        .iter()
+        .any(|g| config.genres.iter().any(|c| c.eq_ignore_ascii_case(g)))
+}
+
+// The position saved for file, if any
+pub fn saved(mpd: &MPD, file: &str) -> Result<Option<Duration>> {
+    let value = mpd.with_raw(|raw| raw.sticker(file, POSITION))?;
+    Ok(value
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64))
+}
+
+struct Tracked {
+    id: Id,
+    file: String,
+    duration: Option<Duration>,
+    elapsed: Duration,
+    paused: bool,
+}
+
+// Saves the position of resumable songs when they are paused, skipped or
+// stopped, or the client exits, and seeks back to it when they play again.
+// Only songs seen starting are moved: one already playing when the client
+// connects was left where it is on purpose.
+pub struct Resumer {
+    app: App,
+    current: Option<Tracked>,
+    // whether playback was followed since connecting
+    watching: bool,
+}
+
+impl Resumer {
+    pub fn new(app: App) -> Self {
+        Self {
+            app,
+            current: None,
+            watching: false,
+        }
+    }
+
+    fn save(&self, mpd: &MPD, song: &Tracked) {
+        let finished = song.duration.is_some_and(|d| song.elapsed + MARGIN >= d);
+        let result = if song.elapsed < MARGIN || finished {
+            mpd.with_raw(|raw| raw.delete_sticker(&song.file, POSITION))
+        } else {
+            let secs = format!("{:.1}", song.elapsed.as_secs_f64());
+            mpd.with_raw(|raw| raw.set_sticker(&song.file, POSITION, &secs))
+        };
+        if let Err(e) = result {
+            log!(
+                Level::Warn,
+                "Failed to save position in {}: {}",
+                song.file,
+                e
+            );
+        }
+    }
+
+    // Seeks a song that just started to where it was left, returning the
+    // position it plays from
+    fn restore(&self, mpd: &MPD, song: &SongMeta, elapsed: Duration) -> Duration {
+        let position = match saved(mpd, song.file()) {
+            Ok(Some(p)) if p >= MARGIN && elapsed + MARGIN < p => p,
+            Ok(_) => return elapsed,
+            Err(e) => {
+                log!(Level::Warn, "No saved position for {}: {}", song.file(), e);
+                return elapsed;
+            }
+        };
+        if let Err(e) = mpd.seek(position) {
+            log!(Level::Warn, "Failed to resume {}: {}", song.file(), e);
+            return elapsed;
+        }
+        let file = song.file().to_string();
+        self.app.notices.offer(
+            &format!(
+                "Resumed at {}, press {} to start over",
+                format_time(position),
+                START_OVER
+            ),
+            NOTICE,
+            START_OVER,
+            move |mpd| {
+                let result = mpd
+                    .seek(Duration::ZERO)
+                    .and_then(|()| mpd.with_raw(|raw| raw.delete_sticker(&file, POSITION)));
+                if let Err(e) = result {
+                    log!(Level::Warn, "Failed to start {} over: {}", file, e);
+                }
+            },
+        );
+        position
+    }
+}
+
+impl Observer for Resumer {
+    fn observe(&mut self, mpd: &MPD) {
+        let Some(status) = mpd.status() else {
+            return;
+        };
+        let song = match status.state {
+            State::Stop => None,
+            _ => mpd
+                .now_playing()
+                .filter(|s| resumable(&self.app.config.resume, s)),
+        };
+        let paused = status.state == State::Pause;
+        let elapsed = mpd.elapsed().unwrap_or_default();
+        let id = song.as_ref().and_then(|s| s.place()).map(|p| p.id);
+        let started = self.watching || elapsed < STARTING;
+        self.watching = true;
+
+        match (self.current.take(), song) {
+            (Some(mut old), Some(_)) if Some(old.id) == id => {
+                old.elapsed = elapsed;
+                if paused && !old.paused {
+                    self.save(mpd, &old);
+                }
+                old.paused = paused;
+                self.current = Some(old);
+            }
+            (old, new) => {
+                // skipped or stopped: the last position seen is where it was left
+                if let Some(old) = old {
+                    self.save(mpd, &old);
+                }
+                let (Some(song), Some(id)) = (new, id) else {
+                    return;
+                };
+                let elapsed = if paused || !started {
+                    elapsed
+                } else {
+                    self.restore(mpd, &song, elapsed)
+                };
+                self.current = Some(Tracked {
+                    id,
+                    file: song.file().into(),
+                    duration: song.duration(),
+                    elapsed,
+                    paused,
+                });
+            }
+        }
+    }
+
+    // what changed while away wasn't seen happen
+    fn connected(&mut self, _mpd: &MPD) {
+        self.watching = false;
+    }
+
+    fn close(&mut self, mpd: &MPD) {
+        let Some(mut song) = self.current.take() else {
+            return;
+        };
+        let playing = mpd.status().and_then(|s| s.song).map(|p| p.id);
+        if let (Some(elapsed), true) = (mpd.elapsed(), playing == Some(song.id)) {
+            song.elapsed = elapsed;
+        }
+        self.save(mpd, &song);
+    }
+}
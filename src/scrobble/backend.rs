@@ -0,0 +1,283 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use md5::{Digest, Md5};
+use serde_json::{json, Map, Value};
+
+use super::{Rejected, Submission, Track};
+use crate::config::{ScrobbleBackend, ScrobbleConfig};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const CLIENT: &str = "mpcursive";
+
+pub const LISTENBRAINZ_URL: &str = "https://api.listenbrainz.org";
+pub const LASTFM_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+// A service, or anything else, that takes submissions. Errors are retried
+// unless they are Rejected.
+pub trait Backend: Send {
+    fn name(&self) -> &str;
+    fn submit(&self, submission: &Submission) -> Result<()>;
+}
+
+// The backend the config asks for, if any
+pub fn from_config(cfg: &ScrobbleConfig) -> Result<Option<Box<dyn Backend>>> {
+    let url = |default: &str| cfg.url.clone().unwrap_or_else(|| default.into());
+    let backend: Box<dyn Backend> = match cfg.backend {
+        None => return Ok(None),
+        Some(ScrobbleBackend::ListenBrainz) => {
+            if cfg.token.is_empty() {
+                bail!("ListenBrainz needs a token");
+            }
+            Box::new(ListenBrainz::new(&url(LISTENBRAINZ_URL), &cfg.token))
+        }
+        Some(ScrobbleBackend::LastFm) => {
+            if [&cfg.api_key, &cfg.api_secret, &cfg.session_key]
+                .iter()
+                .any(|s| s.is_empty())
+            {
+                bail!("Last.fm needs api_key, api_secret and session_key");
+            }
+            Box::new(LastFm::new(
+                &url(LASTFM_URL),
+                &cfg.api_key,
+                &cfg.api_secret,
+                &cfg.session_key,
+            ))
+        }
+        Some(ScrobbleBackend::File) => match &cfg.file {
+            Some(f) => Box::new(FileSink::new(f.clone())),
+            None => bail!("The file backend needs a file"),
+        },
+    };
+    Ok(Some(backend))
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(&format!("{}/{}", CLIENT, env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+// Turns an HTTP failure into an error, Rejected when sending the same again
+// can't work
+fn http_error(e: ureq::Error) -> anyhow::Error {
+    match e {
+        ureq::Error::Status(code, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let message = format!("HTTP {}: {}", code, body.trim());
+            // bad requests; a bad token or key is worth keeping listens for
+            if (400..500).contains(&code) && ![401, 403, 408, 429].contains(&code) {
+                Rejected(message).into()
+            } else {
+                anyhow!(message)
+            }
+        }
+        e => e.into(),
+    }
+}
+
+// ListenBrainz's listen format, also what its importer reads
+pub fn listen_json(track: &Track, listened_at: Option<u64>) -> Value {
+    let mut info = Map::new();
+    info.insert("submission_client".into(), CLIENT.into());
+    info.insert(
+        "submission_client_version".into(),
+        env!("CARGO_PKG_VERSION").into(),
+    );
+    if let Some(d) = track.duration {
+        info.insert("duration_ms".into(), (d * 1000).into());
+    }
+    if let Some(n) = track.track_number {
+        info.insert("tracknumber".into(), n.into());
+    }
+    if let Some(m) = &track.recording_mbid {
+        info.insert("recording_mbid".into(), m.as_str().into());
+    }
+    if let Some(m) = &track.release_mbid {
+        info.insert("release_mbid".into(), m.as_str().into());
+    }
+    let mut metadata = json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+        "additional_info": info,
+    });
+    if let Some(album) = &track.album {
+        metadata["release_name"] = album.as_str().into();
+    }
+    let mut listen = json!({ "track_metadata": metadata });
+    if let Some(at) = listened_at {
+        listen["listened_at"] = at.into();
+    }
+    listen
+}
+
+pub struct ListenBrainz {
+    url: String,
+    token: String,
+    agent: ureq::Agent,
+}
+
+impl ListenBrainz {
+    // url is the API root, e.g. LISTENBRAINZ_URL
+    pub fn new(url: &str, token: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').into(),
+            token: token.into(),
+            agent: agent(),
+        }
+    }
+}
+
+impl Backend for ListenBrainz {
+    fn name(&self) -> &str {
+        "ListenBrainz"
+    }
+
+    fn submit(&self, submission: &Submission) -> Result<()> {
+        let (kind, listen) = match submission {
+            Submission::NowPlaying { track } => ("playing_now", listen_json(track, None)),
+            Submission::Listen { track, listened_at } => {
+                ("single", listen_json(track, Some(*listened_at)))
+            }
+        };
+        let body = json!({ "listen_type": kind, "payload": [listen] });
+        self.agent
+            .post(&format!("{}/1/submit-listens", self.url))
+            .set("Authorization", &format!("Token {}", self.token))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())
+            .map_err(http_error)?;
+        Ok(())
+    }
+}
+
+pub struct LastFm {
+    url: String,
+    api_key: String,
+    secret: String,
+    session: String,
+    agent: ureq::Agent,
+}
+
+impl LastFm {
+    // url is the API endpoint, e.g. LASTFM_URL. The session key comes from
+    // authorizing the API account once, outside the client.
+    pub fn new(url: &str, api_key: &str, secret: &str, session: &str) -> Self {
+        Self {
+            url: url.into(),
+            api_key: api_key.into(),
+            secret: secret.into(),
+            session: session.into(),
+            agent: agent(),
+        }
+    }
+
+    // Every parameter but format, sorted by name and signed with the secret
+    pub fn sign(&self, params: &[(&str, String)]) -> String {
+        let mut sorted: Vec<_> = params.iter().filter(|(k, _)| *k != "format").collect();
+        sorted.sort_by_key(|(k, _)| *k);
+        let mut text: String = sorted.iter().map(|(k, v)| format!("{}{}", k, v)).collect();
+        text.push_str(&self.secret);
+        format!("{:x}", Md5::digest(text.as_bytes()))
+    }
+}
+
+impl Backend for LastFm {
+    fn name(&self) -> &str {
+        "Last.fm"
+    }
+
+    fn submit(&self, submission: &Submission) -> Result<()> {
+        let track = submission.track();
+        let method = match submission {
+            Submission::NowPlaying { .. } => "track.updateNowPlaying",
+            Submission::Listen { .. } => "track.scrobble",
+        };
+        let mut params = vec![
+            ("method", method.to_string()),
+            ("api_key", self.api_key.clone()),
+            ("sk", self.session.clone()),
+            ("artist", track.artist.clone()),
+            ("track", track.title.clone()),
+            ("format", String::from("json")),
+        ];
+        let optional = [
+            ("album", track.album.clone()),
+            ("albumArtist", track.album_artist.clone()),
+            ("trackNumber", track.track_number.map(|n| n.to_string())),
+            ("duration", track.duration.map(|d| d.to_string())),
+            ("mbid", track.recording_mbid.clone()),
+        ];
+        params.extend(optional.into_iter().filter_map(|(k, v)| Some((k, v?))));
+        if let Submission::Listen { listened_at, .. } = submission {
+            params.push(("timestamp", listened_at.to_string()));
+        }
+        let signature = self.sign(&params);
+        params.push(("api_sig", signature));
+
+        let form: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        let body = match self.agent.post(&self.url).send_form(&form) {
+            Ok(response) => response.into_string()?,
+            // errors come with a code in the body, whatever the status
+            Err(ureq::Error::Status(_, response)) => response.into_string()?,
+            Err(e) => return Err(e.into()),
+        };
+        let reply: Value = serde_json::from_str(&body)
+            .map_err(|_| anyhow!("unexpected response: {}", body.trim()))?;
+        let Some(code) = reply["error"].as_u64() else {
+            return Ok(());
+        };
+        let message = format!(
+            "error {}: {}",
+            code,
+            reply["message"].as_str().unwrap_or("")
+        );
+        match code {
+            // service trouble, rate limits and bad sessions pass
+            8 | 9 | 11 | 16 | 29 => bail!(message),
+            _ => Err(Rejected(message).into()),
+        }
+    }
+}
+
+// Appends listens to a file, one ListenBrainz listen per line, for importing
+// elsewhere later. Now playing means nothing there and is skipped.
+pub struct FileSink {
+    file: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(file: PathBuf) -> Self {
+        Self { file }
+    }
+}
+
+impl Backend for FileSink {
+    fn name(&self) -> &str {
+        "File"
+    }
+
+    fn submit(&self, submission: &Submission) -> Result<()> {
+        let Submission::Listen { track, listened_at } = submission else {
+            return Ok(());
+        };
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = listen_json(track, Some(*listened_at)).to_string();
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+use log::{log, Level};
+use mpd::{song::Id, State};
+use serde::{Deserialize, Serialize};
+
+use crate::meta::{join, SongMeta};
+use crate::mpd_util::{
+    idle::Observer,
+    stickers::{unix_time, PlayCounter},
+    MPD,
+};
+
+pub mod backend;
+
+pub use backend::Backend;
+
+// The usual rules: songs over 30 seconds count once half of them, or four
+// minutes, were listened to
+const MIN_LENGTH: Duration = Duration::from_secs(30);
+const SHARE: u8 = 50;
+const ENOUGH: Duration = Duration::from_secs(4 * 60);
+
+// Wait after a failed submission, doubled for every failure in a row
+pub const RETRY: Duration = Duration::from_secs(10);
+const MAX_RETRY: Duration = Duration::from_secs(10 * 60);
+
+// What services are told about a song
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_number: Option<u32>,
+    // in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording_mbid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_mbid: Option<String>,
+}
+
+impl Track {
+    // Songs without an artist and title can't be scrobbled
+    pub fn new(song: &SongMeta, joiner: &str) -> Option<Self> {
+        Some(Self {
+            artist: song.artist(joiner)?,
+            title: song.title.clone()?,
+            album: song.album.clone(),
+            album_artist: join(&song.album_artists, joiner),
+            track_number: song.track.as_ref().and_then(|t| t.number),
+            duration: song.duration().map(|d| d.as_secs()),
+            recording_mbid: song.musicbrainz.recording.clone(),
+            release_mbid: song.musicbrainz.release.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Track {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.artist, self.title)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Submission {
+    NowPlaying {
+        track: Track,
+    },
+    Listen {
+        track: Track,
+        // when listening started, in seconds since the epoch
+        listened_at: u64,
+    },
+}
+
+impl Submission {
+    pub fn track(&self) -> &Track {
+        match self {
+            Submission::NowPlaying { track } | Submission::Listen { track, .. } => track,
+        }
+    }
+}
+
+// A submission the service turned down for good, e.g. as malformed. It is
+// dropped rather than retried.
+#[derive(Debug)]
+pub struct Rejected(pub String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rejected: {}", self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+// Submissions waiting to go out, oldest first. They are kept in a file until
+// the service takes them, so none are lost while it can't be reached or the
+// client isn't running.
+#[derive(Clone)]
+pub struct Queue {
+    file: Option<PathBuf>,
+    shared: Arc<(Mutex<Vec<Submission>>, Condvar)>,
+}
+
+impl Queue {
+    // With what was left in file, if anything
+    pub fn open(file: Option<PathBuf>) -> Self {
+        let pending = match &file {
+            Some(f) => load(f).unwrap_or_else(|e| {
+                log!(Level::Warn, "Failed to read {}: {}", f.display(), e);
+                vec![]
+            }),
+            None => vec![],
+        };
+        Self {
+            file,
+            shared: Arc::new((Mutex::new(pending), Condvar::new())),
+        }
+    }
+
+    // Anything after a now playing makes it out of date, so it goes
+    pub fn push(&self, submission: Submission) {
+        let (pending, wake) = &*self.shared;
+        let mut pending = pending.lock().unwrap();
+        pending.retain(|s| !matches!(s, Submission::NowPlaying { .. }));
+        pending.push(submission);
+        self.save(&pending);
+        wake.notify_all();
+    }
+
+    pub fn pending(&self) -> Vec<Submission> {
+        self.shared.0.lock().unwrap().clone()
+    }
+
+    // Submits in order until one fails, returning how many went out. A
+    // rejected one is dropped and the rest carry on.
+    pub fn flush(&self, backend: &dyn Backend) -> Result<usize> {
+        let mut sent = 0;
+        loop {
+            let Some(next) = self.shared.0.lock().unwrap().first().cloned() else {
+                return Ok(sent);
+            };
+            match backend.submit(&next) {
+                Ok(()) => sent += 1,
+                Err(e) => match e.downcast_ref::<Rejected>() {
+                    Some(r) => log!(
+                        Level::Warn,
+                        "{} dropped {}: {}",
+                        backend.name(),
+                        next.track(),
+                        r.0
+                    ),
+                    None => return Err(e),
+                },
+            }
+            // a newer now playing may have replaced it meanwhile
+            let mut pending = self.shared.0.lock().unwrap();
+            if let Some(i) = pending.iter().position(|s| *s == next) {
+                pending.remove(i);
+                self.save(&pending);
+            }
+        }
+    }
+
+    fn wait(&self) {
+        let (pending, wake) = &*self.shared;
+        let pending = pending.lock().unwrap();
+        drop(wake.wait_while(pending, |p| p.is_empty()).unwrap());
+    }
+
+    fn save(&self, pending: &[Submission]) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Err(e) = save(file, pending) {
+            log!(Level::Warn, "Failed to write {}: {}", file.display(), e);
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<Submission>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Written aside and moved over, so a crash leaves the old queue whole
+fn save(path: &Path, pending: &[Submission]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(pending)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+// Sends what is queued in the background, waiting longer after every failure
+pub fn spawn(queue: Queue, backend: Box<dyn Backend>, retry: Duration) {
+    thread::Builder::new()
+        .name(String::from("scrobble"))
+        .spawn(move || {
+            let mut delay = retry;
+            loop {
+                queue.wait();
+                match queue.flush(&*backend) {
+                    Ok(n) => {
+                        log!(Level::Debug, "Submitted {} to {}", n, backend.name());
+                        delay = retry;
+                    }
+                    Err(e) => {
+                        log!(
+                            Level::Warn,
+                            "{} failed, retrying in {}s: {}",
+                            backend.name(),
+                            delay.as_secs(),
+                            e
+                        );
+                        thread::sleep(delay);
+                        delay = (delay * 2).min(MAX_RETRY);
+                    }
+                }
+            }
+        })
+        .expect("Failed to spawn scrobble thread");
+}
+
+// Queues a now playing when a song starts and a listen once it counts
+pub struct Scrobbler {
+    queue: Queue,
+    joiner: String,
+    counter: PlayCounter,
+    announced: Option<Id>,
+}
+
+impl Scrobbler {
+    pub fn new(queue: Queue, joiner: &str) -> Self {
+        Self {
+            queue,
+            joiner: joiner.into(),
+            counter: PlayCounter::new(SHARE).at_most(ENOUGH),
+            announced: None,
+        }
+    }
+
+    // Fed the playing song and its position, or None once stopped; now is
+    // for measuring, at for the time submitted
+    pub fn listen(
+        &mut self,
+        song: Option<&SongMeta>,
+        elapsed: Duration,
+        now: Instant,
+        at: SystemTime,
+    ) {
+        let Some(song) = song else {
+            self.counter.listen(None, elapsed, now);
+            self.announced = None;
+            return;
+        };
+        let track = Track::new(song, &self.joiner);
+        let id = song.place().map(|p| p.id);
+        if id != self.announced {
+            self.announced = id;
+            if let Some(track) = &track {
+                self.queue.push(Submission::NowPlaying {
+                    track: track.clone(),
+                });
+            }
+        }
+
+        let counted = self.counter.listen(Some(song), elapsed, now);
+        let long = song.duration().is_some_and(|d| d > MIN_LENGTH);
+        if let (Some(_), Some(track), true) = (counted, track, long) {
+            let listened = self.counter.counted().unwrap_or_default();
+            self.queue.push(Submission::Listen {
+                track,
+                listened_at: unix_time(at - listened),
+            });
+        }
+    }
+}
+
+impl Observer for Scrobbler {
+    fn observe(&mut self, mpd: &MPD) {
+        let Some(status) = mpd.status() else {
+            return;
+        };
+        let song = match status.state {
+            State::Pause => return,
+            State::Stop => None,
+            State::Play => mpd.now_playing(),
+        };
+        let elapsed = mpd.elapsed().unwrap_or_default();
+        self.listen(song.as_deref(), elapsed, Instant::now(), SystemTime::now());
+    }
+}
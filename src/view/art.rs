@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use cursive::{
+    event::{Event, EventResult},
+    theme::{Color, ColorStyle, ColorType, PaletteColor},
+    CbSink, Printer, View, XY,
+};
+use image::RgbaImage;
+use unicode_width::UnicodeWidthStr;
+
+use crate::app::App;
+use crate::art::{
+    fit,
+    graphics::{self, Graphics, Placement, Protocol},
+    Cover, Covers,
+};
+use crate::meta::SongMeta;
+
+// The cover as drawn at the current size
+struct Scaled {
+    source: Arc<RgbaImage>,
+    size: XY<usize>,
+    pixels: RgbaImage,
+    origin: XY<usize>, // within the view, to center it
+    cells: XY<usize>,
+    // the escape sequence for sixel and kitty
+    placement: Option<Placement>,
+}
+
+// The current song's cover, following song changes
+pub struct AlbumArt {
+    app: App,
+    covers: Covers,
+    graphics: Graphics,
+    song: Option<Arc<SongMeta>>,
+    cover: Cover,
+    scaled: Option<Scaled>,
+    next_id: u32,
+    size: XY<usize>,
+}
+
+impl AlbumArt {
+    pub fn new(app: App, sink: CbSink, graphics: Graphics) -> Self {
+        let mut s = Self {
+            covers: Covers::new(app.mpd.clone(), sink),
+            app,
+            graphics,
+            song: None,
+            cover: Cover::Missing,
+            scaled: None,
+            next_id: 1,
+            size: XY::zero(),
+        };
+        s.update();
+        s
+    }
+
+    fn update(&mut self) {
+        self.song = self.app.mpd.now_playing();
+        self.cover = match &self.song {
+            Some(song) => self.covers.get(song),
+            None => Cover::Missing,
+        };
+        self.rescale();
+    }
+
+    fn rescale(&mut self) {
+        let Cover::Image(image) = &self.cover else {
+            self.scaled = None;
+            return;
+        };
+        if self
+            .scaled
+            .as_ref()
+            .is_some_and(|s| Arc::ptr_eq(&s.source, image) && s.size == self.size)
+        {
+            return;
+        }
+        if self.size.x == 0 || self.size.y == 0 {
+            self.scaled = None;
+            return;
+        }
+
+        // half blocks have square pixels, two to a cell
+        let cell = match self.graphics.protocol {
+            Protocol::HalfBlock => XY::new(1, 2),
+            _ => graphics::cell_size(),
+        };
+        let pixels = fit(
+            image,
+            (self.size.x * cell.x) as u32,
+            (self.size.y * cell.y) as u32,
+        );
+        let cells = XY::new(
+            (pixels.width() as usize).div_ceil(cell.x),
+            (pixels.height() as usize).div_ceil(cell.y),
+        )
+        .or_min(self.size);
+        let id = self.next_id;
+        self.next_id += 1;
+        let data = match self.graphics.protocol {
+            Protocol::HalfBlock => None,
+            Protocol::Sixel => Some(graphics::sixel(&pixels)),
+            Protocol::Kitty => Some(graphics::kitty(&pixels, id, cells)),
+        };
+        self.scaled = Some(Scaled {
+            source: image.clone(),
+            size: self.size,
+            origin: (self.size - cells) / 2,
+            cells,
+            pixels,
+            placement: data.map(|d| Placement {
+                origin: XY::zero(),
+                id,
+                data: d.into(),
+            }),
+        });
+    }
+
+    fn draw_half_blocks(&self, printer: &Printer, scaled: &Scaled) {
+        let pixel = |x: u32, y: u32| {
+            let [r, g, b, _] = scaled.pixels.get_pixel(x, y).0;
+            ColorType::Color(Color::Rgb(r, g, b))
+        };
+        for row in 0..scaled.cells.y {
+            for x in 0..scaled.cells.x {
+                let (x, y) = (x as u32, row as u32 * 2);
+                let bottom = if y + 1 < scaled.pixels.height() {
+                    pixel(x, y + 1)
+                } else {
+                    ColorType::Palette(PaletteColor::View)
+                };
+                printer.with_color(ColorStyle::new(pixel(x, y), bottom), |p| {
+                    p.print(scaled.origin + (x as usize, row), "▀")
+                });
+            }
+        }
+    }
+}
+
+impl View for AlbumArt {
+    fn draw(&self, printer: &Printer) {
+        let note = match (&self.song, &self.cover, &self.scaled) {
+            (None, _, _) => "Nothing playing",
+            (_, Cover::Loading, _) => "Loading cover",
+            (_, Cover::Missing, _) => "No cover",
+            (_, Cover::Image(_), None) => return,
+            (_, Cover::Image(_), Some(scaled)) => {
+                match &scaled.placement {
+                    None => self.draw_half_blocks(printer, scaled),
+                    Some(p) => self.graphics.place(Placement {
+                        origin: printer.offset + scaled.origin,
+                        ..p.clone()
+                    }),
+                }
+                return;
+            }
+        };
+        let x = printer.size.x.saturating_sub(note.width()) / 2;
+        printer.with_color(ColorStyle::secondary(), |p| {
+            p.print((x, printer.size.y / 2), note)
+        });
+    }
+
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+        self.rescale();
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        if e == Event::Refresh {
+            self.update();
+        }
+        EventResult::Ignored
+    }
+}
@@ -0,0 +1,222 @@
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    theme::ColorStyle,
+    views::Dialog,
+    Printer, Vec2, View, XY,
+};
+use log::{log, Level};
+use mpd::status::AudioFormat;
+use unicode_width::UnicodeWidthStr;
+
+use super::playing::{format_audio, format_time};
+use crate::app::App;
+use crate::clipboard::Clipboard;
+use crate::meta::SongMeta;
+
+// Labels wider than this are cut, so values keep most of the width
+const MAX_LABEL: usize = 24;
+// rows below the fields
+const FOOTER: usize = 2;
+
+// Everything known about a song as label and value: the file, every tag in
+// the order MPD sent them, then technical details, queue place and stickers
+pub fn fields(app: &App, song: &SongMeta) -> Vec<(String, String)> {
+    let s = &song.song;
+    let mut out = vec![(String::from("File"), s.file.clone())];
+    if let Some(path) = app.config.mpd.local_path(&s.file) {
+        out.push(("Path".into(), path.display().to_string()));
+    }
+    if let Some(name) = &s.name {
+        out.push(("Name".into(), name.clone()));
+    }
+    out.extend(s.tags.iter().filter(|(k, _)| k != "Format").cloned());
+
+    if let Some(d) = s.duration {
+        out.push((
+            "Duration".into(),
+            format!("{} ({:.3}s)", format_time(d), d.as_secs_f64()),
+        ));
+    }
+    if let Some((_, format)) = s.tags.iter().find(|(k, _)| k == "Format") {
+        let pretty = match format.parse::<AudioFormat>() {
+            Ok(a) => format!("{} ({})", format_audio(a), format),
+            Err(_) => format.clone(),
+        };
+        out.push(("Format".into(), pretty));
+    }
+    if let Some(modified) = &s.last_mod {
+        out.push(("Last-Modified".into(), modified.clone()));
+    }
+    if let Some(range) = &s.range {
+        out.push(("Range".into(), range.to_string()));
+    }
+    if let Some(place) = s.place {
+        out.push(("Queue position".into(), (place.pos + 1).to_string()));
+        out.push(("Queue id".into(), place.id.to_string()));
+        out.push(("Priority".into(), place.prio.to_string()));
+    }
+
+    if !s.file.contains("://") {
+        match app.mpd.with_raw(|c| c.stickers(&s.file)) {
+            Ok(stickers) => out.extend(
+                stickers
+                    .into_iter()
+                    .map(|(name, value)| (format!("Sticker {}", name), value)),
+            ),
+            Err(e) => log!(Level::Debug, "No stickers for {}: {}", s.file, e),
+        }
+    }
+    out
+}
+
+// Opens the info dialog over whatever is showing
+pub fn open(app: &App, song: &SongMeta) -> EventResult {
+    let dialog = Dialog::around(SongInfo::new(app.clipboard.clone(), fields(app, song)))
+        .title(song.display_title());
+    EventResult::with_cb_once(move |s| s.add_layer(dialog))
+}
+
+// A scrollable list of fields. The selected value can be copied.
+pub struct SongInfo {
+    clipboard: Clipboard,
+    fields: Vec<(String, String)>,
+    selected: usize,
+    offset: usize,
+    copied: Option<String>,
+    size: XY<usize>,
+}
+
+impl SongInfo {
+    pub fn new(clipboard: Clipboard, fields: Vec<(String, String)>) -> Self {
+        Self {
+            clipboard,
+            fields,
+            selected: 0,
+            offset: 0,
+            copied: None,
+            size: XY::zero(),
+        }
+    }
+
+    fn label_width(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|(k, _)| k.width())
+            .max()
+            .unwrap_or(0)
+            .min(MAX_LABEL)
+    }
+
+    fn list_height(&self) -> usize {
+        self.size.y.saturating_sub(FOOTER)
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(self.fields.len().saturating_sub(1));
+        let height = self.list_height();
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+    }
+
+    fn copy(&mut self) {
+        if let Some((label, value)) = self.fields.get(self.selected) {
+            self.clipboard.copy(value);
+            self.copied = Some(label.clone());
+        }
+    }
+}
+
+impl View for SongInfo {
+    fn draw(&self, printer: &Printer) {
+        let label_width = self.label_width();
+        for (y, (label, value)) in self
+            .fields
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(self.list_height())
+            .map(|(i, f)| (i - self.offset, f))
+        {
+            let selected = y + self.offset == self.selected;
+            let label: String = label.chars().take(label_width).collect();
+            printer.with_color(ColorStyle::secondary(), |p| p.print((0, y), &label));
+            let style = if selected {
+                ColorStyle::highlight()
+            } else {
+                ColorStyle::primary()
+            };
+            printer.with_color(style, |p| p.print((label_width + 2, y), value));
+        }
+
+        let footer = match &self.copied {
+            Some(label) => format!("Copied {}", label),
+            None => String::from("y copy · Esc close"),
+        };
+        let y = printer.size.y.saturating_sub(1);
+        printer.with_color(ColorStyle::secondary(), |p| p.print((0, y), &footer));
+    }
+
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+        self.move_by(0);
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        let widest = self
+            .fields
+            .iter()
+            .map(|(_, v)| v.width())
+            .max()
+            .unwrap_or(0);
+        XY::new(
+            (self.label_width() + 2 + widest).min(constraint.x),
+            (self.fields.len() + FOOTER).min(constraint.y),
+        )
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        let page = self.list_height().max(1) as isize;
+        match e {
+            Event::Key(Key::Up) | Event::Char('k') => self.move_by(-1),
+            Event::Key(Key::Down) | Event::Char('j') => self.move_by(1),
+            Event::Key(Key::PageUp) => self.move_by(-page),
+            Event::Key(Key::PageDown) => self.move_by(page),
+            Event::Key(Key::Home) | Event::Char('g') => self.move_by(-(self.fields.len() as isize)),
+            Event::Key(Key::End) | Event::Char('G') => self.move_by(self.fields.len() as isize),
+            Event::Char('y') | Event::Char('c') => self.copy(),
+            Event::Key(Key::Esc) | Event::Char('q') | Event::Char('i') => {
+                return EventResult::with_cb(|s| {
+                    s.pop_layer();
+                })
+            }
+            Event::Mouse {
+                offset,
+                position,
+                event,
+            } => {
+                let Some(pos) = position.checked_sub(offset) else {
+                    return EventResult::Ignored;
+                };
+                match event {
+                    MouseEvent::WheelUp => self.move_by(-3),
+                    MouseEvent::WheelDown => self.move_by(3),
+                    MouseEvent::Press(MouseButton::Left) if pos.y < self.list_height() => {
+                        let row = self.offset + pos.y;
+                        if row < self.fields.len() {
+                            self.selected = row;
+                        }
+                    }
+                    _ => return EventResult::Ignored,
+                }
+            }
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
@@ -0,0 +1,147 @@
+use cursive::{Printer, Vec2, XY};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    // panes side by side
+    Horizontal,
+    // panes stacked top to bottom
+    Vertical,
+}
+
+// A tree of panes. Leaves name a registered view in config, and are resolved
+// to indices into Root's views before use.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Layout<T = String> {
+    Pane(T),
+    Split {
+        split: Split,
+        panes: Vec<Layout<T>>,
+        #[serde(default)]
+        ratios: Vec<f64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub origin: Vec2,
+    pub size: Vec2,
+}
+
+impl Area {
+    pub fn new(origin: Vec2, size: Vec2) -> Self {
+        Self { origin, size }
+    }
+
+    pub fn contains(&self, pos: Vec2) -> bool {
+        pos.fits_in_rect(self.origin, self.size)
+    }
+}
+
+// One-cell line drawn between two panes
+#[derive(Debug, Clone, Copy)]
+pub struct Separator {
+    pub start: Vec2,
+    pub split: Split,
+    pub len: usize,
+}
+
+impl Separator {
+    pub fn draw(&self, printer: &Printer) {
+        match self.split {
+            Split::Horizontal => printer.print_vline(self.start, self.len, "│"),
+            Split::Vertical => printer.print_hline(self.start, self.len, "─"),
+        }
+    }
+}
+
+impl<T> Layout<T> {
+    // Keeps the panes for which f returns Some, dropping splits left empty
+    pub fn filter_map<U>(&self, f: &impl Fn(&T) -> Option<U>) -> Option<Layout<U>> {
+        match self {
+            Layout::Pane(t) => f(t).map(Layout::Pane),
+            Layout::Split {
+                split,
+                panes,
+                ratios,
+            } => {
+                let mut kept = vec![];
+                let mut kept_ratios = vec![];
+                for (i, p) in panes.iter().enumerate() {
+                    if let Some(p) = p.filter_map(f) {
+                        kept.push(p);
+                        kept_ratios.push(ratios.get(i).copied().unwrap_or(1.0));
+                    }
+                }
+                match kept.len() {
+                    0 => None,
+                    1 => kept.pop(),
+                    _ => Some(Layout::Split {
+                        split: *split,
+                        panes: kept,
+                        ratios: kept_ratios,
+                    }),
+                }
+            }
+        }
+    }
+
+    pub fn leaves(&self) -> Vec<&T> {
+        match self {
+            Layout::Pane(t) => vec![t],
+            Layout::Split { panes, .. } => panes.iter().flat_map(|p| p.leaves()).collect(),
+        }
+    }
+
+    // Splits area between the panes, in the same order as leaves()
+    pub fn arrange(&self, area: Area, panes: &mut Vec<Area>, separators: &mut Vec<Separator>) {
+        let Layout::Split {
+            split,
+            panes: children,
+            ratios,
+        } = self
+        else {
+            panes.push(area);
+            return;
+        };
+        let n = children.len();
+        let (along, across) = match split {
+            Split::Horizontal => (area.size.x, area.size.y),
+            Split::Vertical => (area.size.y, area.size.x),
+        };
+        let ratios: Vec<f64> = (0..n)
+            .map(|i| ratios.get(i).copied().unwrap_or(1.0).max(0.0))
+            .collect();
+        let sum: f64 = ratios.iter().sum();
+        let avail = along.saturating_sub(n.saturating_sub(1));
+
+        let mut pos = 0;
+        for (i, child) in children.iter().enumerate() {
+            let len = if i + 1 == n {
+                avail.saturating_sub(pos)
+            } else if sum > 0.0 {
+                ((avail as f64 * ratios[i] / sum) as usize).min(avail.saturating_sub(pos))
+            } else {
+                avail / n
+            };
+            let (origin, size) = match split {
+                Split::Horizontal => (area.origin + (pos + i, 0), XY::new(len, across)),
+                Split::Vertical => (area.origin + (0, pos + i), XY::new(across, len)),
+            };
+            child.arrange(Area::new(origin, size), panes, separators);
+            pos += len;
+            if i + 1 < n && pos + i < along {
+                separators.push(Separator {
+                    start: match split {
+                        Split::Horizontal => area.origin + (pos + i, 0),
+                        Split::Vertical => area.origin + (0, pos + i),
+                    },
+                    split: *split,
+                    len: across,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use std::sync::Arc;
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    theme::{BaseColor, ColorStyle, Effect},
+    Printer, View, XY,
+};
+use log::{log, Level};
+use unicode_width::UnicodeWidthStr;
+
+use super::info;
+use super::playing::{format_time, offline_note};
+use super::playlist::rate;
+use crate::app::App;
+use crate::library::{Filter, Library};
+
+// rows above the lists
+const ROW_OFFSET: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Artists,
+    Songs,
+}
+
+// A scrollable list selection
+#[derive(Default)]
+pub(super) struct Cursor {
+    pub(super) selected: usize,
+    pub(super) offset: usize,
+}
+
+impl Cursor {
+    pub(super) fn move_by(&mut self, delta: isize, len: usize) {
+        self.selected = self
+            .selected
+            .saturating_add_signed(delta)
+            .min(len.saturating_sub(1));
+    }
+
+    pub(super) fn scroll_into_view(&mut self, height: usize) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+    }
+}
+
+// Browses the database by artist, with a filter over all tag values
+pub struct LibraryView {
+    app: App,
+    library: Option<Arc<Library>>,
+    filter_text: String,
+    filtering: bool,
+    artists: Vec<String>,
+    songs: Vec<usize>, // indices into library.songs() for the selected artist
+    column: Column,
+    artist_cursor: Cursor,
+    song_cursor: Cursor,
+    size: XY<usize>,
+}
+
+impl LibraryView {
+    pub fn new(app: App) -> Self {
+        Self {
+            app,
+            library: None,
+            filter_text: String::new(),
+            filtering: false,
+            artists: vec![],
+            songs: vec![],
+            column: Column::Artists,
+            artist_cursor: Cursor::default(),
+            song_cursor: Cursor::default(),
+            size: XY::zero(),
+        }
+    }
+
+    fn artist_width(&self) -> usize {
+        self.size.x / 3
+    }
+
+    fn list_height(&self) -> usize {
+        self.size.y.saturating_sub(ROW_OFFSET)
+    }
+
+    // Picks up a new library from the cache
+    fn sync(&mut self) {
+        let library = self.app.mpd.library();
+        let changed = match (&library, &self.library) {
+            (Some(new), Some(old)) => !Arc::ptr_eq(new, old),
+            (new, old) => new.is_some() != old.is_some(),
+        };
+        if changed {
+            self.library = library;
+            self.refilter();
+        }
+    }
+
+    fn filter(&self) -> Filter {
+        Filter::parse(&self.filter_text).with_ratings(self.app.mpd.ratings())
+    }
+
+    fn refilter(&mut self) {
+        let Some(library) = &self.library else {
+            self.artists.clear();
+            self.songs.clear();
+            return;
+        };
+        let filter = self.filter();
+        self.artists = library
+            .filtered_artists(&filter)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        self.artist_cursor.move_by(0, self.artists.len());
+        self.update_songs();
+    }
+
+    fn update_songs(&mut self) {
+        self.songs.clear();
+        self.song_cursor = Cursor::default();
+        let (Some(library), Some(artist)) =
+            (&self.library, self.artists.get(self.artist_cursor.selected))
+        else {
+            return;
+        };
+        let filter = self.filter();
+        let songs = library.songs();
+        self.songs = library
+            .artist_song_ids(artist)
+            .iter()
+            .copied()
+            .filter(|i| filter.matches(&songs[*i]))
+            .collect();
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let height = self.list_height();
+        match self.column {
+            Column::Artists => {
+                let before = self.artist_cursor.selected;
+                self.artist_cursor.move_by(delta, self.artists.len());
+                self.artist_cursor.scroll_into_view(height);
+                if before != self.artist_cursor.selected {
+                    self.update_songs();
+                }
+            }
+            Column::Songs => {
+                self.song_cursor.move_by(delta, self.songs.len());
+                self.song_cursor.scroll_into_view(height);
+            }
+        }
+    }
+
+    // Adds the selected artist's (filtered) songs or the selected song
+    fn add_selected(&mut self) {
+        let Some(library) = &self.library else {
+            return;
+        };
+        let songs = library.songs();
+        let files: Vec<&str> = match self.column {
+            Column::Artists => self.songs.iter().map(|i| songs[*i].file()).collect(),
+            Column::Songs => self
+                .songs
+                .get(self.song_cursor.selected)
+                .map(|i| songs[*i].file())
+                .into_iter()
+                .collect(),
+        };
+        match self.app.mpd.add(&files) {
+            Ok(()) if !self.app.mpd.online() => log!(
+                Level::Info,
+                "Offline: {} songs will be added when MPD is back",
+                files.len()
+            ),
+            Ok(()) => log!(Level::Info, "Added {} songs", files.len()),
+            Err(e) => log!(Level::Warn, "Failed to add songs: {}", e),
+        }
+    }
+
+    // Info on the selected song in the songs column
+    fn show_info(&self) -> EventResult {
+        let (Some(library), Column::Songs) = (&self.library, self.column) else {
+            return EventResult::Ignored;
+        };
+        match self.songs.get(self.song_cursor.selected) {
+            Some(i) => info::open(&self.app, &library.songs()[*i]),
+            None => EventResult::Ignored,
+        }
+    }
+
+    fn rate(&self, delta: i8) -> EventResult {
+        let (Some(library), Column::Songs) = (&self.library, self.column) else {
+            return EventResult::Ignored;
+        };
+        match self.songs.get(self.song_cursor.selected) {
+            Some(i) => {
+                rate(&self.app, library.songs()[*i].file(), delta);
+                EventResult::Consumed(None)
+            }
+            None => EventResult::Ignored,
+        }
+    }
+
+    fn filter_event(&mut self, e: Event) -> EventResult {
+        match e {
+            Event::Char(c) => {
+                self.filter_text.push(c);
+                self.refilter();
+            }
+            Event::Key(Key::Backspace) | Event::Key(Key::Del) => {
+                self.filter_text.pop();
+                self.refilter();
+            }
+            Event::Key(Key::Esc) => {
+                self.filtering = false;
+                self.filter_text.clear();
+                self.refilter();
+            }
+            Event::Key(Key::Enter) => self.filtering = false,
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn click(&mut self, pos: XY<usize>) -> EventResult {
+        if pos.y < ROW_OFFSET {
+            return EventResult::Ignored;
+        }
+        let row = pos.y - ROW_OFFSET;
+        if pos.x < self.artist_width() {
+            self.column = Column::Artists;
+            let target = self.artist_cursor.offset + row;
+            if target < self.artists.len() {
+                self.artist_cursor.selected = target;
+                self.update_songs();
+            }
+        } else {
+            self.column = Column::Songs;
+            let target = self.song_cursor.offset + row;
+            if target < self.songs.len() {
+                self.song_cursor.selected = target;
+            }
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn draw_list(
+        &self,
+        printer: &Printer,
+        rows: impl Iterator<Item = String>,
+        cursor: &Cursor,
+        active: bool,
+    ) {
+        for (y, row) in rows
+            .skip(cursor.offset)
+            .take(self.list_height())
+            .enumerate()
+        {
+            let i = y + cursor.offset;
+            let style = if i == cursor.selected && active {
+                ColorStyle::highlight()
+            } else {
+                ColorStyle::primary()
+            };
+            printer.with_color(style, |p| {
+                if i == cursor.selected && !active {
+                    p.with_effect(Effect::Reverse, |p| p.print((0, y + ROW_OFFSET), &row));
+                } else {
+                    p.print((0, y + ROW_OFFSET), &row);
+                }
+            });
+        }
+    }
+}
+
+impl View for LibraryView {
+    fn draw(&self, printer: &Printer) {
+        let width = self.artist_width();
+
+        let header = if self.filtering || !self.filter_text.is_empty() {
+            format!("/{}", self.filter_text)
+        } else {
+            match &self.library {
+                Some(l) => format!("{} artists, {} songs", self.artists.len(), l.len()),
+                None => String::from("Library unavailable"),
+            }
+        };
+        printer.with_color(ColorStyle::secondary(), |p| p.print((0, 0), &header));
+        if !self.app.mpd.online() {
+            let note = offline_note(&self.app.mpd);
+            let x = printer.size.x.saturating_sub(note.width());
+            printer.with_color(ColorStyle::from(BaseColor::Red.light()), |p| {
+                p.print((x, 0), &note)
+            });
+        }
+
+        self.draw_list(
+            &printer.cropped((width, printer.size.y)),
+            self.artists.iter().cloned(),
+            &self.artist_cursor,
+            self.column == Column::Artists,
+        );
+        printer.print_vline((width, ROW_OFFSET), self.list_height(), "│");
+
+        let Some(library) = &self.library else {
+            return;
+        };
+        let songs = library.songs();
+        let song_width = printer.size.x.saturating_sub(width + 2);
+        let rows = self.songs.iter().map(|i| {
+            let s = &songs[*i];
+            let time = s.duration().map(format_time).unwrap_or_default();
+            let left = format!(
+                "{:>3} {} · {}",
+                s.track.as_ref().map(|t| t.to_string()).unwrap_or_default(),
+                s.display_title(),
+                s.album.as_deref().unwrap_or("Unknown Album"),
+            );
+            let pad = song_width.saturating_sub(left.width() + time.width());
+            format!("{}{}{}", left, " ".repeat(pad), time)
+        });
+        self.draw_list(
+            &printer
+                .offset((width + 2, 0))
+                .cropped((song_width, printer.size.y)),
+            rows,
+            &self.song_cursor,
+            self.column == Column::Songs,
+        );
+    }
+
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+        self.sync();
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        if self.filtering {
+            return self.filter_event(e);
+        }
+        match e {
+            Event::Char('/') => self.filtering = true,
+            Event::Key(Key::Esc) if !self.filter_text.is_empty() => {
+                self.filter_text.clear();
+                self.refilter();
+            }
+            Event::Key(Key::Up) | Event::Char('k') => self.move_selection(-1),
+            Event::Key(Key::Down) | Event::Char('j') => self.move_selection(1),
+            Event::Key(Key::PageUp) => self.move_selection(-(self.list_height() as isize)),
+            Event::Key(Key::PageDown) => self.move_selection(self.list_height() as isize),
+            Event::Key(Key::Left) | Event::Char('h') => self.column = Column::Artists,
+            Event::Key(Key::Right) | Event::Char('l') => self.column = Column::Songs,
+            Event::Key(Key::Enter) | Event::Char('a') => self.add_selected(),
+            Event::Char('i') => return self.show_info(),
+            Event::Char('+') | Event::Char('=') => return self.rate(1),
+            Event::Char('-') => return self.rate(-1),
+            Event::Mouse {
+                offset,
+                position,
+                event,
+            } => {
+                let Some(pos) = position.checked_sub(offset) else {
+                    return EventResult::Ignored;
+                };
+                match event {
+                    MouseEvent::Press(MouseButton::Left) => return self.click(pos),
+                    MouseEvent::WheelUp => self.move_selection(-3),
+                    MouseEvent::WheelDown => self.move_selection(3),
+                    _ => return EventResult::Ignored,
+                }
+            }
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
@@ -0,0 +1,435 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    theme::{ColorStyle, Effect},
+    CbSink, Printer, View, XY,
+};
+use log::{log, Level};
+use unicode_width::UnicodeWidthStr;
+
+use crate::app::App;
+use crate::lyrics::{self, Lyrics, Source};
+use crate::meta::SongMeta;
+
+// rows above the first line
+const ROW_OFFSET: usize = 2;
+const SCROLL_STEP: usize = 3;
+// how far [ and ] move a stamp
+const NUDGE_MS: u64 = 100;
+
+// Timestamping lines while the song plays
+struct Editor {
+    cursor: usize,
+    changed: bool,
+}
+
+// Where lookups leave what they found. Finding lyrics reads files and asks
+// MPD for the song's tags, so it is done in the background like covers, and
+// the UI is redrawn through the sink once they are in.
+#[derive(Default)]
+struct Found {
+    // the latest lookup; older ones are dropped when they finish
+    wanted: u64,
+    lyrics: Option<Option<Lyrics>>,
+}
+
+pub struct LyricsView {
+    app: App,
+    sink: CbSink,
+    found: Arc<Mutex<Found>>,
+    song: Option<Arc<SongMeta>>,
+    loading: bool,
+    lyrics: Option<Lyrics>,
+    current: Option<usize>, // line being sung, for synced lyrics
+    offset: usize,
+    follow: bool, // keep the current line centered
+    editor: Option<Editor>,
+    message: Option<String>,
+    size: XY<usize>,
+}
+
+impl LyricsView {
+    // Nothing is looked up until the view is first shown
+    pub fn new(app: App, sink: CbSink) -> Self {
+        Self {
+            app,
+            sink,
+            found: Arc::new(Mutex::new(Found::default())),
+            song: None,
+            loading: false,
+            lyrics: None,
+            current: None,
+            offset: 0,
+            follow: true,
+            editor: None,
+            message: None,
+            size: XY::zero(),
+        }
+    }
+
+    // Whether the song shown is still the one playing
+    fn on_song(&self, song: Option<&Arc<SongMeta>>) -> bool {
+        song.map(|s| s.file()) == self.song.as_ref().map(|s| s.file())
+    }
+
+    fn update(&mut self) {
+        let song = self.app.mpd.now_playing();
+        if !self.on_song(song.as_ref()) {
+            // unsaved stamps stay with their song until saved or left
+            if self.editor.as_ref().is_some_and(|e| e.changed) {
+                self.current = None;
+                return;
+            }
+            self.look_up(song.clone());
+            self.song = song;
+            self.lyrics = None;
+            self.editor = None;
+            self.message = None;
+            self.offset = 0;
+            self.follow = true;
+        }
+        if self.loading {
+            if let Some(lyrics) = self.found.lock().unwrap().lyrics.take() {
+                self.lyrics = lyrics;
+                self.loading = false;
+            }
+        }
+
+        let current = match (&self.lyrics, self.app.mpd.elapsed()) {
+            (Some(l), Some(elapsed)) => l.current(elapsed),
+            _ => None,
+        };
+        if current != self.current && self.follow && self.editor.is_none() {
+            if let Some(c) = current {
+                self.center(c);
+            }
+        }
+        self.current = current;
+    }
+
+    // Starts looking for the song's lyrics, dropping any lookup under way
+    fn look_up(&mut self, song: Option<Arc<SongMeta>>) {
+        let wanted = {
+            let mut found = self.found.lock().unwrap();
+            found.wanted += 1;
+            found.lyrics = None;
+            found.wanted
+        };
+        self.loading = song.is_some();
+        let Some(song) = song else {
+            return;
+        };
+        let app = self.app.clone();
+        let found = self.found.clone();
+        let sink = self.sink.clone();
+        thread::Builder::new()
+            .name(String::from("lyrics"))
+            .spawn(move || {
+                let lyrics = lyrics::find(&app.config, &app.mpd, &song);
+                let mut found = found.lock().unwrap();
+                if found.wanted == wanted {
+                    found.lyrics = Some(lyrics);
+                    drop(found);
+                    let _ = sink.send(Box::new(|s| s.on_event(Event::Refresh)));
+                }
+            })
+            .expect("Failed to spawn lyrics thread");
+    }
+
+    fn len(&self) -> usize {
+        self.lyrics.as_ref().map_or(0, |l| l.lines.len())
+    }
+
+    fn visible_rows(&self) -> usize {
+        self.size.y.saturating_sub(ROW_OFFSET)
+    }
+
+    fn max_offset(&self) -> usize {
+        self.len().saturating_sub(self.visible_rows())
+    }
+
+    fn center(&mut self, line: usize) {
+        self.offset = line
+            .saturating_sub(self.visible_rows() / 2)
+            .min(self.max_offset());
+    }
+
+    fn scroll(&mut self, by: isize) {
+        self.follow = false;
+        self.offset = self.offset.saturating_add_signed(by).min(self.max_offset());
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        let len = self.len();
+        let rows = self.visible_rows();
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        editor.cursor = editor
+            .cursor
+            .saturating_add_signed(by)
+            .min(len.saturating_sub(1));
+        // keep it on screen
+        if editor.cursor < self.offset {
+            self.offset = editor.cursor;
+        } else if rows > 0 && editor.cursor >= self.offset + rows {
+            self.offset = editor.cursor + 1 - rows;
+        }
+    }
+
+    // Changes the stamp on the line under the cursor
+    fn stamp(&mut self, f: impl FnOnce(Option<Duration>) -> Option<Duration>) {
+        let (Some(lyrics), Some(editor)) = (&mut self.lyrics, &mut self.editor) else {
+            return;
+        };
+        if let Some(line) = lyrics.lines.get_mut(editor.cursor) {
+            line.time = f(line.time);
+            editor.changed = true;
+        }
+    }
+
+    fn save(&mut self) {
+        let (Some(song), Some(lyrics)) = (&self.song, &mut self.lyrics) else {
+            return;
+        };
+        self.message = Some(match lyrics::save(&self.app.config, song, lyrics) {
+            Ok(path) => {
+                lyrics.source = Source::File(path.clone());
+                if let Some(e) = &mut self.editor {
+                    e.changed = false;
+                }
+                log!(Level::Info, "Saved lyrics to {}", path.display());
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                format!("Saved {}", name)
+            }
+            Err(e) => {
+                log!(Level::Warn, "Failed to save lyrics: {}", e);
+                format!("Not saved: {}", e)
+            }
+        });
+    }
+
+    fn editor_event(&mut self, e: Event) -> EventResult {
+        match e {
+            Event::Key(Key::Up) | Event::Char('k') => self.move_cursor(-1),
+            Event::Key(Key::Down) | Event::Char('j') => self.move_cursor(1),
+            Event::Key(Key::PageUp) => self.move_cursor(-(self.visible_rows() as isize)),
+            Event::Key(Key::PageDown) => self.move_cursor(self.visible_rows() as isize),
+            Event::Key(Key::Enter) | Event::Char(' ') => {
+                // the time is only meaningful for the song being edited
+                let playing = self.app.mpd.now_playing();
+                let elapsed = self.app.mpd.elapsed();
+                let Some(elapsed) = elapsed.filter(|_| self.on_song(playing.as_ref())) else {
+                    return EventResult::Consumed(None);
+                };
+                self.stamp(|_| Some(elapsed));
+                self.move_cursor(1);
+            }
+            Event::Key(Key::Backspace) | Event::Key(Key::Del) => self.stamp(|_| None),
+            Event::Char('[') => {
+                self.stamp(|t| t.map(|t| t.saturating_sub(Duration::from_millis(NUDGE_MS))))
+            }
+            Event::Char(']') => self.stamp(|t| t.map(|t| t + Duration::from_millis(NUDGE_MS))),
+            Event::Char('s') => self.save(),
+            Event::Key(Key::Esc) => {
+                self.editor = None;
+                self.follow = true;
+                // catch up with the song if it moved on
+                self.update();
+            }
+            Event::Mouse { .. } => return self.mouse_event(e),
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn mouse_event(&mut self, e: Event) -> EventResult {
+        let Event::Mouse {
+            offset,
+            position,
+            event,
+        } = e
+        else {
+            return EventResult::Ignored;
+        };
+        let Some(pos) = position.checked_sub(offset) else {
+            return EventResult::Ignored;
+        };
+        match event {
+            MouseEvent::WheelUp => self.scroll(-(SCROLL_STEP as isize)),
+            MouseEvent::WheelDown => self.scroll(SCROLL_STEP as isize),
+            MouseEvent::Press(MouseButton::Left) if pos.y >= ROW_OFFSET => {
+                let line = self.offset + pos.y - ROW_OFFSET;
+                if line >= self.len() {
+                    return EventResult::Ignored;
+                }
+                match &mut self.editor {
+                    Some(editor) => editor.cursor = line,
+                    // jump to a stamped line
+                    None => {
+                        let time = self.lyrics.as_ref().and_then(|l| l.lines[line].time);
+                        if let Some(t) = time {
+                            if let Err(e) = self.app.mpd.seek(t) {
+                                log!(Level::Warn, "Failed to seek: {}", e);
+                            }
+                            self.follow = true;
+                        }
+                    }
+                }
+            }
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn header(&self) -> (String, String) {
+        let Some(song) = &self.song else {
+            return (String::new(), String::new());
+        };
+        let title = match song.artist(&self.app.config.tags.joiner) {
+            Some(a) => format!("{} - {}", a, song.display_title()),
+            None => song.display_title(),
+        };
+        let note = match (&self.message, &self.editor, &self.lyrics) {
+            (Some(m), _, _) => m.clone(),
+            (_, Some(e), _) if e.changed && !self.on_song(self.app.mpd.now_playing().as_ref()) => {
+                String::from("[+] Song changed: s saves, Esc discards")
+            }
+            (_, Some(e), _) => format!(
+                "{}Enter stamps, Del clears, [ ] nudge, s saves, Esc leaves",
+                if e.changed { "[+] " } else { "" }
+            ),
+            (_, None, Some(l)) => {
+                let kind = if l.synced() { "synced" } else { "plain" };
+                match &l.source {
+                    Source::Embedded => format!("{}, from tags", kind),
+                    Source::File(p) => format!(
+                        "{}, {}",
+                        kind,
+                        p.file_name().unwrap_or_default().to_string_lossy()
+                    ),
+                }
+            }
+            (_, None, None) => String::new(),
+        };
+        (title, note)
+    }
+}
+
+impl View for LyricsView {
+    fn draw(&self, printer: &Printer) {
+        let (title, note) = self.header();
+        printer.with_color(ColorStyle::secondary(), |p| {
+            p.print((0, 0), &title);
+            let x = printer.size.x.saturating_sub(note.width());
+            p.print((x.max(title.width() + 1), 0), &note);
+        });
+
+        let Some(lyrics) = &self.lyrics else {
+            let text = if self.loading {
+                "Looking for lyrics"
+            } else if self.song.is_some() {
+                "No lyrics"
+            } else {
+                "Nothing playing"
+            };
+            let x = printer.size.x.saturating_sub(text.width()) / 2;
+            printer.with_color(ColorStyle::secondary(), |p| {
+                p.print((x, printer.size.y / 2), text)
+            });
+            return;
+        };
+
+        let rows = lyrics
+            .lines
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(self.visible_rows());
+        for (y, (i, line)) in rows.enumerate() {
+            let y = y + ROW_OFFSET;
+            let sung = self.current == Some(i);
+            let style = if sung || !lyrics.synced() {
+                ColorStyle::primary()
+            } else {
+                ColorStyle::secondary()
+            };
+            match &self.editor {
+                Some(editor) => {
+                    let stamp = match line.time {
+                        Some(t) => lyrics::format_time(t),
+                        None => String::from("[--:--.--]"),
+                    };
+                    let text = format!("{} {}", stamp, line.text);
+                    printer.with_color(style, |p| {
+                        p.with_effects(
+                            match (editor.cursor == i, sung) {
+                                (true, _) => Effect::Reverse.into(),
+                                (false, true) => Effect::Bold.into(),
+                                _ => Default::default(),
+                            },
+                            |p| p.print((0, y), &text),
+                        )
+                    });
+                }
+                None => {
+                    let x = printer.size.x.saturating_sub(line.text.width()) / 2;
+                    printer.with_color(style, |p| {
+                        if sung {
+                            p.with_effect(Effect::Bold, |p| p.print((x, y), &line.text));
+                        } else {
+                            p.print((x, y), &line.text);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+        match (self.current, &self.editor) {
+            (Some(c), None) if self.follow => self.center(c),
+            _ => self.offset = self.offset.min(self.max_offset()),
+        }
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        if e == Event::Refresh {
+            self.update();
+            return EventResult::Ignored;
+        }
+        if self.editor.is_some() {
+            return self.editor_event(e);
+        }
+        match e {
+            Event::Char('e') if self.lyrics.is_some() => {
+                self.editor = Some(Editor {
+                    cursor: self.current.unwrap_or(self.offset),
+                    changed: false,
+                });
+                self.message = None;
+                self.move_cursor(0);
+            }
+            Event::Key(Key::Up) | Event::Char('k') => self.scroll(-1),
+            Event::Key(Key::Down) | Event::Char('j') => self.scroll(1),
+            Event::Key(Key::PageUp) => self.scroll(-(self.visible_rows() as isize)),
+            Event::Key(Key::PageDown) => self.scroll(self.visible_rows() as isize),
+            // back to following the song
+            Event::Key(Key::Esc) if !self.follow => {
+                self.follow = true;
+                if let Some(c) = self.current {
+                    self.center(c);
+                }
+            }
+            Event::Mouse { .. } => return self.mouse_event(e),
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
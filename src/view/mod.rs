@@ -1,4 +1,11 @@
+pub mod art;
+pub mod info;
+pub mod layout;
+pub mod library;
+pub mod lyrics;
 pub mod playing;
 pub mod playlist;
 pub mod root;
+pub mod stats;
 pub mod titlebar;
+pub mod visualizer;
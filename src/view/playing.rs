@@ -1,40 +1,49 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use cursive::{
-    event::{Event, EventResult},
+    event::{Event, EventResult, MouseButton, MouseEvent},
+    theme::{BaseColor, ColorStyle, ColorType, PaletteColor},
+    utils::markup::ansi,
     Printer, View, XY,
 };
 use log::{log, Level};
-use mpd::{Song, State};
+use mpd::{status::AudioFormat, State, Status};
+use unicode_width::UnicodeWidthStr;
 
+use crate::app::App;
+use crate::config::TimeMode;
+use crate::meta::SongMeta;
 use crate::mpd_util::MPD;
 
-#[derive(Debug)]
+// Left-aligned partial blocks, indexed by eighths filled
+const EIGHTHS: [&str; 8] = [" ", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
 pub struct Playing {
-    song: Option<Song>,
+    app: App,
+    song: Option<Arc<SongMeta>>,
     time: Option<(Duration, Duration)>,
     message: Option<String>,
-    tick_count: usize,
+    time_mode: TimeMode,
+    size: XY<usize>,
 }
 
 impl Playing {
-    pub fn new() -> Self {
+    pub fn new(app: App) -> Self {
         let mut s = Self {
+            time_mode: app.config.status.time_mode,
+            app,
             song: None,
             time: None,
             message: None,
-            tick_count: 0,
+            size: XY::zero(),
         };
         s.update();
         s
     }
 
     pub fn update(&mut self) {
-        if self.tick_count % 25 == 0 {
-            self.song = MPD::now_playing();
-            self.time = MPD::current_time();
-        }
-        self.tick_count += 1;
+        self.song = self.app.mpd.now_playing();
+        self.time = self.app.mpd.current_time();
     }
 
     pub(super) fn lock_title(&mut self, msg: String) {
@@ -49,71 +58,184 @@ impl Playing {
         if let Some(s) = &self.message {
             return s.clone();
         }
+        if let Some(notice) = self.app.notices.current() {
+            return notice;
+        }
         let mut out = String::from("\x1b[1m"); // bold
-        out.push_str(match MPD::status() {
+        out.push_str(match self.app.mpd.status() {
             Some(s) => match s.state {
                 State::Stop => return String::from("\x1b[1mStopped\x1b[0m"),
                 State::Pause => "Paused: ",
                 State::Play => "Playing: ",
             },
-            None => {
-                log!(Level::Warn, "Song status not found");
-                return String::new();
-            }
+            // never reached MPD; the offline note says so
+            None => return String::new(),
         });
         out.push_str("\x1b[0m"); // reset
         out.push_str(
             match &self.song {
                 None => String::from("Unknown"),
                 Some(s) => {
-                    let artist = s
-                        .tags
-                        .iter()
-                        .find(|t| t.0.eq("AlbumArtist"))
-                        .map(|t| t.1.clone())
-                        .or(s.artist.clone());
-                    let title = s.title.clone();
-                    let date = s.tags.iter().find(|t| t.0.eq("Date")).map(|t| t.1.clone());
-                    let album = s.tags.iter().find(|t| t.0.eq("Album")).map(|t| t.1.clone());
-                    let r = match (artist, album, date, title) {
+                    let date = s
+                        .year()
+                        .map(|y| y.to_string())
+                        .or(s.date.as_ref().map(|d| d.raw.clone()));
+                    match (
+                        s.album_artist(&self.app.config.tags.joiner),
+                        &s.album,
+                        date,
+                        &s.title,
+                    ) {
                         (_, _, _, None) => String::from("Unknown"),
                         (Some(aa), Some(a), Some(d), Some(t)) => {
                             format!("{} \"{}\" ({}) - {}", aa, a, d, t)
                         }
                         (Some(aa), Some(a), None, Some(t)) => format!("{} \"{}\" - {}", aa, a, t),
                         (Some(aa), None, _, Some(t)) => format!("{} - {}", aa, t),
-                        (_, _, _, Some(t)) => format!("{}", t),
-                    };
-                    r
+                        (_, _, _, Some(t)) => t.to_string(),
+                    }
                 }
             }
             .as_str(),
         );
-        return out;
+        out
+    }
+
+    fn format_status(&self, status: &Status, elapsed: Duration, total: Duration) -> String {
+        let remaining = total.saturating_sub(elapsed);
+        let time = match self.time_mode {
+            TimeMode::Elapsed => format!("{}/{}", format_time(elapsed), format_time(total)),
+            TimeMode::Remaining => format!("-{}/{}", format_time(remaining), format_time(total)),
+        };
+        let flag = |on: bool, c: char| if on { c } else { '-' };
+        let mut flags = format!(
+            "[{}{}{}{}]",
+            flag(status.repeat, 'r'),
+            flag(status.random, 'z'),
+            flag(status.single, 's'),
+            flag(status.consume, 'c'),
+        );
+        if let Some(x) = status.crossfade.filter(|x| !x.is_zero()) {
+            flags.push_str(&format!(" x{}s", x.as_secs()));
+        }
+        let volume = match status.volume {
+            v if v < 0 => String::new(),
+            v => format!("vol {}%", v),
+        };
+        let bitrate = status
+            .bitrate
+            .filter(|b| *b > 0)
+            .map(|b| format!("{}kbps", b))
+            .unwrap_or_default();
+        let audio = status.audio.map(format_audio).unwrap_or_default();
+        let position = match status.song {
+            Some(p) => format!("{}/{}", p.pos + 1, status.queue_len),
+            None => format!("-/{}", status.queue_len),
+        };
+
+        let out = self
+            .app
+            .config
+            .status
+            .format
+            .replace("{time}", &time)
+            .replace("{elapsed}", &format_time(elapsed))
+            .replace("{total}", &format_time(total))
+            .replace("{remaining}", &format_time(remaining))
+            .replace("{flags}", &flags)
+            .replace("{volume}", &volume)
+            .replace("{bitrate}", &bitrate)
+            .replace("{audio}", &audio)
+            .replace("{position}", &position);
+        // placeholders that expanded to nothing leave runs of spaces behind
+        out.split(' ')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // x is a column on the progress bar
+    fn seek_to(&mut self, x: usize) {
+        let Some(total) = self
+            .app
+            .mpd
+            .status()
+            .and_then(|s| s.duration.or(s.time.map(|t| t.1)))
+        else {
+            return;
+        };
+        let pct = x as f64 / self.size.x.max(1) as f64;
+        if let Err(e) = self.app.mpd.seek(total.mul_f64(pct.clamp(0.0, 1.0))) {
+            log!(Level::Warn, "Failed to seek: {}", e);
+        }
+        self.time = self.app.mpd.current_time();
+    }
+
+    fn draw_bar(&self, printer: &Printer, y: usize, pct: f64) {
+        let palette = &printer.theme.palette;
+        let front = palette
+            .custom("progress")
+            .map(|c| ColorType::Color(*c))
+            .unwrap_or(ColorType::Palette(PaletteColor::Highlight));
+        let back = palette
+            .custom("progress_bg")
+            .map(|c| ColorType::Color(*c))
+            .unwrap_or(ColorType::Palette(PaletteColor::View));
+
+        let width = printer.size.x;
+        let eighths = (width as f64 * 8.0 * pct.clamp(0.0, 1.0)) as usize;
+        let mut bar = "█".repeat(eighths / 8);
+        if eighths / 8 < width {
+            bar.push_str(EIGHTHS[eighths % 8]);
+            bar.push_str(&" ".repeat(width - eighths / 8 - 1));
+        }
+        printer.with_color(ColorStyle::new(front, back), |p| {
+            p.print(XY::from((0, y)), &bar)
+        });
     }
 }
 
 impl View for Playing {
     fn draw(&self, printer: &Printer<'_, '_>) {
-        printer.print(
-            XY::from((0, printer.size.y - 1)),
-            self.format_title().as_str(),
-        );
-        if let Some(time) = &self.time {
-            let mut el = time.1;
-            if let Some(e) = MPD::elapsed() {
-                el = e;
+        if printer.size.y < 2 {
+            return;
+        }
+        let title_y = printer.size.y - 1;
+        let status = self.app.mpd.status();
+        let mut title_width = printer.size.x;
+
+        // everything shown is the last known state until MPD is back
+        if !self.app.mpd.online() && self.message.is_none() {
+            let note = offline_note(&self.app.mpd);
+            title_width = title_width.saturating_sub(note.width() + 1);
+            printer.with_color(ColorStyle::from(BaseColor::Red.light()), |p| {
+                p.print(XY::from((title_width + 1, title_y)), &note)
+            });
+        }
+
+        if let (Some(time), Some(status)) = (&self.time, &status) {
+            let total = status.duration.unwrap_or(time.1);
+            let elapsed = self.app.mpd.elapsed().unwrap_or(time.0).min(total);
+            let pct = if total.is_zero() {
+                0.0
+            } else {
+                elapsed.as_secs_f64() / total.as_secs_f64()
+            };
+            self.draw_bar(printer, printer.size.y - 2, pct);
+
+            if self.message.is_none() {
+                let line = self.format_status(status, elapsed, total);
+                let width = line.width().min(title_width);
+                title_width -= width;
+                printer.with_color(ColorStyle::secondary(), |p| {
+                    p.print(XY::from((title_width, title_y)), &line)
+                });
             }
-            let pct = el.as_secs_f64() / time.1.as_secs_f64();
-            printer.print(
-                XY::from((0, printer.size.y - 2)),
-                format!(
-                    "{}>",
-                    "=".repeat(((printer.size.x as f64 * pct) as usize).max(1) - 1)
-                )
-                .as_str(),
-            );
         }
+
+        printer
+            .cropped((title_width.saturating_sub(1), printer.size.y))
+            .print_styled(XY::from((0, title_y)), &ansi::parse(self.format_title()));
     }
 
     fn required_size(&mut self, constraint: XY<usize>) -> XY<usize> {
@@ -123,13 +245,67 @@ impl View for Playing {
         }
     }
 
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+    }
+
     fn on_event(&mut self, e: Event) -> EventResult {
         match e {
             Event::Refresh => {
                 self.update();
                 EventResult::Ignored
             }
+            Event::Char(c) if self.app.notices.act(c, &self.app.mpd) => EventResult::Consumed(None),
+            Event::Char('t') => {
+                self.time_mode = self.time_mode.toggle();
+                EventResult::Consumed(None)
+            }
+            Event::Mouse {
+                offset,
+                position,
+                event: MouseEvent::Press(MouseButton::Left) | MouseEvent::Hold(MouseButton::Left),
+            } => match position.checked_sub(offset) {
+                // the bar is the second to last row
+                Some(pos) if pos.y + 2 == self.size.y => {
+                    self.seek_to(pos.x);
+                    EventResult::Consumed(None)
+                }
+                _ => EventResult::Ignored,
+            },
             _ => EventResult::Ignored,
         }
     }
 }
+
+// "offline, as of 3m ago"
+pub(crate) fn offline_note(mpd: &MPD) -> String {
+    match mpd.last_seen() {
+        Some(t) => format!("offline, as of {} ago", format_age(t.elapsed())),
+        None => String::from("offline"),
+    }
+}
+
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s => format!("{}h", s / 3600),
+    }
+}
+
+pub(crate) fn format_time(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+pub(crate) fn format_audio(a: AudioFormat) -> String {
+    let bits = match a.bits {
+        0 => String::from("f"),
+        b => format!("{}bit", b),
+    };
+    format!("{}kHz/{}/{}ch", a.rate as f64 / 1000.0, bits, a.chans)
+}
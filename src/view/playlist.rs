@@ -1,17 +1,35 @@
 #![allow(unused)]
 
+use std::time::{Duration, Instant};
+
 use cursive::{
-    theme::{Effect, Style, StyleType},
+    event::{Event, EventResult, MouseButton, MouseEvent},
+    theme::{BaseColor, ColorStyle, Effect, Style, StyleType},
     utils::{
-        markup::ansi::{self, Parser},
+        markup::{
+            ansi::{self, Parser},
+            StyledString,
+        },
         span::{SpannedStr, SpannedString},
     },
     View, XY,
 };
 use log::{log, Level};
-use mpd::Song;
+use mpd::song::Id;
+
+use super::info;
+use super::playing::offline_note;
+use crate::app::App;
+use crate::meta::SongMeta;
+use crate::mpd_util::{
+    stickers::{stars, Ratings, MAX_RATING},
+    Pending,
+};
 
-use crate::mpd_util::MPD;
+// rows above the first song
+const ROW_OFFSET: usize = 2;
+const SCROLL_STEP: usize = 3;
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
 
 enum ColumnKey {
     Album,
@@ -19,6 +37,7 @@ enum ColumnKey {
     Artist,
     Disc,
     Duration,
+    Rating,
     Title,
     Track,
 }
@@ -32,61 +51,73 @@ struct Column {
 }
 
 impl Column {
-    fn normalize(columns: &mut Vec<Column>) {
+    fn normalize(columns: &mut [Column]) {
         let sum: f64 = columns.iter().map(|c| c.ratio).sum();
         columns.iter_mut().for_each(|c| c.ratio /= sum);
     }
 
-    fn get(&self, song: &Song) -> Option<String> {
+    fn get(&self, song: &SongMeta, joiner: &str, ratings: &Ratings) -> Option<String> {
         match self.key {
-            ColumnKey::Album => song
-                .tags
-                .iter()
-                .find(|t| t.0.eq("Album"))
-                .map(|t| t.1.clone()),
-            ColumnKey::AlbumArtist => song
-                .tags
-                .iter()
-                .find(|t| t.0.eq("AlbumArtist"))
-                .map(|t| t.1.clone()),
-            ColumnKey::Artist => song.artist.clone(),
-            ColumnKey::Disc => song
-                .tags
-                .iter()
-                .find(|t| t.0.eq("Disc"))
-                .map(|t| t.1.clone()),
+            ColumnKey::Album => song.album.clone(),
+            ColumnKey::AlbumArtist => song.album_artist(joiner),
+            ColumnKey::Artist => song.artist(joiner),
+            ColumnKey::Disc => song.disc.as_ref().map(|d| d.to_string()),
             ColumnKey::Duration => song
-                .duration
+                .duration()
                 .map(|d| format!("{:02}:{:02}", d.as_secs() / 60, d.as_secs() % 60)),
-            ColumnKey::Title => song.title.clone(),
-            ColumnKey::Track => song
-                .tags
-                .iter()
-                .find(|t| t.0.eq("Track"))
-                .map(|t| t.1.clone())
-                .map(|t| format!("{:02}", t.parse::<usize>().unwrap())),
+            // blank rather than Unknown for songs nobody rated
+            ColumnKey::Rating => Some(ratings.get(song.file()).map(stars).unwrap_or_default()),
+            ColumnKey::Title => Some(song.display_title()),
+            ColumnKey::Track => song.track.as_ref().map(|t| t.to_string()),
         }
     }
 }
 
+// Moves a song's rating by delta stars, for the queue and library keys
+pub(super) fn rate(app: &App, file: &str, delta: i8) {
+    let rating = app.mpd.ratings().get(file).unwrap_or(0);
+    let rating = rating.saturating_add_signed(delta).min(MAX_RATING);
+    match app.mpd.set_rating(file, rating) {
+        Ok(()) => log!(Level::Info, "Rated {} {}", file, stars(rating)),
+        Err(e) => log!(Level::Warn, "Failed to rate {}: {}", file, e),
+    }
+}
+
+// Formatted rows for the visible window. Rebuilt only when the queue
+// version, scroll offset or view size changes, so drawing costs the same
+// for any queue length.
+#[derive(Default)]
+struct Rows {
+    key: Option<(u32, u64, usize, XY<usize>)>,
+    rows: Vec<(Option<Id>, StyledString)>,
+}
+
 pub struct Playlist {
+    app: App,
     view_size: XY<usize>,
     offset: usize,
     selected: Option<usize>,
     columns: Vec<Column>,
+    last_click: Option<(usize, Instant)>,
+    len: usize,
+    rows: Rows,
 }
 
 impl Playlist {
-    pub fn new() -> Self {
+    pub fn new(app: App) -> Self {
         Self {
+            columns: Playlist::default_columns(app.config.stickers.rating_column),
+            app,
             view_size: XY::zero(),
             offset: 0,
             selected: Some(0),
-            columns: Playlist::default_columns(),
+            last_click: None,
+            len: 0,
+            rows: Rows::default(),
         }
     }
 
-    fn default_columns() -> Vec<Column> {
+    fn default_columns(rating: bool) -> Vec<Column> {
         let mut cols = vec![
             Column {
                 header: "Artist".into(),
@@ -124,22 +155,36 @@ impl Playlist {
                 format: "\x1b[35m",
             },
         ];
+        if rating {
+            let time = cols.len() - 1;
+            cols.insert(
+                time,
+                Column {
+                    header: "Rating".into(),
+                    min_width: 6,
+                    ratio: 0.0,
+                    key: ColumnKey::Rating,
+                    format: "\x1b[33m",
+                },
+            );
+        }
         Column::normalize(&mut cols);
         cols
     }
 
-    fn format_song(&self, song: &Song) -> String {
+    fn format_song(&self, song: &SongMeta, ratings: &Ratings) -> String {
         let mut out = String::new();
         for (col, width) in self.column_widths() {
+            // the color goes outside the cut, which would split its escape
+            let (format, text) = match col.get(song, &self.app.config.tags.joiner, ratings) {
+                Some(text) => (col.format, text),
+                None => ("\x1b[90m", String::from("Unknown")),
+            };
             out.push_str(
                 format!(
                     "{0}{1:2$} {3}",
-                    col.format,
-                    col.get(song)
-                        .unwrap_or("\x1b[90mUnknown".into())
-                        .chars()
-                        .take(width - 1)
-                        .collect::<String>(),
+                    format,
+                    text.chars().take(width - 1).collect::<String>(),
                     width - 1,
                     "\x1b[0m"
                 )
@@ -149,6 +194,78 @@ impl Playlist {
         out
     }
 
+    fn visible_rows(&self) -> usize {
+        self.view_size.y.saturating_sub(ROW_OFFSET)
+    }
+
+    fn update_rows(&mut self) {
+        self.len = self.app.mpd.queue_len();
+        let Some(version) = self.app.mpd.queue_version() else {
+            self.rows = Rows::default();
+            return;
+        };
+        let ratings = self.app.mpd.ratings();
+        let key = Some((version, ratings.generation, self.offset, self.view_size));
+        if self.rows.key == key {
+            return;
+        }
+        let window = self
+            .app
+            .mpd
+            .queue_window(self.offset..self.offset + self.visible_rows());
+        self.rows = Rows {
+            key,
+            rows: window
+                .iter()
+                .map(|song| {
+                    (
+                        song.place().map(|p| p.id),
+                        ansi::parse(self.format_song(song, &ratings)),
+                    )
+                })
+                .collect(),
+        };
+    }
+
+    fn scroll(&mut self, up: bool) {
+        let max = self.len.saturating_sub(self.visible_rows());
+        self.offset = if up {
+            self.offset.saturating_sub(SCROLL_STEP)
+        } else {
+            (self.offset + SCROLL_STEP).min(max)
+        };
+    }
+
+    fn click(&mut self, row: usize) -> EventResult {
+        let index = self.offset + row;
+        if index >= self.len {
+            return EventResult::Ignored;
+        }
+        let now = Instant::now();
+        let double = matches!(self.last_click,
+            Some((i, t)) if i == index && now.duration_since(t) < DOUBLE_CLICK);
+        self.selected = Some(index);
+        if double {
+            self.last_click = None;
+            if let Err(e) = self.app.mpd.play_pos(index as u32) {
+                log!(Level::Warn, "Failed to play {}: {}", index, e);
+            }
+        } else {
+            self.last_click = Some((index, now));
+        }
+        EventResult::Consumed(None)
+    }
+
+    // Moves the selected song's rating up or down a star
+    fn rate(&mut self, delta: i8) -> EventResult {
+        let Some(song) = self.selected.and_then(|i| self.app.mpd.queue_song(i)) else {
+            return EventResult::Ignored;
+        };
+        rate(&self.app, song.file(), delta);
+        self.update_rows();
+        EventResult::Consumed(None)
+    }
+
     fn column_widths(&self) -> Vec<(&Column, usize)> {
         let mut widths = vec![];
         let max_width = self.view_size.x;
@@ -158,6 +275,7 @@ impl Playlist {
                 return widths;
             }
             widths.push((col, col.min_width));
+            width += col.min_width;
         }
         widths.clear();
         // all columns fit with at least min_width
@@ -172,27 +290,90 @@ impl Playlist {
 
 impl View for Playlist {
     fn draw(&self, printer: &cursive::Printer) {
-        let q = MPD::queue();
-        if !q.is_some() {
-            printer.print(XY { x: 0, y: 0 }, "Queue was None");
+        if self.rows.key.is_none() {
+            printer.print(XY { x: 0, y: 0 }, "Queue unavailable");
             return;
         }
-        let q = q.unwrap();
-        let current = MPD::now_playing().unwrap_or_default();
-        let count = (q.len() - self.offset).min(printer.size.y - 1);
-        for row in 0..count {
-            let line = self.format_song(&q[row + self.offset]);
-            let mut spanstr = ansi::parse(line);
-            if (q[row + self.offset].eq(&current)) {
-                spanstr
-                    .spans_raw_attr_mut()
-                    .for_each(|span| span.attr.effects |= Effect::Bold)
+        if !self.app.mpd.online() {
+            let pending: usize = self
+                .app
+                .mpd
+                .pending()
+                .iter()
+                .map(|p| match p {
+                    Pending::Add(files) => files.len(),
+                })
+                .sum();
+            let mut note = offline_note(&self.app.mpd);
+            if pending > 0 {
+                note.push_str(&format!(", {} songs waiting to be added", pending));
             }
-            printer.print_styled(XY { x: 0, y: row + 2 }, &spanstr);
+            printer.with_color(ColorStyle::from(BaseColor::Red.light()), |p| {
+                p.print((0, 0), &note)
+            });
+        }
+        let current = self.app.mpd.status().and_then(|s| s.song).map(|p| p.id);
+        for (row, (id, line)) in self.rows.rows.iter().enumerate() {
+            let bold = current.is_some() && *id == current;
+            let reverse = self.selected == Some(row + self.offset);
+            let pos = XY {
+                x: 0,
+                y: row + ROW_OFFSET,
+            };
+            if !bold && !reverse {
+                printer.print_styled(pos, line);
+                continue;
+            }
+            let mut line = line.clone();
+            line.spans_raw_attr_mut().for_each(|span| {
+                if bold {
+                    span.attr.effects |= Effect::Bold;
+                }
+                if reverse {
+                    span.attr.effects |= Effect::Reverse;
+                }
+            });
+            printer.print_styled(pos, &line);
         }
     }
 
     fn layout(&mut self, size: cursive::Vec2) {
         self.view_size = size;
+        self.update_rows();
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        match e {
+            Event::Mouse {
+                offset,
+                position,
+                event,
+            } => {
+                let Some(pos) = position.checked_sub(offset) else {
+                    return EventResult::Ignored;
+                };
+                match event {
+                    MouseEvent::WheelUp => {
+                        self.scroll(true);
+                        EventResult::Consumed(None)
+                    }
+                    MouseEvent::WheelDown => {
+                        self.scroll(false);
+                        EventResult::Consumed(None)
+                    }
+                    MouseEvent::Press(MouseButton::Left) if pos.y >= ROW_OFFSET => {
+                        self.click(pos.y - ROW_OFFSET)
+                    }
+                    _ => EventResult::Ignored,
+                }
+            }
+            Event::Char('+') | Event::Char('=') => self.rate(1),
+            Event::Char('-') => self.rate(-1),
+            Event::Char('i') => match self.selected.and_then(|i| self.app.mpd.queue_song(i)) {
+                Some(song) => info::open(&self.app, &song),
+                None => EventResult::Ignored,
+            },
+            _ => EventResult::Ignored,
+        }
     }
 }
@@ -3,7 +3,7 @@
 use std::time::{Duration, Instant};
 
 use cursive::{
-    event::{Event, EventResult, Key},
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
     view::ViewWrapper,
     views::{DummyView, NamedView, ResizedView, TextView},
     Vec2, View, XY,
@@ -11,95 +11,378 @@ use cursive::{
 use cursive_flexi_logger_view::FlexiLoggerView;
 use log::{log, Level};
 
-use super::{playing::Playing, titlebar::Titlebar};
-use crate::global_cursive;
+use super::{
+    layout::{Area, Layout, Separator},
+    library::LibraryView,
+    playing::Playing,
+    playlist::Playlist,
+    titlebar::Titlebar,
+};
+use crate::app::App;
 
 enum EventMode {
     Pass,
     Input,
 }
 
+// A content view registered with Root, shown as a tab in the titlebar
+pub struct Tab {
+    pub name: String,
+    pub key: char,
+    pub icon: String,
+    pub view: Box<dyn View>,
+}
+
+// A titlebar entry: either a single registered view or a configured layout
+// of several of them.
+struct Page {
+    id: String, // config refers to pages by lowercase name
+    label: String,
+    key: Option<char>,
+    layout: Layout<usize>, // leaves index into Root::content
+    focus: usize,          // index into layout.leaves()
+}
+
+impl Page {
+    fn new(name: &str, key: Option<char>, icon: &str, layout: Layout<usize>) -> Self {
+        let label = match key {
+            Some(k) => format!(" {} {} {} ", k, icon, name),
+            None => format!(" {} {} ", icon, name),
+        };
+        Self {
+            id: name.to_lowercase(),
+            label,
+            key,
+            layout,
+            focus: 0,
+        }
+    }
+
+    fn focused_view(&self) -> Option<usize> {
+        self.layout.leaves().get(self.focus).map(|v| **v)
+    }
+}
+
 pub struct Root {
+    app: App,
+
     // Child views
     titlebar: ResizedView<Titlebar>,
-    content: Vec<Box<dyn View>>,
+    content: Vec<Tab>,
     playing: ResizedView<Playing>,
 
     // State
-    selected: usize,
+    pages: Vec<Page>, // in display order
+    selected: usize,  // index into pages
+    panes: Vec<Area>, // arrangement of the selected page, relative to the content area
+    separators: Vec<Separator>,
     input: Option<String>,
     mode: EventMode,
-    last_tick: Instant,
+    size: Vec2,
+}
+
+// Heights of the titlebar, content and playing areas. Playing is kept as long
+// as possible on short terminals, then the titlebar.
+fn regions(height: usize) -> (usize, usize, usize) {
+    let playing = height.min(2);
+    let title = (height - playing).min(2);
+    (title, height - playing - title, playing)
 }
 
 impl Root {
-    pub fn new() -> Self {
-        Self {
-            titlebar: ResizedView::with_fixed_height(2, Titlebar::new("Title".into())),
-            content: vec![
-                Box::new(FlexiLoggerView::new()),
-                Box::new(super::playlist::Playlist::new()),
-            ],
-            //content: vec![Box::new(TextView::new(""))],
-            playing: ResizedView::with_fixed_height(2, Playing::new()),
+    pub fn new(app: App) -> Self {
+        let mut root = Self {
+            titlebar: ResizedView::with_fixed_height(2, Titlebar::new()),
+            content: vec![],
+            playing: ResizedView::with_fixed_height(2, Playing::new(app.clone())),
+            app: app.clone(),
 
+            pages: vec![],
             selected: 0,
+            panes: vec![],
+            separators: vec![],
             input: None,
             mode: EventMode::Pass,
-            last_tick: Instant::now(),
+            size: Vec2::zero(),
+        };
+        root.register("Log", '1', "≡", FlexiLoggerView::new());
+        root.register("Queue", '2', "♫", Playlist::new(app.clone()));
+        root.register("Library", '3', "▤", LibraryView::new(app));
+        root
+    }
+
+    pub fn register<V: View>(&mut self, name: &str, key: char, icon: &str, view: V) {
+        self.content.push(Tab {
+            name: name.into(),
+            key,
+            icon: icon.into(),
+            view: Box::new(view),
+        });
+        self.update_pages();
+    }
+
+    // Rebuild pages from the registered views and configured layouts. Listed
+    // pages come first in the configured order, the rest follow in
+    // registration order, and disabled pages are hidden.
+    fn update_pages(&mut self) {
+        let cfg = self.app.config.clone();
+        let find = |name: &String| {
+            self.content
+                .iter()
+                .position(|t| t.name.eq_ignore_ascii_case(name))
+        };
+
+        let mut pages: Vec<Page> = self
+            .content
+            .iter()
+            .enumerate()
+            .map(|(i, t)| Page::new(&t.name, Some(t.key), &t.icon, Layout::Pane(i)))
+            .collect();
+        for l in &cfg.layouts {
+            match l.layout.filter_map(&find) {
+                Some(layout) => pages.push(Page::new(&l.name, l.key, &l.icon, layout)),
+                None => log!(Level::Warn, "Layout {} has no known views", l.name),
+            }
+        }
+
+        let mut ordered: Vec<Page> = vec![];
+        for name in &cfg.tabs.order {
+            if let Some(i) = pages.iter().position(|p| name.eq_ignore_ascii_case(&p.id)) {
+                ordered.push(pages.remove(i));
+            }
+        }
+        ordered.extend(pages);
+        ordered.retain(|p| {
+            !cfg.tabs
+                .disabled
+                .iter()
+                .any(|d| d.eq_ignore_ascii_case(&p.id))
+        });
+
+        // keep the current page and pane focus across rebuilds
+        let current = self.pages.get(self.selected).map(|p| p.id.clone());
+        for page in ordered.iter_mut() {
+            if let Some(old) = self.pages.iter().find(|p| p.id == page.id) {
+                page.focus = old.focus.min(page.layout.leaves().len() - 1);
+            }
+        }
+        self.selected = current
+            .and_then(|c| ordered.iter().position(|p| p.id == c))
+            .unwrap_or(0);
+        self.pages = ordered;
+        self.titlebar.get_inner_mut().set_tabs(
+            self.pages.iter().map(|p| p.label.clone()).collect(),
+            self.selected,
+        );
+    }
+
+    // Hidden views miss refreshes, so the page is brought up to date as it
+    // is shown
+    fn select(&mut self, page: usize) {
+        if page < self.pages.len() {
+            self.selected = page;
+            self.titlebar.get_inner_mut().set_active(page);
+            for view in self.visible() {
+                self.content[view].view.on_event(Event::Refresh);
+            }
+            self.layout(self.size);
+        }
+    }
+
+    fn select_next(&mut self, forward: bool) {
+        if self.pages.is_empty() {
+            return;
         }
+        let n = self.pages.len();
+        self.select(if forward {
+            (self.selected + 1) % n
+        } else {
+            (self.selected + n - 1) % n
+        });
+    }
+
+    fn focus_next(&mut self) {
+        if let Some(page) = self.pages.get_mut(self.selected) {
+            page.focus = (page.focus + 1) % page.layout.leaves().len();
+        }
+    }
+
+    fn visible(&self) -> Vec<usize> {
+        match self.pages.get(self.selected) {
+            Some(p) => p.layout.leaves().into_iter().copied().collect(),
+            None => vec![],
+        }
+    }
+
+    fn focused_mut(&mut self) -> Option<&mut Box<dyn View>> {
+        let view = self.pages.get(self.selected)?.focused_view()?;
+        Some(&mut self.content[view].view)
     }
 
     fn pass_event(&mut self, e: Event) -> EventResult {
-        match self.content[self.selected].on_event(e.clone()) {
+        let result = match self.focused_mut() {
+            Some(v) => v.on_event(e.clone()),
+            None => EventResult::Ignored,
+        };
+        match result {
             EventResult::Ignored => self.playing.on_event(e),
             r => r,
         }
     }
+
+    fn global_event(&mut self, e: Event) -> EventResult {
+        match e {
+            Event::Char(':') => {
+                self.input = Some(String::from(':'));
+                self.mode = EventMode::Input;
+                EventResult::Consumed(None)
+            }
+            Event::Char('q') => EventResult::with_cb(|s| s.quit()),
+            Event::Key(Key::Tab) => {
+                self.select_next(true);
+                EventResult::Consumed(None)
+            }
+            Event::Shift(Key::Tab) => {
+                self.select_next(false);
+                EventResult::Consumed(None)
+            }
+            Event::CtrlChar('w') => {
+                self.focus_next();
+                EventResult::Consumed(None)
+            }
+            Event::Char(c) => match self.pages.iter().position(|p| p.key == Some(c)) {
+                Some(tab) => {
+                    self.select(tab);
+                    EventResult::Consumed(None)
+                }
+                None => self.playing.on_event(e),
+            },
+            _ => self.playing.on_event(e),
+        }
+    }
+
+    // Mouse events go to whichever child is under the pointer, with the
+    // offset shifted to that child's origin. Pressing on a pane focuses it.
+    fn route_mouse(&mut self, offset: Vec2, position: Vec2, event: MouseEvent) -> EventResult {
+        let Some(pos) = position.checked_sub(offset) else {
+            return EventResult::Ignored;
+        };
+        let (title_height, content_height, _) = regions(self.size.y);
+        if pos.y < title_height {
+            match (event, self.titlebar.get_inner().tab_at(pos.x)) {
+                (MouseEvent::Press(MouseButton::Left), Some(tab)) => {
+                    self.select(tab);
+                    EventResult::Consumed(None)
+                }
+                _ => EventResult::Ignored,
+            }
+        } else if pos.y < title_height + content_height {
+            let content_pos = pos - (0, title_height);
+            let Some(pane) = self.panes.iter().position(|a| a.contains(content_pos)) else {
+                return EventResult::Ignored;
+            };
+            let origin = self.panes[pane].origin;
+            if let (MouseEvent::Press(_), Some(page)) = (event, self.pages.get_mut(self.selected)) {
+                page.focus = pane;
+            }
+            let view = self.visible()[pane];
+            self.content[view].view.on_event(Event::Mouse {
+                offset: offset + (0, title_height) + origin,
+                position,
+                event,
+            })
+        } else {
+            self.playing.on_event(Event::Mouse {
+                offset: offset + (0, title_height + content_height),
+                position,
+                event,
+            })
+        }
+    }
 }
 
 impl View for Root {
-    // 'Root' is functionally a vertical linear layout
+    // 'Root' is functionally a vertical linear layout, with the middle
+    // section split between the panes of the selected page
     fn draw(&self, printer: &cursive::Printer) {
-        let mut title_printer = printer.clone();
-        let mut content_printer = printer.clone();
-        let mut playing_printer = printer.clone();
-        title_printer.output_size.y = 2;
-        title_printer.size.y = 2;
+        let (title_height, content_height, playing_height) = regions(printer.size.y);
+        let width = printer.size.x;
 
-        content_printer.output_size.y -= 4;
-        content_printer.size.y -= 4;
-        content_printer.offset.y += 2;
+        self.titlebar.draw(&printer.cropped((width, title_height)));
 
-        playing_printer.output_size.y = 2;
-        playing_printer.size.y = 2;
-        playing_printer.offset.y += title_printer.size.y + content_printer.size.y;
+        let content_printer = printer
+            .offset((0, title_height))
+            .cropped((width, content_height));
+        let focus = self.pages.get(self.selected).map(|p| p.focus);
+        for (i, (view, area)) in self.visible().iter().zip(&self.panes).enumerate() {
+            if area.size.x == 0 || area.size.y == 0 {
+                continue;
+            }
+            let pane_printer = content_printer
+                .offset(area.origin)
+                .cropped(area.size)
+                .focused(focus == Some(i));
+            self.content[*view].view.draw(&pane_printer);
+        }
+        self.separators
+            .iter()
+            .for_each(|s| s.draw(&content_printer));
 
-        self.titlebar.draw(&title_printer);
-        self.content[self.selected].draw(&content_printer);
-        self.playing.draw(&playing_printer);
+        self.playing.draw(
+            &printer
+                .offset((0, title_height + content_height))
+                .cropped((width, playing_height)),
+        );
     }
 
     fn layout(&mut self, size: XY<usize>) {
         // log!(Level::Debug, "Layout");
-        self.titlebar.layout(XY { x: size.x, y: 2 });
-        self.playing.layout(XY { x: size.x, y: 2 });
-        self.content[self.selected].layout(XY {
+        self.size = size;
+        // keep the command line visible while typing; layout runs on every redraw
+        if let Some(i) = &self.input {
+            self.playing.get_inner_mut().lock_title(i.clone());
+        } else {
+            self.playing.get_inner_mut().unlock_title();
+        }
+        let (title_height, content_height, playing_height) = regions(size.y);
+        self.titlebar.layout(XY {
             x: size.x,
-            y: size.y - 4,
-        })
+            y: title_height,
+        });
+        self.playing.layout(XY {
+            x: size.x,
+            y: playing_height,
+        });
+
+        self.panes.clear();
+        self.separators.clear();
+        if let Some(page) = self.pages.get(self.selected) {
+            page.layout.arrange(
+                Area::new(Vec2::zero(), XY::new(size.x, content_height)),
+                &mut self.panes,
+                &mut self.separators,
+            );
+        }
+        for (view, area) in self.visible().into_iter().zip(self.panes.clone()) {
+            self.content[view].view.layout(area.size);
+        }
     }
 
     fn needs_relayout(&self) -> bool {
         self.titlebar.needs_relayout()
-            || self.content[self.selected].needs_relayout()
+            || self
+                .visible()
+                .iter()
+                .any(|v| self.content[*v].view.needs_relayout())
             || self.playing.needs_relayout()
     }
 
     fn call_on_any(&mut self, s: &cursive::view::Selector, e: cursive::event::AnyCb) {
         // log!(Level::Debug, "Call On Any");
         self.titlebar.call_on_any(s, e);
-        self.content.iter_mut().for_each(|v| v.call_on_any(s, e));
+        self.content
+            .iter_mut()
+            .for_each(|t| t.view.call_on_any(s, e));
         self.playing.call_on_any(s, e);
     }
 
@@ -109,12 +392,13 @@ impl View for Root {
             y: 4 + self
                 .content
                 .iter_mut()
-                .map(|v| {
-                    v.required_size(XY {
-                        x: constraint.x,
-                        y: constraint.y - 4, // reserve for title bar & playing
-                    })
-                    .y
+                .map(|t| {
+                    t.view
+                        .required_size(XY {
+                            x: constraint.x,
+                            y: constraint.y.saturating_sub(4), // reserve for title bar & playing
+                        })
+                        .y
                 })
                 .max()
                 .unwrap_or(1),
@@ -124,45 +408,35 @@ impl View for Root {
     fn on_event(&mut self, e: Event) -> EventResult {
         // handle refresh event seperately
         if let Some(&Event::Refresh) = Some(&e) {
-            let cur_time = Instant::now();
-            let dt = cur_time.duration_since(self.last_tick);
-            self.last_tick = cur_time;
-            self.titlebar
-                .get_inner_mut()
-                .set_title(format!("{:.2} tps", 1.0 / dt.as_secs_f64()));
-
-            if let Some(i) = &self.input {
-                self.playing.get_inner_mut().lock_title(i.clone());
-            } else {
-                self.playing.get_inner_mut().unlock_title();
+            for view in self.visible() {
+                self.content[view].view.on_event(Event::Refresh);
             }
-            self.pass_event(e);
+            self.playing.on_event(e);
             return EventResult::Ignored; // Always mark refresh as ignored
         }
 
+        if let Event::Mouse {
+            offset,
+            position,
+            event,
+        } = e
+        {
+            return self.route_mouse(offset, position, event);
+        }
+
         // handle other events
         match self.mode {
-            EventMode::Pass => match e {
-                Event::Char(':') => {
-                    self.input = Some(String::from(':'));
-                    self.mode = EventMode::Input;
-                    EventResult::Consumed(None)
+            // the focused view gets first pick, so it can take text input
+            EventMode::Pass => {
+                let result = match self.focused_mut() {
+                    Some(v) => v.on_event(e.clone()),
+                    None => EventResult::Ignored,
+                };
+                match result {
+                    EventResult::Ignored => self.global_event(e),
+                    r => r,
                 }
-                Event::Char('q') => {
-                    global_cursive().quit();
-                    EventResult::Consumed(None)
-                }
-
-                Event::Char('1') => {
-                    self.selected = 0;
-                    EventResult::Consumed(None)
-                }
-                Event::Char('2') => {
-                    self.selected = 1;
-                    EventResult::Consumed(None)
-                }
-                _ => self.pass_event(e),
-            },
+            }
             EventMode::Input => match e {
                 Event::Char(c) => {
                     self.input.iter_mut().for_each(|s| s.push(c));
@@ -0,0 +1,343 @@
+use std::{sync::Arc, time::SystemTime};
+
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    theme::ColorStyle,
+    Printer, View, XY,
+};
+use log::{log, Level};
+use time::{Date, OffsetDateTime, UtcOffset};
+use unicode_width::UnicodeWidthStr;
+
+use super::library::Cursor;
+use super::playing::format_time;
+use crate::app::App;
+use crate::history::{History, Period, Play, Stats};
+
+// rows above the list
+const ROW_OFFSET: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Artists,
+    Albums,
+    Tracks,
+    Days,
+    Recent,
+}
+
+const SECTIONS: [Section; 5] = [
+    Section::Artists,
+    Section::Albums,
+    Section::Tracks,
+    Section::Days,
+    Section::Recent,
+];
+
+impl Section {
+    fn name(self) -> &'static str {
+        match self {
+            Section::Artists => "Artists",
+            Section::Albums => "Albums",
+            Section::Tracks => "Tracks",
+            Section::Days => "Days",
+            Section::Recent => "Recent",
+        }
+    }
+
+    fn index(self) -> usize {
+        SECTIONS.iter().position(|s| *s == self).unwrap_or(0)
+    }
+}
+
+// Top artists, albums and tracks and listening time per day over a period,
+// and the recently played songs, from the local history
+pub struct StatsView {
+    app: App,
+    history: History,
+    offset: UtcOffset, // local time, for telling days apart
+    plays: Arc<Vec<Play>>,
+    period: Period,
+    stats: Stats,
+    today: Option<Date>, // when stats were worked out
+    section: Section,
+    cursor: Cursor,
+    size: XY<usize>,
+}
+
+impl StatsView {
+    pub fn new(app: App, history: History, offset: UtcOffset) -> Self {
+        Self {
+            app,
+            history,
+            offset,
+            plays: Arc::default(),
+            period: Period::Week,
+            stats: Stats::default(),
+            today: None,
+            section: Section::Artists,
+            cursor: Cursor::default(),
+            size: XY::zero(),
+        }
+    }
+
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::from(SystemTime::now()).to_offset(self.offset)
+    }
+
+    fn list_height(&self) -> usize {
+        self.size.y.saturating_sub(ROW_OFFSET)
+    }
+
+    // Works the stats out again after a play was recorded, the period
+    // changed or the day went by
+    fn sync(&mut self, force: bool) {
+        let plays = self.history.plays();
+        let now = self.now();
+        if force || !Arc::ptr_eq(&plays, &self.plays) || self.today != Some(now.date()) {
+            self.stats = Stats::new(&plays, self.period, now);
+            self.plays = plays;
+            self.today = Some(now.date());
+            self.cursor.move_by(0, self.len());
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self.section {
+            Section::Artists => self.stats.artists.len(),
+            Section::Albums => self.stats.albums.len(),
+            Section::Tracks => self.stats.tracks.len(),
+            Section::Days => self.stats.days.len(),
+            Section::Recent => self.plays.len(),
+        }
+    }
+
+    fn select_section(&mut self, section: Section) {
+        if section != self.section {
+            self.section = section;
+            self.cursor = Cursor::default();
+        }
+    }
+
+    fn move_section(&mut self, delta: isize) {
+        let i = (self.section.index() as isize + delta).rem_euclid(SECTIONS.len() as isize);
+        self.select_section(SECTIONS[i as usize]);
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        self.cursor.move_by(delta, self.len());
+        self.cursor.scroll_into_view(self.list_height());
+    }
+
+    // The songs played under the selected row
+    fn selected_files(&self) -> Vec<&str> {
+        let i = self.cursor.selected;
+        let counts = match self.section {
+            Section::Artists => &self.stats.artists,
+            Section::Albums => &self.stats.albums,
+            Section::Tracks => &self.stats.tracks,
+            Section::Days => return vec![],
+            Section::Recent => {
+                return self
+                    .plays
+                    .iter()
+                    .rev()
+                    .nth(i)
+                    .map(|p| p.file.as_str())
+                    .into_iter()
+                    .collect();
+            }
+        };
+        counts
+            .get(i)
+            .map(|c| c.files.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    fn add_selected(&self) -> EventResult {
+        let files = self.selected_files();
+        if files.is_empty() {
+            return EventResult::Ignored;
+        }
+        match self.app.mpd.add(&files) {
+            Ok(()) if !self.app.mpd.online() => log!(
+                Level::Info,
+                "Offline: {} songs will be added when MPD is back",
+                files.len()
+            ),
+            Ok(()) => log!(Level::Info, "Added {} songs", files.len()),
+            Err(e) => log!(Level::Warn, "Failed to add songs: {}", e),
+        }
+        EventResult::Consumed(None)
+    }
+
+    fn click(&mut self, pos: XY<usize>) -> EventResult {
+        if pos.y < ROW_OFFSET {
+            let mut x = 0;
+            for section in SECTIONS {
+                x += section.name().width() + 2;
+                if pos.x < x {
+                    self.select_section(section);
+                    break;
+                }
+            }
+            return EventResult::Consumed(None);
+        }
+        let target = self.cursor.offset + pos.y - ROW_OFFSET;
+        if target < self.len() {
+            self.cursor.selected = target;
+        }
+        EventResult::Consumed(None)
+    }
+
+    // Each row as its left and right-aligned parts
+    fn rows(&self) -> Vec<(String, String)> {
+        let counts = match self.section {
+            Section::Artists => &self.stats.artists,
+            Section::Albums => &self.stats.albums,
+            Section::Tracks => &self.stats.tracks,
+            Section::Days => {
+                let longest = self.stats.days.iter().map(|(_, d)| *d).max();
+                let longest = longest.unwrap_or_default().as_secs_f64().max(1.0);
+                // the bar gets what the date and time leave of the width
+                let width = self.size.x.saturating_sub(10 + 2 + 9);
+                return self
+                    .stats
+                    .days
+                    .iter()
+                    .map(|(date, listened)| {
+                        let bar = (listened.as_secs_f64() / longest * width as f64).round();
+                        (
+                            format!("{}  {}", date, "█".repeat(bar as usize)),
+                            format_time(*listened),
+                        )
+                    })
+                    .collect();
+            }
+            Section::Recent => {
+                return self
+                    .plays
+                    .iter()
+                    .rev()
+                    .map(|p| {
+                        let when = OffsetDateTime::from_unix_timestamp(p.time as i64)
+                            .map(|t| {
+                                let t = t.to_offset(self.offset);
+                                format!("{} {:02}:{:02}", t.date(), t.hour(), t.minute())
+                            })
+                            .unwrap_or_default();
+                        let song = match &p.artist {
+                            Some(a) => format!("{} · {}", p.display_title(), a),
+                            None => p.display_title().into(),
+                        };
+                        (format!("{}  {}", when, song), format_time(p.listened()))
+                    })
+                    .collect();
+            }
+        };
+        counts
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                (
+                    format!("{:>3} {}", i + 1, c.name),
+                    format!("{} × {}", c.plays, format_time(c.listened)),
+                )
+            })
+            .collect()
+    }
+}
+
+impl View for StatsView {
+    fn draw(&self, printer: &Printer) {
+        let mut x = 0;
+        for section in SECTIONS {
+            let label = format!(" {} ", section.name());
+            let style = if section == self.section {
+                ColorStyle::highlight()
+            } else {
+                ColorStyle::secondary()
+            };
+            printer.with_color(style, |p| p.print((x, 0), &label));
+            x += label.width();
+        }
+        let summary = match self.section {
+            Section::Recent => format!("{} plays", self.plays.len()),
+            _ => format!(
+                "{} · {} plays · {}",
+                self.period.label(),
+                self.stats.plays,
+                format_time(self.stats.listened)
+            ),
+        };
+        if x + summary.width() < printer.size.x {
+            printer.with_color(ColorStyle::secondary(), |p| {
+                p.print((printer.size.x - summary.width(), 0), &summary)
+            });
+        }
+
+        if self.plays.is_empty() {
+            printer.print((0, ROW_OFFSET), "No plays recorded yet");
+            return;
+        }
+        let width = printer.size.x;
+        for (y, (left, right)) in self
+            .rows()
+            .into_iter()
+            .skip(self.cursor.offset)
+            .take(self.list_height())
+            .enumerate()
+        {
+            let pad = width.saturating_sub(left.width() + right.width());
+            let style = if y + self.cursor.offset == self.cursor.selected {
+                ColorStyle::highlight()
+            } else {
+                ColorStyle::primary()
+            };
+            printer.with_color(style, |p| {
+                p.print(
+                    (0, y + ROW_OFFSET),
+                    &format!("{}{}{}", left, " ".repeat(pad), right),
+                )
+            });
+        }
+    }
+
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+        self.sync(false);
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        match e {
+            Event::Key(Key::Up) | Event::Char('k') => self.move_selection(-1),
+            Event::Key(Key::Down) | Event::Char('j') => self.move_selection(1),
+            Event::Key(Key::PageUp) => self.move_selection(-(self.list_height() as isize)),
+            Event::Key(Key::PageDown) => self.move_selection(self.list_height() as isize),
+            Event::Key(Key::Left) | Event::Char('h') => self.move_section(-1),
+            Event::Key(Key::Right) | Event::Char('l') => self.move_section(1),
+            Event::Char('p') => {
+                self.period = self.period.next();
+                self.sync(true);
+            }
+            Event::Key(Key::Enter) | Event::Char('a') => return self.add_selected(),
+            Event::Mouse {
+                offset,
+                position,
+                event,
+            } => {
+                let Some(pos) = position.checked_sub(offset) else {
+                    return EventResult::Ignored;
+                };
+                match event {
+                    MouseEvent::Press(MouseButton::Left) => return self.click(pos),
+                    MouseEvent::WheelUp => self.move_selection(-3),
+                    MouseEvent::WheelDown => self.move_selection(3),
+                    _ => return EventResult::Ignored,
+                }
+            }
+            _ => return EventResult::Ignored,
+        }
+        EventResult::Consumed(None)
+    }
+}
@@ -1,21 +1,62 @@
-use cursive::{View, XY};
+use cursive::{theme::ColorStyle, Printer, View, XY};
+use unicode_width::UnicodeWidthStr;
 
 pub struct Titlebar {
-    title: String,
+    tabs: Vec<String>,
+    active: usize,
+}
+
+impl Default for Titlebar {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Titlebar {
-    pub fn new(title: String) -> Self {
-        Self { title }
+    pub fn new() -> Self {
+        Self {
+            tabs: vec![],
+            active: 0,
+        }
+    }
+
+    pub fn set_tabs(&mut self, tabs: Vec<String>, active: usize) {
+        self.tabs = tabs;
+        self.active = active;
+    }
+
+    pub fn set_active(&mut self, active: usize) {
+        self.active = active;
     }
 
-    pub fn set_title(&mut self, title: String) {
-        self.title = title;
+    // Index of the tab label drawn at column x
+    pub fn tab_at(&self, x: usize) -> Option<usize> {
+        let mut start = 0;
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let end = start + tab.width();
+            if x < end {
+                return Some(i);
+            }
+            start = end;
+        }
+        None
     }
 }
 
 impl View for Titlebar {
-    fn draw(&self, printer: &cursive::Printer) {
-        printer.print(XY::zero(), self.title.as_str());
+    fn draw(&self, printer: &Printer) {
+        let mut x = 0;
+        for (i, tab) in self.tabs.iter().enumerate() {
+            let color = if i == self.active {
+                ColorStyle::highlight()
+            } else {
+                ColorStyle::secondary()
+            };
+            printer.with_color(color, |p| p.print(XY::from((x, 0)), tab));
+            x += tab.width();
+        }
+        if printer.size.y > 1 {
+            printer.print_hline(XY::from((0, 1)), printer.size.x, "─");
+        }
     }
 }
@@ -0,0 +1,168 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cursive::{
+    event::{Event, EventResult},
+    theme::{ColorStyle, ColorType, PaletteColor},
+    Printer, View, XY,
+};
+
+use crate::app::App;
+use crate::config::VisualizerMode;
+use crate::visualizer::{Pcm, Spectrum, FFT_SIZE};
+
+// Bottom-aligned partial blocks, indexed by eighths filled
+const EIGHTHS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+// Columns per bar when the config leaves it to the width, gap included
+const BAR_WIDTH: usize = 3;
+// The scope shows this many of the latest samples across the width
+const SCOPE_SAMPLES: usize = 1024;
+
+pub struct VisualizerView {
+    app: App,
+    pcm: Option<Arc<Pcm>>,
+    spectrum: Spectrum,
+    mode: VisualizerMode,
+    levels: Vec<f32>,
+    scope: Vec<f32>,
+    updated: Option<Instant>,
+    size: XY<usize>,
+}
+
+impl VisualizerView {
+    // pcm is None without a fifo configured
+    pub fn new(app: App, pcm: Option<Arc<Pcm>>) -> Self {
+        let cfg = &app.config.visualizer;
+        Self {
+            spectrum: Spectrum::new(cfg.smoothing, cfg.falloff),
+            mode: cfg.mode,
+            app,
+            pcm,
+            levels: vec![],
+            scope: vec![],
+            updated: None,
+            size: XY::zero(),
+        }
+    }
+
+    fn bars(&self) -> usize {
+        match self.app.config.visualizer.bars {
+            0 => self.size.x.div_ceil(BAR_WIDTH),
+            n => n.min(self.size.x),
+        }
+    }
+
+    // The spectrum and scope heights in rows
+    fn split(&self, height: usize) -> (usize, usize) {
+        match self.mode {
+            VisualizerMode::Spectrum => (height, 0),
+            VisualizerMode::Scope => (0, height),
+            VisualizerMode::Both => (height - height / 2, height / 2),
+        }
+    }
+
+    fn update(&mut self) {
+        let Some(pcm) = &self.pcm else {
+            return;
+        };
+        let samples = pcm.latest(FFT_SIZE);
+        let now = Instant::now();
+        let dt = self.updated.map_or(Duration::ZERO, |t| now - t);
+        self.updated = Some(now);
+        self.levels = self
+            .spectrum
+            .update(&samples, pcm.rate, self.bars(), dt)
+            .to_vec();
+        self.scope = samples[FFT_SIZE - SCOPE_SAMPLES..].to_vec();
+    }
+
+    fn draw_spectrum(&self, printer: &Printer, style: ColorStyle) {
+        let (width, height) = (printer.size.x, printer.size.y);
+        let n = self.levels.len();
+        if n == 0 || height == 0 {
+            return;
+        }
+        printer.with_color(style, |p| {
+            for (i, level) in self.levels.iter().enumerate() {
+                // spread the columns evenly, leaving a gap if there's room
+                let x = i * width / n;
+                let w = (i + 1) * width / n - x;
+                let w = if w > 1 { w - 1 } else { w };
+                let eighths = (level * (height * 8) as f32).round() as usize;
+                for row in 0..height {
+                    let fill = eighths.saturating_sub(row * 8).min(8);
+                    if fill > 0 {
+                        p.print_hline((x, height - 1 - row), w, EIGHTHS[fill]);
+                    }
+                }
+            }
+        });
+    }
+
+    // One dot per column, at half-row resolution
+    fn draw_scope(&self, printer: &Printer, style: ColorStyle) {
+        let (width, height) = (printer.size.x, printer.size.y);
+        if self.scope.is_empty() || height == 0 {
+            return;
+        }
+        let halves = height * 2;
+        printer.with_color(style, |p| {
+            for x in 0..width {
+                let v = self.scope[x * self.scope.len() / width].clamp(-1.0, 1.0);
+                let half = ((1.0 - v) / 2.0 * (halves - 1) as f32).round() as usize;
+                p.print(
+                    (x, half / 2),
+                    if half.is_multiple_of(2) { "▀" } else { "▄" },
+                );
+            }
+        });
+    }
+}
+
+impl View for VisualizerView {
+    fn draw(&self, printer: &Printer) {
+        let Some(pcm) = &self.pcm else {
+            let note = "Set visualizer.fifo to an MPD fifo output";
+            let x = printer.size.x.saturating_sub(note.len()) / 2;
+            printer.with_color(ColorStyle::secondary(), |p| {
+                p.print((x, printer.size.y / 2), note)
+            });
+            return;
+        };
+        pcm.mark_drawn();
+
+        let palette = &printer.theme.palette;
+        let front = palette
+            .custom("visualizer")
+            .map(|c| ColorType::Color(*c))
+            .unwrap_or(ColorType::Palette(PaletteColor::Highlight));
+        let style = ColorStyle::new(front, ColorType::Palette(PaletteColor::View));
+
+        let (top, bottom) = self.split(printer.size.y);
+        self.draw_spectrum(&printer.cropped((printer.size.x, top)), style);
+        self.draw_scope(
+            &printer.offset((0, top)).cropped((printer.size.x, bottom)),
+            style,
+        );
+    }
+
+    fn layout(&mut self, size: XY<usize>) {
+        self.size = size;
+    }
+
+    fn on_event(&mut self, e: Event) -> EventResult {
+        match e {
+            Event::Refresh => {
+                self.update();
+                EventResult::Ignored
+            }
+            Event::Char('m') => {
+                self.mode = self.mode.next();
+                EventResult::Consumed(None)
+            }
+            _ => EventResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::Read,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use cursive::{event::Event, CbSink};
+use log::{log, Level};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+// Samples per FFT, about 46ms at 44.1kHz
+pub const FFT_SIZE: usize = 2048;
+// Samples older than this count as silence, so bars drop once MPD pauses
+const STALE: Duration = Duration::from_millis(200);
+// The UI is redrawn at most this often while audio flows
+const FRAME: Duration = Duration::from_millis(33);
+// Nobody is looking if the view hasn't been drawn for this long
+const UNWATCHED: Duration = Duration::from_secs(1);
+const REOPEN_DELAY: Duration = Duration::from_millis(500);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+// Frequencies the bars cover, spaced logarithmically
+const LOW_HZ: f32 = 40.0;
+const HIGH_HZ: f32 = 16000.0;
+// Levels this far below full scale are drawn empty
+const RANGE_DB: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sample {
+    I16,
+    I32,
+    F32,
+}
+
+// The fifo output's format, as MPD writes it: interleaved, native endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub rate: u32,
+    pub sample: Sample,
+    pub channels: usize,
+}
+
+impl Format {
+    // rate:bits:channels as in mpd.conf, e.g. 44100:16:2 or 48000:f:2
+    pub fn parse(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let [rate, bits, channels] = parts[..] else {
+            bail!("Expected rate:bits:channels, got {}", s);
+        };
+        let sample = match bits {
+            "16" => Sample::I16,
+            "32" => Sample::I32,
+            "f" => Sample::F32,
+            b => bail!("Unsupported sample format {}", b),
+        };
+        let format = Self {
+            rate: rate
+                .parse()
+                .map_err(|_| anyhow!("Bad sample rate {}", rate))?,
+            sample,
+            channels: channels
+                .parse()
+                .map_err(|_| anyhow!("Bad channel count {}", channels))?,
+        };
+        if format.rate == 0 || format.channels == 0 {
+            bail!("Invalid format {}", s);
+        }
+        Ok(format)
+    }
+
+    fn sample_bytes(&self) -> usize {
+        match self.sample {
+            Sample::I16 => 2,
+            Sample::I32 | Sample::F32 => 4,
+        }
+    }
+
+    pub fn frame_bytes(&self) -> usize {
+        self.sample_bytes() * self.channels
+    }
+
+    // Whole frames in bytes, mixed down to mono in -1..1
+    pub fn decode(&self, bytes: &[u8]) -> Vec<f32> {
+        let width = self.sample_bytes();
+        bytes
+            .chunks_exact(self.frame_bytes())
+            .map(|frame| {
+                let sum: f32 = frame
+                    .chunks_exact(width)
+                    .map(|s| match self.sample {
+                        Sample::I16 => i16::from_ne_bytes([s[0], s[1]]) as f32 / 32768.0,
+                        Sample::I32 => {
+                            i32::from_ne_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2147483648.0
+                        }
+                        Sample::F32 => f32::from_ne_bytes([s[0], s[1], s[2], s[3]]),
+                    })
+                    .sum();
+                sum / self.channels as f32
+            })
+            .collect()
+    }
+}
+
+// The most recent audio, shared between the reader and the view
+pub struct Pcm {
+    pub rate: u32,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    samples: VecDeque<f32>,
+    received: Option<Instant>,
+    drawn: Option<Instant>,
+}
+
+impl Pcm {
+    pub fn new(rate: u32) -> Self {
+        Self {
+            rate,
+            inner: Mutex::new(Inner {
+                samples: VecDeque::with_capacity(FFT_SIZE),
+                received: None,
+                drawn: None,
+            }),
+        }
+    }
+
+    pub fn push(&self, samples: &[f32]) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.samples.extend(samples);
+        let excess = inner.samples.len().saturating_sub(FFT_SIZE);
+        inner.samples.drain(..excess);
+        inner.received = Some(Instant::now());
+    }
+
+    // The last n samples, oldest first. Silence if nothing came lately.
+    pub fn latest(&self, n: usize) -> Vec<f32> {
+        let inner = self.inner.lock().unwrap();
+        let mut out = vec![0.0; n];
+        if inner.received.is_none_or(|t| t.elapsed() > STALE) {
+            return out;
+        }
+        let have = inner.samples.len().min(n);
+        let skip = inner.samples.len() - have;
+        for (o, s) in out[n - have..]
+            .iter_mut()
+            .zip(inner.samples.iter().skip(skip))
+        {
+            *o = *s;
+        }
+        out
+    }
+
+    // Called when drawn, so the reader only drives redraws someone sees
+    pub fn mark_drawn(&self) {
+        self.inner.lock().unwrap().drawn = Some(Instant::now());
+    }
+
+    fn watched(&self) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .drawn
+            .is_some_and(|t| t.elapsed() < UNWATCHED)
+    }
+}
+
+// Reads the fifo on a thread of its own, for as long as the UI is up.
+// Opening a fifo blocks until MPD opens its end, and MPD closes it when
+// playback stops, so the reader just opens it again.
+pub fn spawn(path: PathBuf, format: Format, sink: CbSink) -> Arc<Pcm> {
+    let pcm = Arc::new(Pcm::new(format.rate));
+    let shared = pcm.clone();
+    thread::Builder::new()
+        .name(String::from("fifo"))
+        .spawn(move || {
+            let mut warned = false;
+            loop {
+                match read(&path, format, &shared, &sink) {
+                    Ok(true) => thread::sleep(REOPEN_DELAY),
+                    Ok(false) => return,
+                    Err(e) => {
+                        if !warned {
+                            log!(Level::Warn, "Can't read {}: {}", path.display(), e);
+                            warned = true;
+                        }
+                        thread::sleep(RETRY_DELAY);
+                    }
+                }
+            }
+        })
+        .expect("Failed to spawn fifo thread");
+    pcm
+}
+
+// Ok(true) when the writer went away, Ok(false) once the UI has
+fn read(path: &PathBuf, format: Format, pcm: &Pcm, sink: &CbSink) -> Result<bool> {
+    let mut file = File::open(path)?;
+    let frame = format.frame_bytes();
+    let mut buf = vec![0; frame * 512];
+    let mut filled = 0;
+    let mut refreshed = Instant::now();
+    loop {
+        let n = file.read(&mut buf[filled..])?;
+        if n == 0 {
+            return Ok(true);
+        }
+        filled += n;
+        let whole = filled - filled % frame;
+        pcm.push(&format.decode(&buf[..whole]));
+        // keep a partial frame for the next read
+        buf.copy_within(whole..filled, 0);
+        filled -= whole;
+
+        if pcm.watched() && refreshed.elapsed() >= FRAME {
+            refreshed = Instant::now();
+            if sink.send(Box::new(|s| s.on_event(Event::Refresh))).is_err() {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+// Bar heights from 0 to 1 out of the latest samples
+pub struct Spectrum {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    smoothing: f32,
+    falloff: f32,
+    smoothed: Vec<f32>,
+    levels: Vec<f32>,
+}
+
+impl Spectrum {
+    pub fn new(smoothing: f32, falloff: f32) -> Self {
+        // Hann, which keeps a tone from smearing into neighbouring bars
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = std::f32::consts::PI * i as f32 / (FFT_SIZE - 1) as f32;
+                x.sin().powi(2)
+            })
+            .collect();
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            smoothing: smoothing.clamp(0.0, 0.99),
+            falloff: falloff.max(0.0),
+            smoothed: vec![],
+            levels: vec![],
+        }
+    }
+
+    // dt is the time since the last update, for the falloff
+    pub fn update(&mut self, samples: &[f32], rate: u32, bars: usize, dt: Duration) -> &[f32] {
+        if self.levels.len() != bars {
+            self.smoothed = vec![0.0; bars];
+            self.levels = vec![0.0; bars];
+        }
+        let mut buf: Vec<Complex<f32>> = samples
+            .iter()
+            .chain(std::iter::repeat(&0.0))
+            .zip(&self.window)
+            .map(|(s, w)| Complex::new(s * w, 0.0))
+            .collect();
+        self.fft.process(&mut buf);
+
+        // scaled so a full scale sine peaks at 1
+        let gain: f32 = 2.0 / self.window.iter().sum::<f32>();
+        let bin_hz = rate as f32 / FFT_SIZE as f32;
+        let high = HIGH_HZ.min(rate as f32 / 2.0);
+        let edge = |i: usize| LOW_HZ * (high / LOW_HZ).powf(i as f32 / bars as f32);
+        let drop = self.falloff * dt.as_secs_f32();
+        for i in 0..bars {
+            let lo = (edge(i) / bin_hz) as usize;
+            let hi = ((edge(i + 1) / bin_hz).ceil() as usize).clamp(lo + 1, FFT_SIZE / 2);
+            let peak = buf[lo.min(hi - 1)..hi]
+                .iter()
+                .map(|c| c.norm() * gain)
+                .fold(0.0, f32::max);
+            let db = 20.0 * peak.max(1e-9).log10();
+            let level = ((db + RANGE_DB) / RANGE_DB).clamp(0.0, 1.0);
+
+            let s = &mut self.smoothed[i];
+            *s = *s * self.smoothing + level * (1.0 - self.smoothing);
+            self.levels[i] = s.max(self.levels[i] - drop);
+        }
+        &self.levels
+    }
+}
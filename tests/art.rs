@@ -0,0 +1,206 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use common::{eventually, server};
+use cursive::reexports::crossbeam_channel::unbounded;
+use image::{ImageFormat, Rgba, RgbaImage};
+use mpcursive::art::{
+    fetch,
+    graphics::{from_env, kitty, sixel, Protocol},
+    Cover, Covers,
+};
+use mpcursive::meta::SongMeta;
+use mpd::Song;
+
+fn png(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+    let mut out = Cursor::new(vec![]);
+    RgbaImage::from_pixel(width, height, Rgba(color))
+        .write_to(&mut out, ImageFormat::Png)
+        .unwrap();
+    out.into_inner()
+}
+
+fn meta(file: &str, album: &str) -> SongMeta {
+    SongMeta::new(Song {
+        file: file.into(),
+        tags: vec![
+            ("Artist".into(), "Band".into()),
+            ("Album".into(), album.into()),
+        ],
+        ..Default::default()
+    })
+}
+
+fn loaded(covers: &Covers, song: &SongMeta) -> Cover {
+    let mut cover = covers.get(song);
+    eventually("the cover to load", || {
+        cover = covers.get(song);
+        !matches!(cover, Cover::Loading)
+    });
+    cover
+}
+
+#[test]
+fn covers_are_read_in_chunks() {
+    let (fake, app) = server();
+    // fetch doesn't decode, so any bytes will do
+    let data: Vec<u8> = (0..600_000).map(|i| (i % 251) as u8).collect();
+    fake.state()
+        .covers
+        .insert("Band/Record".into(), data.clone());
+
+    let fetched = fetch(&app.mpd, "Band/Record/01.flac").unwrap();
+    assert_eq!(fetched, Some(data));
+    let s = fake.state();
+    assert_eq!(s.commands("binarylimit"), ["binarylimit \"262144\""]);
+    assert_eq!(
+        s.commands("albumart"),
+        [
+            "albumart \"Band/Record/01.flac\" \"0\"",
+            "albumart \"Band/Record/01.flac\" \"262144\"",
+            "albumart \"Band/Record/01.flac\" \"524288\"",
+        ]
+    );
+}
+
+#[test]
+fn embedded_pictures_come_first() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.covers.insert("Band/Record".into(), png(2, 2, [0; 4]));
+        s.pictures
+            .insert("Band/Record/01.flac".into(), png(3, 3, [255; 4]));
+    }
+    let covers = Covers::new(app.mpd.clone(), unbounded().0);
+
+    let Cover::Image(image) = loaded(&covers, &meta("Band/Record/01.flac", "Record")) else {
+        panic!("no cover");
+    };
+    assert_eq!(image.dimensions(), (3, 3));
+    assert!(fake.state().commands("albumart").is_empty());
+
+    // a file without a picture still has the directory's cover
+    let Cover::Image(image) = loaded(&covers, &meta("Band/Record/02.flac", "Bonus")) else {
+        panic!("no cover");
+    };
+    assert_eq!(image.dimensions(), (2, 2));
+}
+
+#[test]
+fn covers_are_cached_per_album() {
+    let (fake, app) = server();
+    fake.state()
+        .covers
+        .insert("Band/Record".into(), png(4, 4, [9, 9, 9, 255]));
+    let covers = Covers::new(app.mpd.clone(), unbounded().0);
+
+    assert!(matches!(
+        loaded(&covers, &meta("Band/Record/01.flac", "Record")),
+        Cover::Image(_)
+    ));
+    let asked = fake.state().log.len();
+    assert!(matches!(
+        covers.get(&meta("Band/Record/02.flac", "Record")),
+        Cover::Image(_)
+    ));
+    assert_eq!(fake.state().log.len(), asked);
+
+    // and an album without one isn't asked for again either
+    assert!(matches!(
+        loaded(&covers, &meta("Band/Bare/01.flac", "Bare")),
+        Cover::Missing
+    ));
+    let asked = fake.state().log.len();
+    assert!(matches!(
+        covers.get(&meta("Band/Bare/02.flac", "Bare")),
+        Cover::Missing
+    ));
+    assert_eq!(fake.state().log.len(), asked);
+}
+
+#[test]
+fn unreadable_covers_are_missing() {
+    let (fake, app) = server();
+    fake.state()
+        .covers
+        .insert("Band/Record".into(), b"not an image".to_vec());
+    let covers = Covers::new(app.mpd.clone(), unbounded().0);
+    assert!(matches!(
+        loaded(&covers, &meta("Band/Record/01.flac", "Record")),
+        Cover::Missing
+    ));
+}
+
+#[test]
+fn failed_fetches_are_not_retried_at_once() {
+    let (fake, app) = server();
+    fake.state()
+        .covers
+        .insert("Band/Record".into(), png(4, 4, [0, 0, 255, 255]));
+    fake.state().drop_on("readpicture");
+    let covers = Covers::new(app.mpd.clone(), unbounded().0);
+    let song = meta("Band/Record/01.flac", "Record");
+    assert!(matches!(loaded(&covers, &song), Cover::Missing));
+    for _ in 0..10 {
+        assert!(matches!(covers.get(&song), Cover::Missing));
+    }
+    assert_eq!(fake.state().commands("readpicture").len(), 1);
+}
+
+#[test]
+fn sixel_encoding() {
+    let white = RgbaImage::from_pixel(2, 6, Rgba([255; 4]));
+    let out = sixel(&white);
+    assert!(out.starts_with("\x1bP0;1;0q\"1;1;2;6#0;2;0;0;0"));
+    // one band of six rows, all set, in the brightest color
+    assert!(out.ends_with("#215~~-\x1b\\"));
+
+    // runs of more than three are compressed, colors in a band split by $
+    let mut striped = RgbaImage::from_pixel(5, 1, Rgba([0, 0, 0, 255]));
+    striped.put_pixel(4, 0, Rgba([255, 0, 0, 255]));
+    let out = sixel(&striped);
+    assert!(out.ends_with("#0!4@?$#180!4?@-\x1b\\"));
+}
+
+#[test]
+fn kitty_encoding() {
+    let small = kitty(&RgbaImage::new(1, 1), 7, (1, 1).into());
+    assert_eq!(
+        small,
+        "\x1b_Ga=T,f=32,s=1,v=1,i=7,c=1,r=1,C=1,q=2,m=0;AAAAAA==\x1b\\"
+    );
+
+    // 64x64 RGBA is 16384 bytes, so 21848 of base64 in six chunks
+    let large = kitty(&RgbaImage::new(64, 64), 2, (8, 4).into());
+    let chunks: Vec<&str> = large.split_inclusive("\x1b\\").collect();
+    assert_eq!(chunks.len(), 6);
+    assert!(chunks[0].starts_with("\x1b_Ga=T,f=32,s=64,v=64,i=2,c=8,r=4,C=1,q=2,m=1;"));
+    assert!(chunks[1..5].iter().all(|c| c.starts_with("\x1b_Gm=1;")));
+    assert!(chunks[5].starts_with("\x1b_Gm=0;"));
+}
+
+#[test]
+fn protocol_detection() {
+    let detect = |vars: &[(&str, &str)]| {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        from_env(|k| vars.get(k).cloned())
+    };
+    assert_eq!(detect(&[("TERM", "xterm-256color")]), Protocol::HalfBlock);
+    assert_eq!(detect(&[("TERM", "xterm-kitty")]), Protocol::Kitty);
+    assert_eq!(
+        detect(&[("TERM", "xterm-256color"), ("TERM_PROGRAM", "WezTerm")]),
+        Protocol::Kitty
+    );
+    assert_eq!(detect(&[("TERM", "foot-extra")]), Protocol::Sixel);
+    assert_eq!(detect(&[("TERM", "mlterm")]), Protocol::Sixel);
+    assert_eq!(
+        detect(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux-0/default")]),
+        Protocol::HalfBlock
+    );
+}
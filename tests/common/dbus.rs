@@ -0,0 +1,115 @@
+// A private message bus, so tests don't touch the desktop's session bus.
+// Needs dbus-daemon; without it there is no bus and the tests say so.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use zbus::{blocking::Connection, message::Message, MatchRule};
+
+pub struct Bus {
+    pub address: String,
+    daemon: Child,
+}
+
+impl Bus {
+    // Listening on a socket in dir
+    pub fn start(dir: &Path) -> Option<Self> {
+        fs::create_dir_all(dir).unwrap();
+        let config = dir.join("bus.conf");
+        fs::write(
+            &config,
+            format!(
+                r#"<busconfig>
+  <type>session</type>
+  <listen>unix:dir={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#,
+                dir.display()
+            ),
+        )
+        .unwrap();
+        let daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut daemon) = daemon else {
+            eprintln!("no dbus-daemon, skipping");
+            return None;
+        };
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Some(Self {
+            address: address.trim().into(),
+            daemon,
+        })
+    }
+
+    pub fn connect(&self) -> Connection {
+        zbus::blocking::connection::Builder::address(self.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    // Signals matching rule, as they arrive on a connection of their own
+    pub fn signals(&self, rule: &str) -> Signals {
+        let conn = self.connect();
+        let rule = MatchRule::try_from(rule).unwrap().to_owned();
+        let messages = zbus::blocking::MessageIterator::for_match_rule(rule, &conn, None).unwrap();
+        let (send, receive) = mpsc::channel();
+        thread::spawn(move || {
+            let _conn = conn;
+            for m in messages.flatten() {
+                if send.send(m).is_err() {
+                    return;
+                }
+            }
+        });
+        Signals(receive)
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+pub struct Signals(mpsc::Receiver<Message>);
+
+impl Signals {
+    // The next signal named member, skipping others
+    pub fn next(&self, member: &str) -> Message {
+        loop {
+            let m = self
+                .0
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap_or_else(|_| panic!("timed out waiting for {}", member));
+            if m.header().member().is_some_and(|n| n.as_str() == member) {
+                return m;
+            }
+        }
+    }
+
+    // Whether anything arrived within a moment
+    pub fn quiet(&self) -> bool {
+        self.0.recv_timeout(Duration::from_millis(200)).is_err()
+    }
+}
@@ -0,0 +1,1061 @@
+#![allow(dead_code)]
+
+// A scriptable, in-process stand-in for MPD. It speaks enough of the text
+// protocol for the client (status, queue, playback, idle, the database and
+// command lists), keeps everything in memory, and can be told to fail
+// commands or drop connections.
+
+pub mod dbus;
+pub mod http;
+pub mod screen;
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use mpcursive::{
+    app::App,
+    clipboard::Clipboard,
+    config::{Config, MpdConfig},
+    mpd_util::MPD,
+    notice::Notices,
+};
+
+const POLL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FakeSong {
+    pub file: String,
+    pub tags: Vec<(String, String)>,
+    pub duration: f64,
+}
+
+impl FakeSong {
+    pub fn new(file: &str, tags: &[(&str, &str)], duration: f64) -> Self {
+        Self {
+            file: file.into(),
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            duration,
+        }
+    }
+
+    pub fn tag(&self, key: &str) -> Vec<&str> {
+        self.tags
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    fn pairs(&self) -> Vec<(String, String)> {
+        let mut out = vec![(String::from("file"), self.file.clone())];
+        out.extend(self.tags.iter().cloned());
+        out.push((
+            String::from("Time"),
+            (self.duration.round() as u64).to_string(),
+        ));
+        out.push((String::from("duration"), format!("{:.3}", self.duration)));
+        out
+    }
+}
+
+// Builds a database of albums: artist i has albums j with tracks k, as
+// "Artist i/Album j/k.flac"
+pub fn library(artists: usize, albums: usize, tracks: usize) -> Vec<FakeSong> {
+    let mut songs = vec![];
+    for a in 0..artists {
+        for b in 0..albums {
+            for t in 0..tracks {
+                let artist = format!("Artist {}", a);
+                let album = format!("Album {}", b);
+                let title = format!("Song {}-{}-{}", a, b, t);
+                let track = (t + 1).to_string();
+                songs.push(FakeSong::new(
+                    &format!("{}/{}/{:02}.flac", artist, album, t + 1),
+                    &[
+                        ("Artist", &artist),
+                        ("Album", &album),
+                        ("Title", &title),
+                        ("Track", &track),
+                        ("Date", "2001"),
+                    ],
+                    180.0 + t as f64,
+                ));
+            }
+        }
+    }
+    songs
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u32,
+    pub song: FakeSong,
+    version: u32, // playlist version this position last changed in
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    Play,
+    Pause,
+    Stop,
+}
+
+pub struct State {
+    pub db: Vec<FakeSong>,
+    pub db_update: i64,
+    pub queue: Vec<Entry>,
+    next_id: u32,
+    pub version: u32,
+    pub play: Playback,
+    pub current: Option<usize>,
+    pub elapsed: f64,
+    pub repeat: bool,
+    pub random: bool,
+    pub single: bool,
+    pub consume: bool,
+    pub volume: i32,
+    // every command received, with its arguments, for assertions
+    pub log: Vec<String>,
+    failures: HashMap<String, (u32, String)>,
+    drops: HashMap<String, usize>,
+    holds: HashSet<String>,
+    offline: bool,
+    events: Vec<&'static str>,
+    // cover images: albumart looks up a song's directory, readpicture the
+    // song file itself
+    pub covers: HashMap<String, Vec<u8>>,
+    pub pictures: HashMap<String, Vec<u8>>,
+    pub binary_limit: usize, // for connections that don't set their own
+    // every tag in a file, by file, for readcomments
+    pub comments: HashMap<String, Pairs>,
+    // song stickers by file, as name and value
+    pub stickers: HashMap<String, Pairs>,
+    // commands are refused until a connection sends it
+    pub password: Option<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            db: vec![],
+            db_update: 1,
+            queue: vec![],
+            next_id: 1,
+            version: 1,
+            play: Playback::Stop,
+            current: None,
+            elapsed: 0.0,
+            repeat: false,
+            random: false,
+            single: false,
+            consume: false,
+            volume: 50,
+            log: vec![],
+            failures: HashMap::new(),
+            drops: HashMap::new(),
+            holds: HashSet::new(),
+            offline: false,
+            events: vec![],
+            covers: HashMap::new(),
+            pictures: HashMap::new(),
+            binary_limit: 8192,
+            comments: HashMap::new(),
+            stickers: HashMap::new(),
+            password: None,
+        }
+    }
+}
+
+type Ack = (u32, String);
+type Pairs = Vec<(String, String)>;
+
+impl State {
+    // Wakes idle clients waiting on subsystem
+    pub fn emit(&mut self, subsystem: &'static str) {
+        self.events.push(subsystem);
+    }
+
+    // The next matching command answers with ACK [code@0] {command} message
+    pub fn fail(&mut self, command: &str, code: u32, message: &str) {
+        self.failures.insert(command.into(), (code, message.into()));
+    }
+
+    // The connection is closed when command next arrives, without an
+    // answer. command may also be a whole line, arguments quoted. Calls add
+    // up.
+    pub fn drop_on(&mut self, command: &str) {
+        *self.drops.entry(command.into()).or_default() += 1;
+    }
+
+    // A connection that receives command waits before answering it, until
+    // release
+    pub fn hold(&mut self, command: &str) {
+        self.holds.insert(command.into());
+    }
+
+    pub fn release(&mut self, command: &str) {
+        self.holds.remove(command);
+    }
+
+    // Offline, existing connections are closed and new ones hung up on
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    pub fn queue_files(&self) -> Vec<&str> {
+        self.queue.iter().map(|e| e.song.file.as_str()).collect()
+    }
+
+    pub fn commands(&self, name: &str) -> Vec<&str> {
+        self.log
+            .iter()
+            .filter(|l| l.split(' ').next() == Some(name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn set_queue(&mut self, songs: Vec<FakeSong>) {
+        self.queue.clear();
+        self.current = None;
+        self.insert(0, songs);
+    }
+
+    pub fn insert(&mut self, pos: usize, songs: Vec<FakeSong>) -> Vec<u32> {
+        let mut ids = vec![];
+        let tail = self.queue.split_off(pos);
+        for song in songs {
+            ids.push(self.next_id);
+            self.queue.push(Entry {
+                id: self.next_id,
+                song,
+                version: 0,
+            });
+            self.next_id += 1;
+        }
+        self.queue.extend(tail);
+        self.queue_changed(pos);
+        ids
+    }
+
+    pub fn delete(&mut self, range: std::ops::Range<usize>) {
+        let start = range.start;
+        self.queue.drain(range.clone());
+        self.current = match self.current {
+            Some(c) if range.contains(&c) => None,
+            Some(c) if c >= range.end => Some(c - range.len()),
+            c => c,
+        };
+        self.queue_changed(start);
+    }
+
+    // Replaces a queued song's tags in place, keeping its id, as MPD does
+    // when a stream sends new tags
+    pub fn retag(&mut self, pos: usize, tags: &[(&str, &str)]) {
+        let duration = self.queue[pos].song.duration;
+        let file = self.queue[pos].song.file.clone();
+        self.queue[pos].song = FakeSong::new(&file, tags, duration);
+        self.version += 1;
+        self.queue[pos].version = self.version;
+        self.emit("playlist");
+    }
+
+    // Every position from pos on counts as changed in the new version
+    fn queue_changed(&mut self, pos: usize) {
+        self.version += 1;
+        for e in &mut self.queue[pos..] {
+            e.version = self.version;
+        }
+        self.emit("playlist");
+    }
+
+    fn play_at(&mut self, pos: usize) {
+        self.current = Some(pos);
+        self.elapsed = 0.0;
+        self.play = Playback::Play;
+        self.emit("player");
+    }
+
+    fn status(&self) -> Pairs {
+        let b = |v: bool| String::from(if v { "1" } else { "0" });
+        let mut out = vec![
+            ("volume".into(), self.volume.to_string()),
+            ("repeat".into(), b(self.repeat)),
+            ("random".into(), b(self.random)),
+            ("single".into(), b(self.single)),
+            ("consume".into(), b(self.consume)),
+            ("playlist".into(), self.version.to_string()),
+            ("playlistlength".into(), self.queue.len().to_string()),
+            (
+                "state".into(),
+                match self.play {
+                    Playback::Play => "play",
+                    Playback::Pause => "pause",
+                    Playback::Stop => "stop",
+                }
+                .into(),
+            ),
+        ];
+        if let Some(e) = self.current.and_then(|c| self.queue.get(c)) {
+            let pos = self.current.unwrap();
+            out.push(("song".into(), pos.to_string()));
+            out.push(("songid".into(), e.id.to_string()));
+            if self.play != Playback::Stop {
+                out.push((
+                    "time".into(),
+                    format!("{}:{}", self.elapsed as u64, e.song.duration as u64),
+                ));
+                out.push(("elapsed".into(), format!("{:.3}", self.elapsed)));
+                out.push(("duration".into(), format!("{:.3}", e.song.duration)));
+                out.push(("bitrate".into(), String::from("320")));
+                out.push(("audio".into(), String::from("44100:16:2")));
+            }
+        }
+        out
+    }
+
+    fn entry(&self, pos: usize) -> Pairs {
+        let e = &self.queue[pos];
+        let mut out = e.song.pairs();
+        out.push(("Pos".into(), pos.to_string()));
+        out.push(("Id".into(), e.id.to_string()));
+        out
+    }
+
+    fn matches(song: &FakeSong, filters: &[String], exact: bool) -> bool {
+        filters.chunks(2).all(|f| {
+            let [tag, value] = f else {
+                return false;
+            };
+            let values: Vec<&str> = match tag.to_lowercase().as_str() {
+                "file" => vec![song.file.as_str()],
+                "any" => song.tags.iter().map(|(_, v)| v.as_str()).collect(),
+                _ => song.tag(tag),
+            };
+            values.iter().any(|v| {
+                if exact {
+                    v == value
+                } else {
+                    v.to_lowercase().contains(&value.to_lowercase())
+                }
+            })
+        })
+    }
+
+    // albumart and readpicture: one chunk of the image from an offset
+    fn binary(
+        &mut self,
+        command: &str,
+        args: &[String],
+        limit: Option<usize>,
+    ) -> Result<Vec<u8>, Ack> {
+        if let Some(ack) = self.failures.remove(command) {
+            return Err(ack);
+        }
+        let (Some(uri), Some(offset)) = (args.first(), args.get(1)) else {
+            return Err((2, String::from("too few arguments")));
+        };
+        let offset: usize = offset
+            .parse()
+            .map_err(|_| (2, format!("Integer expected: {}", offset)))?;
+        let data = match command {
+            "albumart" => {
+                let dir = uri.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+                match self.covers.get(dir) {
+                    Some(d) => d,
+                    None => return Err((50, String::from("No file exists"))),
+                }
+            }
+            _ => match self.pictures.get(uri.as_str()) {
+                Some(d) => d,
+                None => return Ok(b"OK\n".to_vec()),
+            },
+        };
+        if offset > data.len() {
+            return Err((2, String::from("Bad file offset")));
+        }
+        let limit = limit.unwrap_or(self.binary_limit);
+        let chunk = &data[offset..data.len().min(offset + limit)];
+        let mut out = format!("size: {}\n", data.len());
+        if command == "readpicture" {
+            out.push_str("type: image/png\n");
+        }
+        out.push_str(&format!("binary: {}\n", chunk.len()));
+        let mut out = out.into_bytes();
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\nOK\n");
+        Ok(out)
+    }
+
+    fn exec(&mut self, command: &str, args: &[String]) -> Result<Pairs, Ack> {
+        if let Some(ack) = self.failures.remove(command) {
+            return Err(ack);
+        }
+        let arg = |i: usize| -> Result<&str, Ack> {
+            args.get(i)
+                .map(String::as_str)
+                .ok_or((2, String::from("too few arguments")))
+        };
+        let num = |i: usize| -> Result<usize, Ack> {
+            arg(i)?
+                .parse()
+                .map_err(|_| (2, format!("Integer expected: {}", args[i])))
+        };
+        let float = |i: usize| -> Result<f64, Ack> {
+            arg(i)?
+                .parse()
+                .map_err(|_| (2, format!("Number expected: {}", args[i])))
+        };
+        let flag = |i: usize| -> Result<bool, Ack> { Ok(num(i)? != 0) };
+        let bad_index = || (2, String::from("Bad song index"));
+
+        let mut out: Pairs = vec![];
+        match command {
+            "ping" => {}
+            "status" => out = self.status(),
+            "replay_gain_status" => out.push(("replay_gain_mode".into(), "off".into())),
+            "stats" => {
+                let artists: HashSet<&str> = self.db.iter().flat_map(|s| s.tag("Artist")).collect();
+                let albums: HashSet<&str> = self.db.iter().flat_map(|s| s.tag("Album")).collect();
+                out = vec![
+                    ("artists".into(), artists.len().to_string()),
+                    ("albums".into(), albums.len().to_string()),
+                    ("songs".into(), self.db.len().to_string()),
+                    ("uptime".into(), "1".into()),
+                    ("playtime".into(), "0".into()),
+                    (
+                        "db_playtime".into(),
+                        (self.db.iter().map(|s| s.duration).sum::<f64>() as u64).to_string(),
+                    ),
+                    ("db_update".into(), self.db_update.to_string()),
+                ];
+            }
+            "currentsong" => {
+                if let Some(c) = self.current.filter(|c| *c < self.queue.len()) {
+                    out = self.entry(c);
+                }
+            }
+            "playlistinfo" => {
+                let range = match args.first() {
+                    None => 0..self.queue.len(),
+                    Some(r) => match r.split_once(':') {
+                        Some((s, e)) => {
+                            let s: usize = s.parse().map_err(|_| bad_index())?;
+                            let e: usize = if e.is_empty() {
+                                self.queue.len()
+                            } else {
+                                e.parse().map_err(|_| bad_index())?
+                            };
+                            s..e.min(self.queue.len())
+                        }
+                        None => {
+                            let p = num(0)?;
+                            p..p + 1
+                        }
+                    },
+                };
+                if range.start > self.queue.len()
+                    || (range.start == self.queue.len() && !range.is_empty())
+                {
+                    return Err(bad_index());
+                }
+                for pos in range {
+                    out.extend(self.entry(pos));
+                }
+            }
+            "playlistid" => {
+                let positions = match args.first() {
+                    None => 0..self.queue.len(),
+                    Some(_) => {
+                        let id = num(0)? as u32;
+                        let pos = self
+                            .queue
+                            .iter()
+                            .position(|e| e.id == id)
+                            .ok_or((50, String::from("No such song")))?;
+                        pos..pos + 1
+                    }
+                };
+                for pos in positions {
+                    out.extend(self.entry(pos));
+                }
+            }
+            "plchanges" | "plchangesposid" => {
+                let since = num(0)? as u32;
+                for (pos, e) in self.queue.iter().enumerate() {
+                    if e.version > since {
+                        if command == "plchanges" {
+                            out.extend(self.entry(pos));
+                        } else {
+                            out.push(("cpos".into(), pos.to_string()));
+                            out.push(("Id".into(), e.id.to_string()));
+                        }
+                    }
+                }
+            }
+            "add" | "addid" => {
+                let uri = arg(0)?;
+                let songs: Vec<FakeSong> = self
+                    .db
+                    .iter()
+                    .filter(|s| {
+                        uri.is_empty()
+                            || s.file == uri
+                            || (command == "add" && s.file.starts_with(&format!("{}/", uri)))
+                    })
+                    .cloned()
+                    .collect();
+                if songs.is_empty() {
+                    return Err((50, String::from("No such song")));
+                }
+                let pos = match args.get(1) {
+                    Some(_) => num(1)?.min(self.queue.len()),
+                    None => self.queue.len(),
+                };
+                let ids = self.insert(pos, songs);
+                if command == "addid" {
+                    out.push(("Id".into(), ids[0].to_string()));
+                }
+            }
+            "clear" => {
+                let len = self.queue.len();
+                self.delete(0..len);
+                self.play = Playback::Stop;
+            }
+            "delete" => {
+                let range = match arg(0)?.split_once(':') {
+                    Some((s, e)) => {
+                        s.parse().map_err(|_| bad_index())?..e.parse().map_err(|_| bad_index())?
+                    }
+                    None => num(0)?..num(0)? + 1,
+                };
+                if range.end > self.queue.len() || range.is_empty() {
+                    return Err(bad_index());
+                }
+                self.delete(range);
+            }
+            "deleteid" => {
+                let id = num(0)? as u32;
+                let pos = self
+                    .queue
+                    .iter()
+                    .position(|e| e.id == id)
+                    .ok_or((50, String::from("No such song")))?;
+                self.delete(pos..pos + 1);
+            }
+            "play" => {
+                let pos = match args.first() {
+                    Some(_) => num(0)?,
+                    None => self.current.unwrap_or(0),
+                };
+                if pos >= self.queue.len() {
+                    return Err(bad_index());
+                }
+                self.play_at(pos);
+            }
+            "playid" => {
+                let id = num(0)? as u32;
+                let pos = self
+                    .queue
+                    .iter()
+                    .position(|e| e.id == id)
+                    .ok_or((50, String::from("No such song")))?;
+                self.play_at(pos);
+            }
+            "pause" => {
+                let pause = match args.first() {
+                    Some(_) => flag(0)?,
+                    None => self.play == Playback::Play,
+                };
+                if self.play != Playback::Stop {
+                    self.play = if pause {
+                        Playback::Pause
+                    } else {
+                        Playback::Play
+                    };
+                }
+                self.emit("player");
+            }
+            "stop" => {
+                self.play = Playback::Stop;
+                self.elapsed = 0.0;
+                self.emit("player");
+            }
+            "next" | "previous" => {
+                let Some(c) = self.current else {
+                    return Err((3, String::from("Not playing")));
+                };
+                let pos = if command == "next" {
+                    c + 1
+                } else {
+                    c.saturating_sub(1)
+                };
+                if pos >= self.queue.len() {
+                    self.play = Playback::Stop;
+                    self.current = None;
+                    self.emit("player");
+                } else {
+                    self.play_at(pos);
+                }
+            }
+            "seekcur" => {
+                if self.current.is_none() {
+                    return Err((3, String::from("Not playing")));
+                }
+                self.elapsed = float(0)?;
+                self.emit("player");
+            }
+            "seek" => {
+                let pos = num(0)?;
+                if pos >= self.queue.len() {
+                    return Err(bad_index());
+                }
+                self.play_at(pos);
+                self.elapsed = float(1)?;
+            }
+            "repeat" | "random" | "single" | "consume" => {
+                let v = flag(0)?;
+                match command {
+                    "repeat" => self.repeat = v,
+                    "random" => self.random = v,
+                    "single" => self.single = v,
+                    _ => self.consume = v,
+                }
+                self.emit("options");
+            }
+            "setvol" => {
+                self.volume = num(0)? as i32;
+                self.emit("mixer");
+            }
+            "list" => {
+                let tag = arg(0)?;
+                let mut values: Vec<&str> = self
+                    .db
+                    .iter()
+                    .filter(|s| State::matches(s, &args[1..], true))
+                    .flat_map(|s| s.tag(tag))
+                    .collect();
+                values.sort();
+                values.dedup();
+                let key = match tag.to_lowercase().as_str() {
+                    "albumartist" => String::from("AlbumArtist"),
+                    t => {
+                        let mut c = t.chars();
+                        c.next()
+                            .map(|f| f.to_uppercase().chain(c).collect())
+                            .unwrap_or_default()
+                    }
+                };
+                out = values
+                    .into_iter()
+                    .map(|v| (key.clone(), v.to_string()))
+                    .collect();
+            }
+            "find" | "search" => {
+                if args.is_empty() || !args.len().is_multiple_of(2) {
+                    return Err((2, String::from("incorrect arguments")));
+                }
+                for song in &self.db {
+                    if State::matches(song, args, command == "find") {
+                        out.extend(song.pairs());
+                    }
+                }
+            }
+            "readcomments" => {
+                let file = arg(0)?;
+                out.extend(self.comments.get(file).cloned().unwrap_or_default());
+            }
+            "sticker" => {
+                if arg(1)? != "song" {
+                    return Err((2, String::from("unknown sticker domain")));
+                }
+                let file = arg(2)?;
+                let missing = || (50, String::from("no such sticker"));
+                match arg(0)? {
+                    "get" => {
+                        let name = arg(3)?;
+                        let (_, value) = self
+                            .stickers
+                            .get(file)
+                            .and_then(|s| s.iter().find(|(n, _)| n == name))
+                            .ok_or_else(missing)?;
+                        out.push(("sticker".into(), format!("{}={}", name, value)));
+                    }
+                    "set" => {
+                        let (name, value) = (arg(3)?, arg(4)?);
+                        let stickers = self.stickers.entry(file.into()).or_default();
+                        match stickers.iter_mut().find(|(n, _)| n == name) {
+                            Some(s) => s.1 = value.into(),
+                            None => stickers.push((name.into(), value.into())),
+                        }
+                        self.emit("sticker");
+                    }
+                    "delete" => {
+                        let name = arg(3)?;
+                        let stickers = self.stickers.get_mut(file).ok_or_else(missing)?;
+                        let before = stickers.len();
+                        stickers.retain(|(n, _)| n != name);
+                        if stickers.len() == before {
+                            return Err(missing());
+                        }
+                        self.emit("sticker");
+                    }
+                    "list" => {
+                        for (name, value) in self.stickers.get(file).into_iter().flatten() {
+                            out.push(("sticker".into(), format!("{}={}", name, value)));
+                        }
+                    }
+                    "find" => {
+                        let name = arg(3)?;
+                        let mut files: Vec<&String> = self
+                            .stickers
+                            .keys()
+                            .filter(|f| file.is_empty() || f.starts_with(&format!("{}/", file)))
+                            .collect();
+                        files.sort();
+                        for f in files {
+                            if let Some((_, value)) =
+                                self.stickers[f].iter().find(|(n, _)| n == name)
+                            {
+                                out.push(("file".into(), f.clone()));
+                                out.push(("sticker".into(), format!("{}={}", name, value)));
+                            }
+                        }
+                    }
+                    _ => return Err((2, String::from("bad request"))),
+                }
+            }
+            "listallinfo" => {
+                let prefix = args.first().map(String::as_str).unwrap_or("");
+                for song in self.db.iter().filter(|s| s.file.starts_with(prefix)) {
+                    out.extend(song.pairs());
+                }
+            }
+            _ => return Err((5, format!("unknown command \"{}\"", command))),
+        }
+        Ok(out)
+    }
+}
+
+pub struct FakeMpd {
+    pub addr: SocketAddr,
+    shared: Arc<Mutex<State>>,
+}
+
+impl FakeMpd {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(Mutex::new(State::default()));
+        let s = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if s.lock().unwrap().offline {
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                }
+                let s = s.clone();
+                thread::spawn(move || serve(stream, s));
+            }
+        });
+        Self { addr, shared }
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.lock().unwrap()
+    }
+
+    // Scratch space for this server's tests, such as the library cache
+    pub fn dir(&self) -> PathBuf {
+        env::temp_dir().join(format!(
+            "mpcursive-test-{}-{}",
+            std::process::id(),
+            self.addr.port()
+        ))
+    }
+
+    pub fn config(&self) -> Config {
+        Config {
+            mpd: MpdConfig {
+                host: self.addr.ip().to_string(),
+                port: self.addr.port(),
+                password: None,
+                music_directory: None,
+            },
+            ..Config::default()
+        }
+    }
+
+    // A client of this server alone, with a library cache nobody else uses
+    pub fn app(&self) -> App {
+        self.app_with(self.config())
+    }
+
+    // Connected as the idle thread would, unless the server is offline
+    pub fn app_with(&self, config: Config) -> App {
+        let app = App {
+            mpd: MPD::new(
+                &config.mpd.address(),
+                config.mpd.password.clone(),
+                Some(self.dir().join("server")),
+            ),
+            config: Arc::new(config),
+            clipboard: Clipboard::default(),
+            notices: Notices::default(),
+        };
+        let _ = app.mpd.connect();
+        app
+    }
+}
+
+fn serve(stream: TcpStream, shared: Arc<Mutex<State>>) {
+    let (tx, rx) = mpsc::channel();
+    let reader = BufReader::new(stream.try_clone().unwrap());
+    thread::spawn(move || {
+        for line in reader.lines() {
+            match line {
+                Ok(l) => {
+                    if tx.send(l).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    });
+    let mut out = stream.try_clone().unwrap();
+    let close = || {
+        let _ = stream.shutdown(Shutdown::Both);
+    };
+    if out.write_all(b"OK MPD 0.23.5\n").is_err() {
+        return;
+    }
+
+    let mut seen = shared.lock().unwrap().events.len();
+    let mut idle: Option<Vec<String>> = None;
+    let mut list: Option<(bool, Vec<String>)> = None;
+    let mut limit: Option<usize> = None; // binarylimit, per connection
+    let mut allowed = false; // sent the password
+    loop {
+        {
+            let state = shared.lock().unwrap();
+            if state.offline {
+                return close();
+            }
+            if let Some(filter) = &idle {
+                let changed = changes(&state.events[seen..], filter);
+                if !changed.is_empty() {
+                    seen = state.events.len();
+                    idle = None;
+                    drop(state);
+                    let _ = out.write_all(format!("{}OK\n", changed).as_bytes());
+                }
+            }
+        }
+        let line = match rx.recv_timeout(POLL) {
+            Ok(l) => l,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+        let words = split(&line);
+        let Some(command) = words.first().cloned() else {
+            continue;
+        };
+        let mut state = shared.lock().unwrap();
+        state.log.push(line.clone());
+        while state.holds.contains(&command) {
+            drop(state);
+            thread::sleep(POLL);
+            state = shared.lock().unwrap();
+        }
+        let dropped = if state.drops.get(&line).is_some_and(|n| *n > 0) {
+            &line
+        } else {
+            &command
+        };
+        if let Some(n) = state.drops.get_mut(dropped).filter(|n| **n > 0) {
+            *n -= 1;
+            return close();
+        }
+
+        if let Some(filter) = idle.take() {
+            if command == "noidle" {
+                let changed = changes(&state.events[seen..], &filter);
+                seen = state.events.len();
+                let _ = out.write_all(format!("{}OK\n", changed).as_bytes());
+            } else {
+                // anything else while idle is a protocol error; MPD hangs up
+                return close();
+            }
+            continue;
+        }
+
+        let locked = state.password.is_some() && !allowed;
+        let reply = match (command.as_str(), &mut list) {
+            ("password", None) => {
+                if words.get(1) == state.password.as_ref() {
+                    allowed = true;
+                    String::from("OK\n")
+                } else {
+                    String::from("ACK [3@0] {password} incorrect password\n")
+                }
+            }
+            (_, None) if locked && command != "close" => format!(
+                "ACK [4@0] {{{}}} you don't have permission for \"{}\"\n",
+                command, command
+            ),
+            ("command_list_begin", None) => {
+                list = Some((false, vec![]));
+                continue;
+            }
+            ("command_list_ok_begin", None) => {
+                list = Some((true, vec![]));
+                continue;
+            }
+            ("command_list_end", Some(_)) => {
+                let (ok, lines) = list.take().unwrap();
+                let mut reply = String::new();
+                let mut failed = false;
+                for (i, l) in lines.iter().enumerate() {
+                    let words = split(l);
+                    match state.exec(&words[0], &words[1..]) {
+                        Ok(pairs) => {
+                            reply.push_str(&format_pairs(&pairs));
+                            if ok {
+                                reply.push_str("list_OK\n");
+                            }
+                        }
+                        Err((code, msg)) => {
+                            reply.push_str(&format!(
+                                "ACK [{}@{}] {{{}}} {}\n",
+                                code, i, words[0], msg
+                            ));
+                            failed = true;
+                            break;
+                        }
+                    }
+                }
+                if !failed {
+                    reply.push_str("OK\n");
+                }
+                reply
+            }
+            (_, Some((_, lines))) => {
+                lines.push(line);
+                continue;
+            }
+            ("idle", None) => {
+                idle = Some(words[1..].to_vec());
+                continue;
+            }
+            ("noidle", None) => continue,
+            ("close", None) => return close(),
+            ("binarylimit", None) => match words.get(1).and_then(|w| w.parse().ok()) {
+                Some(n) if n >= 64 => {
+                    limit = Some(n);
+                    String::from("OK\n")
+                }
+                _ => format!("ACK [2@0] {{{}}} Value too small\n", command),
+            },
+            ("albumart" | "readpicture", None) => {
+                match state.binary(&command, &words[1..], limit) {
+                    Ok(reply) => {
+                        drop(state);
+                        if out.write_all(&reply).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Err((code, msg)) => format!("ACK [{}@0] {{{}}} {}\n", code, command, msg),
+                }
+            }
+            (_, None) => match state.exec(&command, &words[1..]) {
+                Ok(pairs) => format!("{}OK\n", format_pairs(&pairs)),
+                Err((code, msg)) => format!("ACK [{}@0] {{{}}} {}\n", code, command, msg),
+            },
+        };
+        drop(state);
+        if out.write_all(reply.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+fn changes(events: &[&'static str], filter: &[String]) -> String {
+    let mut seen = vec![];
+    for e in events {
+        if (filter.is_empty() || filter.iter().any(|f| f == e)) && !seen.contains(e) {
+            seen.push(*e);
+        }
+    }
+    seen.iter().map(|e| format!("changed: {}\n", e)).collect()
+}
+
+fn format_pairs(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}: {}\n", k, v))
+        .collect()
+}
+
+// Splits a command line into words, honouring double quotes and escapes
+fn split(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek() == Some(&' ') {
+            chars.next();
+        }
+        let Some(&c) = chars.peek() else {
+            return words;
+        };
+        let mut word = String::new();
+        if c == '"' {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => word.extend(chars.next()),
+                    '"' => break,
+                    c => word.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ' ' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+        }
+        words.push(word);
+    }
+}
+
+// Every test gets a server and client of its own, so they run in parallel
+pub fn server() -> (FakeMpd, App) {
+    let server = FakeMpd::start();
+    let app = server.app();
+    (server, app)
+}
+
+// Polls until f holds, for things that settle in background threads
+pub fn eventually(what: &str, mut f: impl FnMut() -> bool) {
+    for _ in 0..500 {
+        if f() {
+            return;
+        }
+        thread::sleep(POLL);
+    }
+    panic!("timed out waiting for {}", what);
+}
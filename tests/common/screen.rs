@@ -0,0 +1,162 @@
+// Drives the whole UI on cursive's puppet backend and compares what it
+// draws against golden files in tests/snapshots. Run with UPDATE_SNAPSHOTS=1
+// to write them after an intended change.
+
+use std::{env, fs, path::PathBuf};
+
+use cursive::{
+    backends::puppet::{
+        observed::{GraphemePart, ObservedScreen, ObservedStyle},
+        Backend,
+    },
+    event::{Event, Key, MouseButton, MouseEvent},
+    reexports::crossbeam_channel::{Receiver, Sender},
+    views::ResizedView,
+    CbSink, Cursive, CursiveRunner, XY,
+};
+use mpcursive::{app::App, view::root::Root};
+
+pub struct Screen {
+    runner: CursiveRunner<Cursive>,
+    input: Sender<Option<Event>>,
+    frames: Receiver<ObservedScreen>,
+}
+
+impl Screen {
+    // Root on a fixed-size terminal, with the theme the binary uses
+    pub fn new(app: App, width: usize, height: usize) -> Self {
+        Self::with(app, width, height, |_, _| {})
+    }
+
+    // Same, with setup for views that main registers on Root
+    pub fn with(
+        app: App,
+        width: usize,
+        height: usize,
+        setup: impl FnOnce(&mut Root, CbSink),
+    ) -> Self {
+        let backend = Backend::init(Some(XY::new(width, height)));
+        let input = backend.input();
+        let frames = backend.stream();
+        let mut siv = Cursive::new();
+        siv.load_toml(include_str!("../../themes/dark.toml"))
+            .unwrap();
+        let mut root = Root::new(app);
+        setup(&mut root, siv.cb_sink().clone());
+        siv.add_fullscreen_layer(ResizedView::with_full_screen(root));
+        Self {
+            runner: siv.into_runner(backend),
+            input,
+            frames,
+        }
+    }
+
+    pub fn send(&mut self, e: Event) {
+        self.input.send(Some(e)).unwrap();
+        self.runner.step();
+    }
+
+    pub fn keys(&mut self, s: &str) {
+        s.chars().for_each(|c| self.send(Event::Char(c)));
+    }
+
+    pub fn key(&mut self, k: Key) {
+        self.send(Event::Key(k));
+    }
+
+    pub fn mouse(&mut self, x: usize, y: usize, event: MouseEvent) {
+        self.send(Event::Mouse {
+            offset: XY::zero(),
+            position: XY::new(x, y),
+            event,
+        });
+    }
+
+    pub fn click(&mut self, x: usize, y: usize) {
+        self.mouse(x, y, MouseEvent::Press(MouseButton::Left));
+        self.mouse(x, y, MouseEvent::Release(MouseButton::Left));
+    }
+
+    // Picks up the server state the way an idle event would, then draws
+    pub fn render(&mut self) -> String {
+        self.send(Event::Refresh);
+        let frame = self.frames.try_iter().last().expect("nothing was drawn");
+        format_screen(&frame)
+    }
+
+    pub fn assert_snapshot(&mut self, name: &str) {
+        let actual = self.render();
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("snapshots")
+            .join(format!("{}.txt", name));
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "no snapshot at {}, run with UPDATE_SNAPSHOTS=1 to create it\n{}",
+                path.display(),
+                actual
+            )
+        });
+        if expected != actual {
+            panic!(
+                "screen differs from {}\n--- expected\n{}\n--- actual\n{}",
+                path.display(),
+                expected,
+                actual
+            );
+        }
+    }
+}
+
+// The text of each row, then the same grid with one letter per style and a
+// legend for the letters. Letters are handed out in reading order, so a
+// style change shows up where it happens rather than renaming everything.
+fn format_screen(frame: &ObservedScreen) -> String {
+    const LETTERS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let size = frame.size();
+    let mut styles: Vec<ObservedStyle> = vec![];
+    let mut text = String::new();
+    let mut grid = String::new();
+    for y in 0..size.y {
+        let mut line = String::new();
+        for x in 0..size.x {
+            let Some(cell) = &frame[XY::new(x, y)] else {
+                line.push(' ');
+                grid.push('.');
+                continue;
+            };
+            if let GraphemePart::Begin(s) = &cell.letter {
+                line.push_str(s);
+            }
+            let i = match styles.iter().position(|s| *s == *cell.style) {
+                Some(i) => i,
+                None => {
+                    styles.push((*cell.style).clone());
+                    styles.len() - 1
+                }
+            };
+            grid.push(LETTERS.chars().nth(i).unwrap_or('?'));
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+        grid.push('\n');
+    }
+
+    let mut out = format!("{}\n{}\n", text, grid);
+    for (letter, style) in LETTERS.chars().zip(&styles) {
+        out.push_str(&format!(
+            "{}: {:?} on {:?}",
+            letter, style.colors.front, style.colors.back
+        ));
+        for effect in style.effects.iter() {
+            out.push_str(&format!(" {:?}", effect));
+        }
+        out.push('\n');
+    }
+    out
+}
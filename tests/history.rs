@@ -0,0 +1,219 @@
+mod common;
+
+use std::{fs, thread, time::Duration};
+
+use common::{screen::Screen, server, FakeSong, Playback};
+use mpcursive::{
+    history::{History, Period, Play, Recorder, Stats},
+    mpd_util::idle::Observer,
+    plays::Plays,
+    view::stats::StatsView,
+};
+use time::{OffsetDateTime, UtcOffset};
+
+// 2025-10-09 08:53:20 UTC
+const NOW: u64 = 1_760_000_000;
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+
+fn play(time: u64, file: &str, artist: &str, album: &str, listened: f64) -> Play {
+    Play {
+        time,
+        file: file.into(),
+        title: Some(file.trim_end_matches(".flac").into()),
+        artist: Some(artist.into()),
+        album_artist: Some(artist.into()),
+        album: Some(album.into()),
+        duration: Some(listened),
+        listened,
+    }
+}
+
+fn plays() -> Vec<Play> {
+    vec![
+        play(NOW - 20 * DAY, "d.flac", "C", "Z", 50.0),
+        play(NOW - 2 * DAY, "c.flac", "B", "Y", 300.0),
+        // late yesterday in UTC, but already today two hours east
+        play(1_759_966_200, "e.flac", "B", "Y", 60.0),
+        play(NOW - HOUR, "a1.flac", "A", "X", 200.0),
+        play(NOW - HOUR, "a2.flac", "A", "X", 100.0),
+    ]
+}
+
+#[test]
+fn history_file() {
+    let (fake, _) = server();
+    let path = fake.dir().join("history").join("plays.jsonl");
+    let _ = fs::remove_file(&path);
+    let history = History::new(Some(path.clone()));
+    let [first, second, ..] = &plays()[..] else {
+        unreachable!()
+    };
+    history.record(first.clone()).unwrap();
+    assert_eq!(history.plays()[..], [first.clone()][..]);
+    let before = history.plays();
+    history.record(second.clone()).unwrap();
+    assert_eq!(before.len(), 1);
+    assert_eq!(*history.plays(), [first.clone(), second.clone()]);
+
+    // a line cut short is skipped, the rest still read
+    let mut text = fs::read_to_string(&path).unwrap();
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.starts_with(r#"{"time":1758272000,"file":"d.flac","title":"d""#));
+    text.push_str("{\"time\":17");
+    fs::write(&path, text).unwrap();
+    assert_eq!(History::new(Some(path)).plays().len(), 2);
+
+    let memory = History::new(None);
+    memory.record(first.clone()).unwrap();
+    assert_eq!(memory.plays().len(), 1);
+}
+
+#[test]
+fn stats_over_periods() {
+    let now = OffsetDateTime::from_unix_timestamp(NOW as i64)
+        .unwrap()
+        .to_offset(UtcOffset::from_hms(2, 0, 0).unwrap());
+    let plays = plays();
+    let names = |counts: &[mpcursive::history::Count]| -> Vec<String> {
+        counts
+            .iter()
+            .map(|c| format!("{} {}", c.name, c.plays))
+            .collect()
+    };
+
+    let today = Stats::new(&plays, Period::Today, now);
+    assert_eq!(today.plays, 3);
+    assert_eq!(today.listened, Duration::from_secs(360));
+    assert_eq!(names(&today.artists), ["A 2", "B 1"]);
+    assert_eq!(today.days.len(), 1);
+    assert_eq!(today.days[0].0.to_string(), "2025-10-09");
+
+    // tied on plays, so the longer listened comes first
+    let week = Stats::new(&plays, Period::Week, now);
+    assert_eq!(names(&week.artists), ["B 2", "A 2"]);
+    assert_eq!(names(&week.albums), ["Y · B 2", "X · A 2"]);
+    assert_eq!(week.albums[1].files, ["a1.flac", "a2.flac"]);
+    assert_eq!(names(&week.tracks)[0], "c · B 1");
+    let days: Vec<(String, u64)> = week
+        .days
+        .iter()
+        .map(|(d, l)| (d.to_string(), l.as_secs()))
+        .collect();
+    assert_eq!(days.len(), 7);
+    assert_eq!(days[0], ("2025-10-09".into(), 360));
+    assert_eq!(days[1], ("2025-10-08".into(), 0));
+    assert_eq!(days[2], ("2025-10-07".into(), 300));
+    assert_eq!(days[6].0, "2025-10-03");
+
+    assert_eq!(Stats::new(&plays, Period::Month, now).plays, 5);
+    let all = Stats::new(&plays, Period::All, now);
+    assert_eq!(all.days.len(), 21);
+    assert_eq!(all.days[20].0.to_string(), "2025-09-19");
+    assert_eq!(Period::All.next(), Period::Today);
+}
+
+#[test]
+fn completed_plays_are_recorded() {
+    let (fake, app) = server();
+    fake.state().set_queue(vec![
+        FakeSong::new("a.flac", &[("Artist", "A"), ("Title", "One")], 10.0),
+        FakeSong::new("b.flac", &[("Artist", "B"), ("Title", "Two")], 600.0),
+    ]);
+    let history = History::new(None);
+    let mut counter = Plays::new(1)
+        .with_stickers()
+        .with_history(Recorder::new(history.clone(), "; "));
+    let at = |counter: &mut Plays, song: usize, play: Playback| {
+        {
+            let mut s = fake.state();
+            s.current = Some(song);
+            s.play = play;
+        }
+        app.mpd.invalidate();
+        counter.observe(&app.mpd);
+    };
+
+    at(&mut counter, 0, Playback::Play);
+    thread::sleep(Duration::from_millis(150));
+    at(&mut counter, 0, Playback::Play);
+    // counted, but still playing
+    assert!(history.plays().is_empty());
+    thread::sleep(Duration::from_millis(150));
+    at(&mut counter, 0, Playback::Pause);
+    at(&mut counter, 0, Playback::Play);
+    // another song that doesn't count
+    at(&mut counter, 1, Playback::Play);
+    thread::sleep(Duration::from_millis(150));
+    at(&mut counter, 1, Playback::Play);
+    at(&mut counter, 1, Playback::Stop);
+
+    let plays = history.plays();
+    assert_eq!(plays.len(), 1);
+    let play = &plays[0];
+    assert_eq!(play.file, "a.flac");
+    assert_eq!(play.title.as_deref(), Some("One"));
+    assert_eq!(play.artist.as_deref(), Some("A"));
+    assert_eq!(play.duration, Some(10.0));
+    // listening went on after it counted
+    assert!(
+        play.listened >= 0.3 && play.listened < 1.0,
+        "{}",
+        play.listened
+    );
+
+    // the one playing on exit
+    at(&mut counter, 0, Playback::Play);
+    thread::sleep(Duration::from_millis(150));
+    at(&mut counter, 0, Playback::Play);
+    assert_eq!(history.plays().len(), 1);
+    counter.close(&app.mpd);
+    assert_eq!(history.plays().len(), 2);
+    // counted once for both
+    assert_eq!(
+        fake.state().stickers["a.flac"][0],
+        ("playCount".into(), "2".into())
+    );
+    assert!(!fake.state().stickers.contains_key("b.flac"));
+}
+
+#[test]
+fn stats_view() {
+    let (fake, app) = server();
+    fake.state().db = ["a1.flac", "a2.flac"]
+        .iter()
+        .map(|f| FakeSong::new(f, &[], 60.0))
+        .collect();
+    let history = History::new(None);
+    let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+    for p in plays() {
+        history
+            .record(Play {
+                time: now - 60,
+                ..p
+            })
+            .unwrap();
+    }
+    let mut screen = Screen::with(app.clone(), 72, 8, |root, _| {
+        root.register(
+            "Stats",
+            '7',
+            "◷",
+            StatsView::new(app, history.clone(), UtcOffset::UTC),
+        )
+    });
+    screen.keys("7");
+    screen.assert_snapshot("stats_artists");
+
+    // albums, re-queued from
+    screen.keys("lja");
+    assert_eq!(fake.state().queue_files(), ["a1.flac", "a2.flac"]);
+
+    screen.keys("lll");
+    let recent = screen.render();
+    assert!(recent.contains("a2 · A"), "{}", recent);
+    assert!(recent.find("a2 · A") < recent.find("a1 · A"));
+
+    screen.keys("hp");
+    assert!(screen.render().contains("last 30 days · 5 plays · 11:50"));
+}
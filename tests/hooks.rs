@@ -0,0 +1,261 @@
+mod common;
+
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{eventually, server, FakeSong, Playback};
+use mpcursive::{config::HooksConfig, hooks::Hooks, mpd_util::idle::Observer};
+use serde_json::Value;
+
+fn songs() -> Vec<FakeSong> {
+    vec![
+        FakeSong::new(
+            "a/one.flac",
+            &[
+                ("Artist", "A"),
+                ("Artist", "B"),
+                ("Title", "One"),
+                ("Album", "X"),
+                ("Track", "1/9"),
+            ],
+            200.0,
+        ),
+        FakeSong::new("a/two.flac", &[("Title", "Two")], 300.0),
+    ]
+}
+
+fn lines(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn hooks_follow_playback() {
+    let (fake, app) = server();
+    let dir = fake.dir().join("hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("log");
+    let hook = |what: &str| Some(format!("echo \"{}\" >> '{}'", what, log.display()));
+    let cfg = HooksConfig {
+        on_song_change: hook("song_change $MPCURSIVE_TITLE"),
+        on_play: hook("play"),
+        on_pause: hook("pause $MPCURSIVE_ELAPSED"),
+        on_stop: hook("stop"),
+        on_queue_change: hook("queue_change $MPCURSIVE_QUEUE_LENGTH"),
+        on_connect: hook("connect $MPCURSIVE_STATE"),
+        ..HooksConfig::default()
+    };
+    fake.state().set_queue(songs());
+    let mut hooks = Hooks::new(&cfg, "; ", Duration::from_secs(5));
+    // each step's hooks are let run, as a hook still waiting when its event
+    // fires again is dropped
+    let mut change = |f: &dyn Fn(&mut common::State), total: usize| {
+        f(&mut fake.state());
+        app.mpd.invalidate();
+        hooks.observe(&app.mpd);
+        eventually("the hooks to run", || lines(&log).len() == total);
+    };
+
+    // started while stopped: nothing to say yet
+    change(&|_| {}, 0);
+    change(
+        &|s| {
+            s.current = Some(0);
+            s.play = Playback::Play;
+        },
+        2,
+    );
+    change(
+        &|s| {
+            s.play = Playback::Pause;
+            s.elapsed = 42.0;
+        },
+        3,
+    );
+    // ticks while nothing changed
+    change(&|_| {}, 3);
+    change(
+        &|s| {
+            s.current = Some(1);
+            s.elapsed = 0.0;
+            s.play = Playback::Play;
+        },
+        5,
+    );
+    change(
+        &|s| {
+            s.insert(2, vec![FakeSong::new("b.flac", &[], 60.0)]);
+        },
+        6,
+    );
+    change(&|s| s.play = Playback::Stop, 7);
+    hooks.connected(&app.mpd);
+
+    let expected = [
+        "song_change One",
+        "play",
+        "pause 42",
+        "song_change Two",
+        "play",
+        "queue_change 3",
+        "stop",
+        "connect stop",
+    ];
+    eventually("the hooks to run", || lines(&log).len() == expected.len());
+    assert_eq!(lines(&log), expected);
+}
+
+#[test]
+fn hooks_are_told_about_the_song() {
+    let (fake, app) = server();
+    let dir = fake.dir().join("hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cfg = HooksConfig {
+        on_song_change: Some(format!(
+            "cat > '{0}/song.json'; env | grep ^MPCURSIVE_ | sort > '{0}/env'",
+            dir.display()
+        )),
+        ..HooksConfig::default()
+    };
+    {
+        let mut s = fake.state();
+        s.set_queue(songs());
+        s.current = Some(0);
+        s.play = Playback::Play;
+        s.volume = 70;
+    }
+    let mut hooks = Hooks::new(&cfg, "; ", Duration::from_secs(5));
+    hooks.observe(&app.mpd);
+    eventually("the hook to run", || lines(&dir.join("env")).len() > 5);
+
+    let env = lines(&dir.join("env"));
+    for var in [
+        "MPCURSIVE_EVENT=song_change",
+        "MPCURSIVE_STATE=play",
+        "MPCURSIVE_VOLUME=70",
+        "MPCURSIVE_QUEUE_LENGTH=2",
+        "MPCURSIVE_FILE=a/one.flac",
+        "MPCURSIVE_TITLE=One",
+        "MPCURSIVE_ARTIST=A; B",
+        "MPCURSIVE_ALBUM=X",
+        "MPCURSIVE_TRACK=1/9",
+        "MPCURSIVE_DURATION=200",
+        "MPCURSIVE_POSITION=0",
+    ] {
+        assert!(env.iter().any(|l| l == var), "{} in {:?}", var, env);
+    }
+    assert!(!env.iter().any(|l| l.starts_with("MPCURSIVE_DATE=")));
+
+    let json: Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("song.json")).unwrap()).unwrap();
+    assert_eq!(json["event"], "song_change");
+    assert_eq!(json["state"], "play");
+    let song = &json["song"];
+    assert_eq!(song["file"], "a/one.flac");
+    assert_eq!(song["artists"], serde_json::json!(["A", "B"]));
+    assert_eq!(song["duration"], 200.0);
+    assert_eq!(song["date"], Value::Null);
+}
+
+#[test]
+fn slow_hooks_are_stopped() {
+    let (fake, app) = server();
+    let dir = fake.dir().join("hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cfg = HooksConfig {
+        on_play: Some(String::from("sleep 30")),
+        on_pause: Some(format!("touch '{}/paused'", dir.display())),
+        ..HooksConfig::default()
+    };
+    {
+        let mut s = fake.state();
+        s.set_queue(songs());
+        s.current = Some(0);
+        s.play = Playback::Play;
+    }
+    let mut hooks = Hooks::new(&cfg, "; ", Duration::from_millis(200));
+    let start = Instant::now();
+    hooks.observe(&app.mpd);
+    fake.state().play = Playback::Pause;
+    app.mpd.invalidate();
+    hooks.observe(&app.mpd);
+    // the hooks run elsewhere
+    assert!(start.elapsed() < Duration::from_millis(150));
+
+    eventually("the next hook", || dir.join("paused").exists());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn timed_out_hooks_take_their_children() {
+    let (fake, app) = server();
+    let dir = fake.dir().join("hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let cfg = HooksConfig {
+        on_play: Some(format!(
+            "(sleep 0.5; touch '{}/late') & sleep 30",
+            dir.display()
+        )),
+        on_pause: Some(format!("touch '{}/paused'", dir.display())),
+        ..HooksConfig::default()
+    };
+    {
+        let mut s = fake.state();
+        s.set_queue(songs());
+        s.current = Some(0);
+        s.play = Playback::Play;
+    }
+    let mut hooks = Hooks::new(&cfg, "; ", Duration::from_millis(200));
+    hooks.observe(&app.mpd);
+    fake.state().play = Playback::Pause;
+    app.mpd.invalidate();
+    hooks.observe(&app.mpd);
+
+    eventually("the next hook", || dir.join("paused").exists());
+    thread::sleep(Duration::from_millis(800));
+    assert!(!dir.join("late").exists());
+}
+
+#[test]
+fn stale_runs_are_dropped() {
+    let (fake, app) = server();
+    let dir = fake.dir().join("hooks");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("log");
+    let cfg = HooksConfig {
+        on_queue_change: Some(format!(
+            "echo $MPCURSIVE_QUEUE_LENGTH >> '{}'; sleep 0.3",
+            log.display()
+        )),
+        ..HooksConfig::default()
+    };
+    fake.state().set_queue(songs());
+    let mut hooks = Hooks::new(&cfg, "; ", Duration::from_secs(5));
+    hooks.observe(&app.mpd);
+    for n in 3..7 {
+        fake.state()
+            .insert(0, vec![FakeSong::new(&format!("{}.flac", n), &[], 60.0)]);
+        app.mpd.invalidate();
+        hooks.observe(&app.mpd);
+        if n == 3 {
+            eventually("the first hook", || lines(&log).len() == 1);
+        }
+    }
+
+    // the one running finishes, and only the latest of those waiting runs
+    eventually("the hooks to run", || lines(&log).len() == 2);
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(lines(&log), ["3", "6"]);
+}
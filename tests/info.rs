@@ -0,0 +1,103 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{screen::Screen, server, FakeSong};
+use cursive::event::Key;
+use mpcursive::{clipboard, meta::SongMeta, mpd_util::proto::songs_from_pairs, view::info};
+
+fn pairs(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|l| l.trim().split_once(": "))
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect()
+}
+
+#[test]
+fn fields_cover_tags_and_details() {
+    let (fake, _) = server();
+    let mut config = fake.config();
+    config.mpd.music_directory = Some(PathBuf::from("/music"));
+    let app = fake.app_with(config);
+    fake.state().stickers.insert(
+        "Band/Record/01.flac".into(),
+        vec![("rating".into(), "8".into())],
+    );
+
+    let song = songs_from_pairs(pairs(
+        "
+        file: Band/Record/01.flac
+        Last-Modified: 2024-03-01T12:00:00Z
+        Format: 44100:24:2
+        Artist: Band
+        Artist: Guest
+        Title: Song
+        Time: 205
+        duration: 205.347
+        Pos: 4
+        Id: 17
+        Prio: 3
+        ",
+    ))
+    .remove(0);
+    let fields = info::fields(&app, &SongMeta::new(song));
+    let expected: Vec<(String, String)> = [
+        ("File", "Band/Record/01.flac"),
+        ("Path", "/music/Band/Record/01.flac"),
+        ("Artist", "Band"),
+        ("Artist", "Guest"),
+        ("Title", "Song"),
+        ("Duration", "3:25 (205.347s)"),
+        ("Format", "44.1kHz/24bit/2ch (44100:24:2)"),
+        ("Last-Modified", "2024-03-01T12:00:00Z"),
+        ("Queue position", "5"),
+        ("Queue id", "17"),
+        ("Priority", "3"),
+        ("Sticker rating", "8"),
+    ]
+    .iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    assert_eq!(fields, expected);
+
+    // streams have no path and no stickers
+    let stream = songs_from_pairs(pairs("file: http://radio.example/live\nName: Radio")).remove(0);
+    assert_eq!(
+        info::fields(&app, &SongMeta::new(stream)),
+        [
+            ("File".into(), "http://radio.example/live".into()),
+            ("Name".into(), "Radio".into())
+        ]
+    );
+    assert_eq!(fake.state().commands("sticker").len(), 1);
+}
+
+#[test]
+fn info_popup_over_the_queue() {
+    let (fake, app) = server();
+    fake.state().set_queue(vec![FakeSong::new(
+        "Band/Record/01.flac",
+        &[
+            ("Artist", "Band"),
+            ("Title", "Song"),
+            ("Album", "Record"),
+            ("Format", "44100:16:2"),
+        ],
+        60.0,
+    )]);
+    let mut screen = Screen::new(app.clone(), 50, 14);
+    screen.keys("2");
+    screen.render();
+    screen.keys("i");
+    screen.keys("jjy");
+    screen.assert_snapshot("info_popup");
+    assert_eq!(app.clipboard.last().as_deref(), Some("Song"));
+
+    screen.key(Key::Esc);
+    assert!(!screen.render().contains("Copied"));
+}
+
+#[test]
+fn osc52() {
+    assert_eq!(clipboard::osc52("hi"), "\x1b]52;c;aGk=\x07");
+}
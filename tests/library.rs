@@ -0,0 +1,108 @@
+use mpcursive::library::{Filter, Library};
+use mpcursive::meta::SongMeta;
+use mpcursive::mpd_util::proto::songs_from_pairs;
+
+fn pairs(lines: &str) -> Vec<(String, String)> {
+    lines
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let (k, v) = l.split_once(": ").unwrap();
+            (k.to_string(), v.to_string())
+        })
+        .collect()
+}
+
+fn library() -> Library {
+    let songs = songs_from_pairs(pairs(
+        "
+        directory: Collab
+        file: Collab/01.flac
+        Artist: Alice
+        Artist: Bob
+        Title: Duet
+        Album: Together
+        Genre: Jazz
+        Genre: Soul
+        Track: 1
+        Time: 200
+        duration: 200.5
+        file: Alice/01.flac
+        Artist: Alice
+        ArtistSort: Alice, The
+        Title: Solo
+        Album: Alone
+        Genre: Jazz
+        playlist: Alice/list.m3u
+        file: Untagged.mp3
+        ",
+    ));
+    Library::new(songs.into_iter().map(SongMeta::from).collect())
+}
+
+#[test]
+fn keeps_repeated_tags() {
+    let songs = songs_from_pairs(pairs(
+        "
+        file: a.flac
+        Artist: Alice
+        Artist: Bob
+        Performer: Carol
+        Performer: Dave
+        Pos: 3
+        Id: 17
+        ",
+    ));
+    assert_eq!(songs.len(), 1);
+    let meta = SongMeta::from(songs[0].clone());
+    assert_eq!(meta.artists, vec!["Alice", "Bob"]);
+    assert_eq!(meta.performers, vec!["Carol", "Dave"]);
+    assert_eq!(meta.artist("; ").as_deref(), Some("Alice; Bob"));
+    let place = meta.place().unwrap();
+    assert_eq!((place.pos, place.id.0), (3, 17));
+}
+
+#[test]
+fn lists_song_under_each_artist() {
+    let lib = library();
+    assert_eq!(lib.len(), 3);
+    assert_eq!(
+        lib.artists().collect::<Vec<_>>(),
+        vec!["Alice", "Bob", "Unknown Artist"]
+    );
+    let titles = |artist| {
+        lib.artist_songs(artist)
+            .iter()
+            .map(|s| s.display_title())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles("Alice"), vec!["Solo", "Duet"]);
+    assert_eq!(titles("Bob"), vec!["Duet"]);
+    assert_eq!(titles("Unknown Artist"), vec!["Untagged.mp3"]);
+}
+
+#[test]
+fn filters_match_any_value() {
+    let lib = library();
+    let titles = |filter: &str| {
+        let mut t: Vec<_> = lib
+            .search(&Filter::parse(filter))
+            .iter()
+            .map(|s| s.display_title())
+            .collect();
+        t.sort();
+        t
+    };
+    assert_eq!(titles("artist:bob"), vec!["Duet"]);
+    assert_eq!(titles("genre:soul"), vec!["Duet"]);
+    assert_eq!(titles("genre:jazz"), vec!["Duet", "Solo"]);
+    assert_eq!(titles("alice alone"), vec!["Solo"]);
+    assert_eq!(titles("nosuchfield:x"), Vec::<String>::new());
+    assert_eq!(titles(""), vec!["Duet", "Solo", "Untagged.mp3"]);
+
+    assert_eq!(
+        lib.filtered_artists(&Filter::parse("genre:soul")),
+        vec!["Alice", "Bob"]
+    );
+}
@@ -0,0 +1,222 @@
+mod common;
+
+use std::{env, fs, path::PathBuf};
+
+use common::{eventually, library, FakeMpd};
+use cursive::Cursive;
+use mpcursive::{
+    app::App,
+    mpd_util::library_cache::{self, CacheFile},
+};
+use mpd::Song;
+
+const SERVER: &str = "127.0.0.1:6600";
+
+// A file of its own for each test, as they run side by side
+fn scratch(name: &str) -> PathBuf {
+    env::temp_dir()
+        .join(format!("mpcursive-test-{}", std::process::id()))
+        .join(format!("{}.bin", name))
+}
+
+fn songs(files: &[&str]) -> Vec<Song> {
+    files
+        .iter()
+        .map(|f| Song {
+            file: f.to_string(),
+            tags: vec![("Artist".into(), "A".into()), ("Artist".into(), "B".into())],
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn files(file: &CacheFile) -> Vec<&str> {
+    file.songs.iter().map(|s| s.file.as_str()).collect()
+}
+
+#[test]
+fn cache_is_read_back() {
+    let path = scratch("read_back");
+    CacheFile::new(SERVER.into(), 42, songs(&["a.flac", "b.flac"]))
+        .save(&path)
+        .unwrap();
+    let file = CacheFile::load(&path, SERVER).unwrap();
+    assert_eq!(file.db_update, 42);
+    assert_eq!(files(&file), ["a.flac", "b.flac"]);
+    // repeated tags are kept
+    assert_eq!(file.songs[0].tags.len(), 2);
+}
+
+#[test]
+fn cache_of_another_server_is_ignored() {
+    let path = scratch("another_server");
+    CacheFile::new(SERVER.into(), 1, songs(&["a.flac"]))
+        .save(&path)
+        .unwrap();
+    assert!(CacheFile::load(&path, "127.0.0.1:6601").is_none());
+}
+
+#[test]
+fn cache_of_another_format_is_ignored() {
+    let path = scratch("another_format");
+    CacheFile::new(SERVER.into(), 1, songs(&["a.flac"]))
+        .save(&path)
+        .unwrap();
+    let mut data = fs::read(&path).unwrap();
+    // the format number leads the file
+    data[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, data).unwrap();
+    assert!(CacheFile::load(&path, SERVER).is_none());
+}
+
+#[test]
+fn torn_or_missing_caches_are_ignored() {
+    let path = scratch("torn");
+    CacheFile::new(SERVER.into(), 1, songs(&["a.flac"]))
+        .save(&path)
+        .unwrap();
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..data.len() - 3]).unwrap();
+    assert!(CacheFile::load(&path, SERVER).is_none());
+    assert!(CacheFile::load(&scratch("missing"), SERVER).is_none());
+}
+
+#[test]
+fn cache_is_named_after_the_server_and_database() {
+    let dir = env::temp_dir().join("mpcursive-test-cache-home");
+    env::set_var("XDG_CACHE_HOME", &dir);
+    let base = library_cache::base("music.local:6600").unwrap();
+    assert_eq!(base, dir.join("mpcursive").join("music.local_6600"));
+    assert_eq!(
+        library_cache::library_path(&base, 1700000000),
+        dir.join("mpcursive")
+            .join("music.local_6600-1700000000.bin")
+    );
+}
+
+fn refresh(app: &App) {
+    library_cache::refresh(&app.mpd, Cursive::new().cb_sink().clone());
+}
+
+fn library_len(app: &App) -> usize {
+    app.mpd.library().map(|l| l.len()).unwrap_or(0)
+}
+
+// The library file the fake server's client writes for db_update
+fn library_file(fake: &FakeMpd, db_update: i64) -> PathBuf {
+    library_cache::library_path(&fake.dir().join("server"), db_update)
+}
+
+// A server whose library was listed once and written to disk
+fn cached() -> (FakeMpd, App) {
+    let fake = FakeMpd::start();
+    fake.state().db = library(2, 1, 3);
+    let app = fake.app();
+    refresh(&app);
+    eventually("the library", || library_len(&app) == 6);
+    let db_update = fake.state().db_update;
+    eventually("the cache file", || library_file(&fake, db_update).exists());
+    (fake, app)
+}
+
+#[test]
+fn library_is_read_back_while_offline() {
+    let (fake, _) = cached();
+    fake.state().set_offline(true);
+
+    let app = fake.app();
+    refresh(&app);
+    eventually("the cached library", || library_len(&app) == 6);
+    let library = app.mpd.library().unwrap();
+    assert_eq!(
+        library.artists().collect::<Vec<_>>(),
+        ["Artist 0", "Artist 1"]
+    );
+}
+
+#[test]
+fn unchanged_database_is_not_listed_again() {
+    let (fake, _) = cached();
+    fake.state().log.clear();
+
+    let app = fake.app();
+    refresh(&app);
+    eventually("the cached library", || library_len(&app) == 6);
+    eventually("the database check", || {
+        !fake.state().commands("stats").is_empty()
+    });
+    // listallinfo would follow stats on the same connection
+    fake.state().set_offline(true);
+    assert!(fake.state().commands("listallinfo").is_empty());
+}
+
+#[test]
+fn library_of_another_server_is_not_shown() {
+    let (a, _) = cached();
+    let b = FakeMpd::start();
+    fs::create_dir_all(b.dir()).unwrap();
+    let db_update = a.state().db_update;
+    fs::copy(library_file(&a, db_update), library_file(&b, db_update)).unwrap();
+    b.state().db = library(1, 1, 1);
+    b.state().hold("listallinfo");
+
+    let app = b.app();
+    refresh(&app);
+    // the cache is looked at before the server is asked
+    eventually("the listing", || {
+        !b.state().commands("listallinfo").is_empty()
+    });
+    assert!(app.mpd.library().is_none());
+    b.state().release("listallinfo");
+    eventually("the library", || library_len(&app) == 1);
+}
+
+#[test]
+fn changed_database_replaces_its_cache() {
+    let (fake, _) = cached();
+    let old = fake.state().db_update;
+    {
+        let mut s = fake.state();
+        s.db = library(1, 1, 2);
+        s.db_update += 1;
+    }
+
+    let app = fake.app();
+    refresh(&app);
+    eventually("the new library", || library_len(&app) == 2);
+    eventually("the new cache file", || {
+        library_file(&fake, old + 1).exists()
+    });
+    eventually("the old cache file removed", || {
+        !library_file(&fake, old).exists()
+    });
+
+    // a later run starts from the new database
+    fake.state().set_offline(true);
+    let app = fake.app();
+    refresh(&app);
+    eventually("the cached library", || library_len(&app) == 2);
+}
+
+#[test]
+fn database_changes_during_a_refresh_are_not_lost() {
+    let fake = FakeMpd::start();
+    fake.state().db = library(1, 1, 2);
+    fake.state().hold("listallinfo");
+    let app = fake.app();
+    refresh(&app);
+    eventually("the listing", || {
+        !fake.state().commands("listallinfo").is_empty()
+    });
+
+    // MPD announces an update while the old database is being listed
+    {
+        let mut s = fake.state();
+        s.db = library(1, 1, 5);
+        s.db_update += 1;
+    }
+    refresh(&app);
+    fake.state().release("listallinfo");
+    eventually("the updated library", || library_len(&app) == 5);
+    assert_eq!(fake.state().commands("listallinfo").len(), 2);
+}
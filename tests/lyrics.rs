@@ -0,0 +1,349 @@
+mod common;
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use common::{eventually, screen::Screen, server, FakeMpd, FakeSong, Playback};
+use cursive::event::Key;
+use mpcursive::{
+    app::App,
+    lyrics::{self, Line, Lyrics, Source},
+    meta::SongMeta,
+    view::lyrics::LyricsView,
+};
+use mpd::Song;
+
+const LRC: &str = "\
+[ar:Band]
+[ti:Song]
+[00:01.00]First
+[00:05.5]Second
+[00:10.250][00:20.00]Chorus
+[00:15.00]
+[Bridge]
+";
+
+fn ms(ms: u64) -> Option<Duration> {
+    Some(Duration::from_millis(ms))
+}
+
+fn line(time: Option<Duration>, text: &str) -> Line {
+    Line {
+        time,
+        text: text.into(),
+    }
+}
+
+fn song(file: &str) -> SongMeta {
+    SongMeta::new(Song {
+        file: file.into(),
+        title: Some("Song".into()),
+        artist: Some("Band".into()),
+        ..Default::default()
+    })
+}
+
+// A server and a client that keeps lyrics under the server's scratch dir
+fn setup() -> (FakeMpd, App, PathBuf) {
+    let (fake, _) = server();
+    let dir = fake.dir();
+    let mut config = fake.config();
+    config.mpd.music_directory = Some(dir.join("music"));
+    config.lyrics.folder = Some(dir.join("lyrics"));
+    let app = fake.app_with(config);
+    fs::create_dir_all(dir.join("music/Band/Record")).unwrap();
+    (fake, app, dir)
+}
+
+#[test]
+fn lrc_parsing() {
+    let lyrics = Lyrics::parse(LRC, Source::Embedded);
+    assert_eq!(
+        lyrics.tags,
+        [("ar".into(), "Band".into()), ("ti".into(), "Song".into())]
+    );
+    // lines with several stamps repeat, in time order, and unstamped ones
+    // keep to the line before them
+    assert_eq!(
+        lyrics.lines,
+        [
+            line(ms(1000), "First"),
+            line(ms(5500), "Second"),
+            line(ms(10250), "Chorus"),
+            line(ms(15000), ""),
+            line(None, "[Bridge]"),
+            line(ms(20000), "Chorus"),
+        ]
+    );
+    assert!(lyrics.synced());
+
+    let offset = Lyrics::parse("[offset:+500]\n[00:02.00]A\n[00:00.20]B", Source::Embedded);
+    assert_eq!(offset.lines, [line(ms(1500), "A"), line(ms(0), "B")]);
+
+    let plain = Lyrics::parse(
+        "[ti:Song]\nOne\n[Chorus]\n[Verse: Singer]\nTwo: three\n\n",
+        Source::Embedded,
+    );
+    assert!(!plain.synced());
+    assert!(plain.tags.is_empty());
+    assert_eq!(
+        plain.lines,
+        [
+            line(None, "[ti:Song]"),
+            line(None, "One"),
+            line(None, "[Chorus]"),
+            line(None, "[Verse: Singer]"),
+            line(None, "Two: three")
+        ]
+    );
+
+    // only tags LRC knows make a header
+    let sections = Lyrics::parse(
+        "[al:Record]\n[Chorus: Singer]\n[00:01.00]A",
+        Source::Embedded,
+    );
+    assert_eq!(sections.tags, [("al".into(), "Record".into())]);
+    assert_eq!(
+        sections.lines,
+        [line(None, "[Chorus: Singer]"), line(ms(1000), "A")]
+    );
+}
+
+#[test]
+fn current_line() {
+    let lyrics = Lyrics::parse(LRC, Source::Embedded);
+    assert_eq!(lyrics.current(Duration::from_millis(500)), None);
+    assert_eq!(lyrics.current(Duration::from_secs(1)), Some(0));
+    assert_eq!(lyrics.current(Duration::from_secs(12)), Some(2));
+    assert_eq!(lyrics.current(Duration::from_secs(16)), Some(3));
+    assert_eq!(lyrics.current(Duration::from_secs(99)), Some(5));
+}
+
+#[test]
+fn lrc_round_trip() {
+    let lyrics = Lyrics::parse(LRC, Source::Embedded);
+    let lrc = lyrics.to_lrc();
+    assert!(lrc.starts_with("[ar:Band]\n[ti:Song]\n[00:01.00]First\n"));
+    assert!(lrc.contains("[00:15.00]\n[Bridge]\n[00:20.00]Chorus\n"));
+    assert_eq!(Lyrics::parse(&lrc, Source::Embedded), lyrics);
+    assert_eq!(
+        lyrics::format_time(Duration::from_millis(754_329)),
+        "[12:34.32]"
+    );
+}
+
+#[test]
+fn lookup_order() {
+    let (fake, app, dir) = setup();
+    let song = song("Band/Record/01.flac");
+    let find = || lyrics::find(&app.config, &app.mpd, &song).map(|l| (l.source, l.lines));
+
+    assert_eq!(find(), None);
+
+    fs::create_dir_all(dir.join("lyrics")).unwrap();
+    fs::write(dir.join("lyrics/Band - Song.txt"), "From folder").unwrap();
+    assert_eq!(
+        find(),
+        Some((
+            Source::File(dir.join("lyrics/Band - Song.txt")),
+            vec![line(None, "From folder")]
+        ))
+    );
+
+    fake.state().comments.insert(
+        "Band/Record/01.flac".into(),
+        vec![
+            ("ARTIST".into(), "Band".into()),
+            ("UNSYNCEDLYRICS".into(), "Unsynced".into()),
+            ("LYRICS".into(), "[00:01.00]Tagged".into()),
+        ],
+    );
+    assert_eq!(
+        find(),
+        Some((Source::Embedded, vec![line(ms(1000), "Tagged")]))
+    );
+
+    let beside = dir.join("music/Band/Record/01.txt");
+    fs::write(&beside, "Beside").unwrap();
+    assert_eq!(
+        find(),
+        Some((Source::File(beside), vec![line(None, "Beside")]))
+    );
+    let beside = dir.join("music/Band/Record/01.lrc");
+    fs::write(&beside, "[00:02.00]Synced").unwrap();
+    assert_eq!(
+        find(),
+        Some((Source::File(beside), vec![line(ms(2000), "Synced")]))
+    );
+}
+
+#[test]
+fn save_locations() {
+    let (fake, app, dir) = setup();
+    let song = song("Band/Record/01.flac");
+    let mut lyrics = Lyrics::parse("[00:01.00]A", Source::Embedded);
+
+    // next to the song, as an .lrc even if they came from a .txt
+    assert_eq!(
+        lyrics::save(&app.config, &song, &lyrics).unwrap(),
+        dir.join("music/Band/Record/01.lrc")
+    );
+    lyrics.source = Source::File(dir.join("music/Band/Record/01.txt"));
+    assert_eq!(
+        lyrics::save(&app.config, &song, &lyrics).unwrap(),
+        dir.join("music/Band/Record/01.lrc")
+    );
+
+    // without a music directory, into the folder
+    let mut config = fake.config();
+    config.lyrics.folder = Some(dir.join("lyrics"));
+    assert_eq!(
+        lyrics::save(&config, &song, &lyrics).unwrap(),
+        dir.join("lyrics/Band - Song.lrc")
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("lyrics/Band - Song.lrc")).unwrap(),
+        "[00:01.00]A\n"
+    );
+
+    // an .lrc from anywhere is written over
+    lyrics.source = Source::File(dir.join("elsewhere.lrc"));
+    assert_eq!(
+        lyrics::save(&fake.config(), &song, &lyrics).unwrap(),
+        dir.join("elsewhere.lrc")
+    );
+    lyrics.source = Source::Embedded;
+    assert!(lyrics::save(&fake.config(), &song, &lyrics).is_err());
+}
+
+fn playing(fake: &FakeMpd, elapsed: f64) {
+    let mut s = fake.state();
+    s.set_queue(vec![FakeSong::new(
+        "Band/Record/01.flac",
+        &[("Artist", "Band"), ("Title", "Song")],
+        60.0,
+    )]);
+    s.current = Some(0);
+    s.play = Playback::Pause;
+    s.elapsed = elapsed;
+}
+
+fn lyrics_screen(app: App) -> Screen {
+    let mut screen = Screen::with(app.clone(), 40, 12, |root, sink| {
+        root.register("Lyrics", '5', "♪", LyricsView::new(app, sink))
+    });
+    screen.keys("5");
+    settled(&mut screen);
+    screen
+}
+
+// The screen once the lookup for the song shown is done
+fn settled(screen: &mut Screen) -> String {
+    let mut shown = screen.render();
+    eventually("the lyrics lookup", || {
+        shown = screen.render();
+        !shown.contains("Looking for lyrics")
+    });
+    shown
+}
+
+#[test]
+fn synced_lyrics_follow_the_song() {
+    let (fake, app, dir) = setup();
+    let text: String = (0..20)
+        .map(|i| format!("[00:{:02}.00]Line {}\n", i * 2, i))
+        .collect();
+    fs::write(dir.join("music/Band/Record/01.lrc"), text).unwrap();
+    playing(&fake, 25.0);
+
+    let mut screen = lyrics_screen(app);
+    screen.assert_snapshot("lyrics_synced");
+}
+
+#[test]
+fn editor_stamps_and_saves() {
+    let (fake, app, dir) = setup();
+    fs::write(dir.join("music/Band/Record/01.txt"), "One\nTwo\nThree\n").unwrap();
+    playing(&fake, 3.0);
+
+    let mut screen = lyrics_screen(app.clone());
+    screen.render();
+    screen.keys("e");
+    screen.key(Key::Enter);
+    fake.state().elapsed = 7.5;
+    app.mpd.invalidate();
+    screen.key(Key::Enter);
+    screen.key(Key::Enter);
+    // the last line stays under the cursor
+    screen.key(Key::Del);
+    screen.keys("k]]s");
+    screen.assert_snapshot("lyrics_editor");
+
+    assert_eq!(
+        fs::read_to_string(dir.join("music/Band/Record/01.lrc")).unwrap(),
+        "[00:03.00]One\n[00:07.70]Two\nThree\n"
+    );
+    // the text file is left alone
+    assert_eq!(
+        fs::read_to_string(dir.join("music/Band/Record/01.txt")).unwrap(),
+        "One\nTwo\nThree\n"
+    );
+}
+
+#[test]
+fn editor_keeps_unsaved_stamps_with_their_song() {
+    let (fake, app, dir) = setup();
+    fs::write(dir.join("music/Band/Record/01.txt"), "One\nTwo\n").unwrap();
+    playing(&fake, 3.0);
+
+    let mut screen = lyrics_screen(app.clone());
+    screen.render();
+    screen.keys("e");
+    screen.key(Key::Enter);
+    {
+        let mut s = fake.state();
+        s.insert(
+            1,
+            vec![FakeSong::new(
+                "Band/Record/02.flac",
+                &[("Artist", "Band"), ("Title", "Other")],
+                60.0,
+            )],
+        );
+        s.current = Some(1);
+        s.elapsed = 9.0;
+    }
+    app.mpd.invalidate();
+    let shown = screen.render();
+    assert!(shown.contains("Song changed"), "{}", shown);
+    assert!(shown.contains("[00:03.00] One"), "{}", shown);
+
+    // the other song's time would be wrong here
+    screen.key(Key::Enter);
+    assert!(screen.render().contains("[--:--.--] Two"));
+
+    screen.keys("s");
+    assert_eq!(
+        fs::read_to_string(dir.join("music/Band/Record/01.lrc")).unwrap(),
+        "[00:03.00]One\nTwo\n"
+    );
+    let shown = settled(&mut screen);
+    assert!(shown.contains("Band - Other"), "{}", shown);
+    assert!(shown.contains("No lyrics"), "{}", shown);
+}
+
+#[test]
+fn lyrics_are_looked_up_once_shown() {
+    let (fake, app, _) = setup();
+    playing(&fake, 3.0);
+    let mut screen = Screen::with(app.clone(), 40, 12, |root, sink| {
+        root.register("Lyrics", '5', "♪", LyricsView::new(app, sink))
+    });
+    screen.render();
+    screen.render();
+    assert!(fake.state().commands("readcomments").is_empty());
+
+    screen.keys("5");
+    let shown = settled(&mut screen);
+    assert!(shown.contains("No lyrics"), "{}", shown);
+    assert_eq!(fake.state().commands("readcomments").len(), 1);
+}
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use mpcursive::meta::{Date, Position, SongMeta};
+use mpd::Song;
+
+fn song(tags: &[(&str, &str)]) -> Song {
+    Song {
+        file: "Artist/Album/01 Track.flac".into(),
+        tags: tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn track_numbers() {
+    let plain = Position::parse("3").unwrap();
+    assert_eq!((plain.number, plain.total), (Some(3), None));
+    assert_eq!(plain.to_string(), "03");
+
+    let of_total = Position::parse(" 3 / 12 ").unwrap();
+    assert_eq!((of_total.number, of_total.total), (Some(3), Some(12)));
+
+    let padded = Position::parse("007").unwrap();
+    assert_eq!(padded.number, Some(7));
+
+    // vinyl sides don't parse, but still display
+    let vinyl = Position::parse("A1").unwrap();
+    assert_eq!(vinyl.number, None);
+    assert_eq!(vinyl.to_string(), "A1");
+
+    let no_number = Position::parse("/12").unwrap();
+    assert_eq!((no_number.number, no_number.total), (None, Some(12)));
+
+    assert_eq!(Position::parse("  "), None);
+}
+
+#[test]
+fn dates() {
+    let year = Date::parse("1999").unwrap();
+    assert_eq!((year.year, year.month, year.day), (Some(1999), None, None));
+
+    let full = Date::parse("2019-03-14").unwrap();
+    assert_eq!(
+        (full.year, full.month, full.day),
+        (Some(2019), Some(3), Some(14))
+    );
+
+    let timestamp = Date::parse("2019-03-14T00:00:00Z").unwrap();
+    assert_eq!(
+        (timestamp.year, timestamp.month, timestamp.day),
+        (Some(2019), Some(3), Some(14))
+    );
+
+    let month = Date::parse("2019-03").unwrap();
+    assert_eq!(
+        (month.year, month.month, month.day),
+        (Some(2019), Some(3), None)
+    );
+
+    let bogus_month = Date::parse("2019-13-01").unwrap();
+    assert_eq!(
+        (bogus_month.year, bogus_month.month, bogus_month.day),
+        (Some(2019), None, None)
+    );
+
+    assert_eq!(Date::parse("14.03.2019").unwrap().year, Some(2019));
+    assert_eq!(Date::parse("March 2019").unwrap().year, Some(2019));
+    assert_eq!(Date::parse("(P) 1999 Label").unwrap().year, Some(1999));
+    assert_eq!(Date::parse("unknown").unwrap().year, None);
+    assert_eq!(Date::parse("unknown").unwrap().to_string(), "unknown");
+}
+
+#[test]
+fn repeated_tags() {
+    let meta = SongMeta::new(song(&[
+        ("Genre", "Rock"),
+        ("Genre", "Indie"),
+        ("Genre", "Rock"),
+        ("Genre", " "),
+        ("Performer", "A (vocals)"),
+        ("Performer", "B (drums)"),
+        ("MUSICBRAINZ_ARTISTID", "a-id"),
+        ("MUSICBRAINZ_ARTISTID", "b-id"),
+    ]));
+    assert_eq!(meta.genres, vec!["Rock", "Indie"]);
+    assert_eq!(meta.performers, vec!["A (vocals)", "B (drums)"]);
+    assert_eq!(meta.musicbrainz.artists, vec!["a-id", "b-id"]);
+}
+
+#[test]
+fn artist_fallbacks() {
+    let mut s = song(&[("Artist", "Feat Artist")]);
+    s.artist = Some("Main Artist".into());
+    let meta = SongMeta::new(s);
+    assert_eq!(meta.artists, vec!["Main Artist", "Feat Artist"]);
+    assert_eq!(
+        meta.album_artist("; ").as_deref(),
+        Some("Main Artist; Feat Artist")
+    );
+    assert_eq!(
+        meta.artist_sort_key().as_deref(),
+        Some("Main Artist; Feat Artist")
+    );
+
+    let meta = SongMeta::new(song(&[
+        ("Artist", "The Beatles"),
+        ("ArtistSort", "Beatles, The"),
+        ("AlbumArtist", "Various Artists"),
+    ]));
+    assert_eq!(meta.album_artist("; ").as_deref(), Some("Various Artists"));
+    assert_eq!(meta.artist_sort_key().as_deref(), Some("Beatles, The"));
+    assert_eq!(
+        meta.album_artist_sort_key().as_deref(),
+        Some("Various Artists")
+    );
+
+    let meta = SongMeta::new(song(&[
+        ("Artist", "The Beatles"),
+        ("ArtistSort", "Beatles, The"),
+    ]));
+    assert_eq!(
+        meta.album_artist_sort_key().as_deref(),
+        Some("Beatles, The")
+    );
+}
+
+#[test]
+fn titles() {
+    let mut s = song(&[]);
+    assert_eq!(SongMeta::new(s.clone()).display_title(), "01 Track.flac");
+
+    s.name = Some("Radio Stream".into());
+    assert_eq!(SongMeta::new(s.clone()).display_title(), "Radio Stream");
+
+    s.title = Some("Song".into());
+    s.duration = Some(Duration::from_secs(61));
+    let meta = SongMeta::new(s);
+    assert_eq!(meta.display_title(), "Song");
+    assert_eq!(meta.duration(), Some(Duration::from_secs(61)));
+}
+
+#[test]
+fn tag_names_are_case_insensitive() {
+    let meta = SongMeta::new(song(&[
+        ("musicbrainz_albumid", "rel"),
+        ("ALBUM", "Loud Album"),
+        ("track", "2/10"),
+        ("disc", "1/2"),
+        ("date", "2001"),
+    ]));
+    assert_eq!(meta.musicbrainz.release.as_deref(), Some("rel"));
+    assert_eq!(meta.album.as_deref(), Some("Loud Album"));
+    assert_eq!(meta.track.as_ref().unwrap().total, Some(10));
+    assert_eq!(meta.disc.as_ref().unwrap().number, Some(1));
+    assert_eq!(meta.year(), Some(2001));
+}
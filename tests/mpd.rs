@@ -0,0 +1,403 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{eventually, library, server, FakeMpd, FakeSong, Playback};
+use mpcursive::{
+    config::MpdConfig,
+    mpd_util::{proto::Ack, proto::Connection, Pending, MPD},
+};
+use mpd::State;
+
+fn queue_of(n: usize) -> Vec<FakeSong> {
+    (0..n)
+        .map(|i| {
+            FakeSong::new(
+                &format!("q/{:03}.flac", i),
+                &[("Title", &format!("Q{}", i)), ("Artist", "Queue")],
+                100.0,
+            )
+        })
+        .collect()
+}
+
+// The client notices on its next request
+fn noticed_offline(mpd: &MPD) -> bool {
+    mpd.invalidate();
+    mpd.status();
+    !mpd.online()
+}
+
+fn titles(mpd: &MPD, range: std::ops::Range<usize>) -> Vec<String> {
+    mpd.queue_window(range)
+        .iter()
+        .map(|s| s.display_title())
+        .collect()
+}
+
+#[test]
+fn status_follows_the_server() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.set_queue(queue_of(3));
+        s.current = Some(1);
+        s.play = Playback::Pause;
+        s.elapsed = 12.0;
+        s.volume = 70;
+    }
+    app.mpd.invalidate();
+    let status = app.mpd.status().unwrap();
+    assert_eq!(status.state, State::Pause);
+    assert_eq!(status.song.map(|p| p.pos), Some(1));
+    assert_eq!(status.volume, 70);
+    assert_eq!(status.queue_len, 3);
+    assert_eq!(app.mpd.elapsed(), Some(Duration::from_secs(12)));
+    assert_eq!(
+        app.mpd.now_playing().map(|s| s.display_title()),
+        Some(String::from("Q1"))
+    );
+}
+
+#[test]
+fn queue_is_fetched_by_window() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(500));
+    app.mpd.invalidate();
+
+    assert_eq!(app.mpd.queue_len(), 500);
+    assert_eq!(titles(&app.mpd, 100..103), ["Q100", "Q101", "Q102"]);
+    // already loaded rows are not asked for again
+    assert_eq!(titles(&app.mpd, 101..104), ["Q101", "Q102", "Q103"]);
+
+    let s = fake.state();
+    assert_eq!(
+        s.commands("playlistinfo"),
+        ["playlistinfo \"100:103\"", "playlistinfo \"103:104\""]
+    );
+}
+
+#[test]
+fn queue_changes_are_patched_in() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(10));
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..10).len(), 10);
+
+    {
+        let mut s = fake.state();
+        s.delete(0..2);
+        let extra = queue_of(12).split_off(10);
+        s.insert(8, extra);
+        s.log.clear();
+    }
+    app.mpd.invalidate();
+    assert_eq!(app.mpd.queue_len(), 10);
+    assert_eq!(
+        titles(&app.mpd, 0..10),
+        ["Q2", "Q3", "Q4", "Q5", "Q6", "Q7", "Q8", "Q9", "Q10", "Q11"]
+    );
+
+    // every position that changed is fetched again
+    let s = fake.state();
+    assert_eq!(s.commands("plchangesposid").len(), 1);
+    assert_eq!(s.commands("playlistinfo"), ["playlistinfo \"0:10\""]);
+}
+
+#[test]
+fn songs_edited_in_place_are_fetched_again() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(4));
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..4), ["Q0", "Q1", "Q2", "Q3"]);
+
+    // same id, new tags
+    {
+        let mut s = fake.state();
+        s.retag(2, &[("Title", "Live")]);
+        s.log.clear();
+    }
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..4), ["Q0", "Q1", "Live", "Q3"]);
+    assert_eq!(
+        fake.state().commands("playlistinfo"),
+        ["playlistinfo \"2:3\""]
+    );
+}
+
+#[test]
+fn queue_starts_over_when_the_server_does() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(4));
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..4), ["Q0", "Q1", "Q2", "Q3"]);
+
+    // a restarted server counts playlist versions from the start again
+    {
+        let mut s = fake.state();
+        s.version = 0;
+        s.set_queue(queue_of(2));
+    }
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..4), ["Q0", "Q1"]);
+}
+
+#[test]
+fn playback_commands_reach_the_server() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(5));
+
+    app.mpd.play_pos(3).unwrap();
+    assert_eq!(fake.state().current, Some(3));
+    assert_eq!(fake.state().play, Playback::Play);
+    assert_eq!(app.mpd.status().unwrap().song.map(|p| p.pos), Some(3));
+
+    app.mpd.seek(Duration::from_secs(42)).unwrap();
+    assert_eq!(fake.state().elapsed, 42.0);
+
+    app.mpd.set_repeat(true).unwrap();
+    assert!(fake.state().repeat);
+    assert!(app.mpd.status().unwrap().repeat);
+}
+
+#[test]
+fn add_appends_to_the_queue() {
+    let (fake, app) = server();
+    fake.state().db = library(2, 1, 2);
+
+    app.mpd
+        .add(&["Artist 1/Album 0/02.flac", "Artist 0/Album 0/01.flac"])
+        .unwrap();
+    assert_eq!(
+        fake.state().queue_files(),
+        ["Artist 1/Album 0/02.flac", "Artist 0/Album 0/01.flac"]
+    );
+    assert_eq!(app.mpd.queue_len(), 2);
+}
+
+#[test]
+fn error_responses_keep_the_connection() {
+    let (fake, app) = server();
+    fake.state().db = library(1, 1, 1);
+
+    let err = app.mpd.add(&["missing.flac"]).unwrap_err();
+    let ack = err.downcast_ref::<Ack>().unwrap();
+    assert_eq!(ack.code, 50);
+    assert_eq!(ack.command, "add");
+
+    fake.state().fail("play", 3, "Not now");
+    assert!(app.mpd.play_pos(0).is_err());
+
+    assert!(app.mpd.online());
+    app.mpd.add(&["Artist 0/Album 0/01.flac"]).unwrap();
+    app.mpd.play_pos(0).unwrap();
+    assert_eq!(fake.state().current, Some(0));
+}
+
+#[test]
+fn dropped_connections_are_reopened() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(2));
+
+    app.mpd.invalidate();
+    assert_eq!(app.mpd.queue_len(), 2);
+
+    // a connection that breaks fails the call and takes the client offline
+    // until it is reopened, which the idle and tick threads do
+    fake.state().drop_on("repeat");
+    assert!(app.mpd.set_repeat(true).is_err());
+    assert!(!app.mpd.online());
+    assert!(app.mpd.set_repeat(true).is_err());
+    assert_eq!(fake.state().commands("repeat").len(), 1);
+    assert!(app.mpd.connect().unwrap());
+    app.mpd.set_repeat(true).unwrap();
+    assert!(fake.state().repeat);
+
+    fake.state().drop_on("playlistinfo");
+    assert!(titles(&app.mpd, 0..2).is_empty());
+    assert!(app.mpd.connect().unwrap());
+    assert_eq!(titles(&app.mpd, 0..2), ["Q0", "Q1"]);
+    // nothing was left to open
+    assert!(!app.mpd.connect().unwrap());
+}
+
+#[test]
+fn offline_changes_are_replayed() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.db = library(1, 1, 3);
+        s.set_queue(queue_of(2));
+    }
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..2), ["Q0", "Q1"]);
+
+    fake.state().set_offline(true);
+    eventually("offline", || noticed_offline(&app.mpd));
+    assert!(app.mpd.last_seen().is_some());
+    // the last known queue is still there
+    assert_eq!(app.mpd.queue_len(), 2);
+    assert_eq!(titles(&app.mpd, 0..2), ["Q0", "Q1"]);
+
+    app.mpd.add(&["Artist 0/Album 0/03.flac"]).unwrap();
+    assert_eq!(
+        app.mpd.pending(),
+        [Pending::Add(vec![String::from("Artist 0/Album 0/03.flac")])]
+    );
+
+    fake.state().set_offline(false);
+    eventually("reconnect", || app.mpd.connect().is_ok());
+    app.mpd.replay_pending().unwrap();
+    assert!(app.mpd.pending().is_empty());
+    assert_eq!(
+        fake.state().queue_files(),
+        ["q/000.flac", "q/001.flac", "Artist 0/Album 0/03.flac"]
+    );
+}
+
+#[test]
+fn replay_cut_short_keeps_only_what_is_left() {
+    let (fake, app) = server();
+    fake.state().db = library(1, 1, 3);
+    app.mpd.invalidate();
+    assert_eq!(app.mpd.queue_len(), 0);
+
+    fake.state().set_offline(true);
+    eventually("offline", || noticed_offline(&app.mpd));
+    let files = [
+        "Artist 0/Album 0/01.flac",
+        "Artist 0/Album 0/02.flac",
+        "Artist 0/Album 0/03.flac",
+    ];
+    app.mpd.add(&files).unwrap();
+
+    fake.state().set_offline(false);
+    eventually("reconnect", || app.mpd.connect().is_ok());
+    // the connection breaks at the second file
+    let second = format!("add \"{}\"", files[1]);
+    fake.state().drop_on(&second);
+    assert!(app.mpd.replay_pending().is_err());
+    assert!(!app.mpd.online());
+    assert_eq!(fake.state().queue_files(), &files[..1]);
+    assert_eq!(
+        app.mpd.pending(),
+        [Pending::Add(
+            files[1..].iter().map(|f| f.to_string()).collect()
+        )]
+    );
+
+    app.mpd.connect().unwrap();
+    app.mpd.replay_pending().unwrap();
+    assert!(app.mpd.pending().is_empty());
+    assert_eq!(fake.state().queue_files(), files);
+}
+
+#[test]
+fn queue_is_shown_when_starting_offline() {
+    let (fake, app) = server();
+    fake.state().set_queue(queue_of(3));
+    app.mpd.invalidate();
+    assert_eq!(titles(&app.mpd, 0..3), ["Q0", "Q1", "Q2"]);
+    app.mpd.save_queue();
+
+    fake.state().set_offline(true);
+    let app = fake.app();
+    assert!(!app.mpd.online());
+    assert_eq!(app.mpd.queue_len(), 3);
+    assert_eq!(titles(&app.mpd, 0..3), ["Q0", "Q1", "Q2"]);
+    // nothing new to write
+    app.mpd.save_queue();
+
+    // the server is asked again once it is back, whatever the version
+    fake.state().retag(1, &[("Title", "Edited")]);
+    fake.state().set_offline(false);
+    eventually("reconnect", || app.mpd.connect().is_ok());
+    assert_eq!(titles(&app.mpd, 0..3), ["Q0", "Edited", "Q2"]);
+}
+
+#[test]
+fn list_and_find_use_the_database() {
+    let (fake, _) = server();
+    fake.state().db = library(3, 2, 2);
+
+    let mut conn = Connection::connect(fake.addr).unwrap();
+    let artists = conn.command("list", &["Artist"]).unwrap();
+    assert_eq!(
+        artists,
+        [
+            (String::from("Artist"), String::from("Artist 0")),
+            (String::from("Artist"), String::from("Artist 1")),
+            (String::from("Artist"), String::from("Artist 2")),
+        ]
+    );
+    let found = conn
+        .command("find", &["Artist", "Artist 1", "Album", "Album 0"])
+        .unwrap();
+    let files: Vec<&str> = found
+        .iter()
+        .filter(|(k, _)| k == "file")
+        .map(|(_, v)| v.as_str())
+        .collect();
+    assert_eq!(
+        files,
+        ["Artist 1/Album 0/01.flac", "Artist 1/Album 0/02.flac"]
+    );
+    assert!(conn.command("find", &["Artist"]).is_err());
+}
+
+#[test]
+fn clients_of_different_servers_are_independent() {
+    let (first, a) = server();
+    let (second, b) = server();
+    first.state().set_queue(queue_of(3));
+    second.state().set_queue(queue_of(5));
+    second.state().volume = 20;
+
+    assert_eq!(a.mpd.queue_len(), 3);
+    assert_eq!(b.mpd.queue_len(), 5);
+    assert_eq!(a.mpd.status().unwrap().volume, 50);
+    assert_eq!(b.mpd.status().unwrap().volume, 20);
+
+    first.state().set_offline(true);
+    eventually("offline", || noticed_offline(&a.mpd));
+    assert!(b.mpd.online());
+}
+
+#[test]
+fn password_is_sent_on_every_connection() {
+    let fake = FakeMpd::start();
+    {
+        let mut s = fake.state();
+        s.password = Some(String::from("secret"));
+        s.set_queue(queue_of(2));
+    }
+    // refused without it
+    assert!(fake.app().mpd.status().is_none());
+
+    let mut config = fake.config();
+    config.mpd.password = Some(String::from("secret"));
+    let app = fake.app_with(config);
+    assert!(app.mpd.online());
+    assert_eq!(titles(&app.mpd, 0..2), ["Q0", "Q1"]);
+    // the client and the protocol connection
+    assert_eq!(fake.state().commands("password").len(), 2);
+}
+
+#[test]
+fn mpd_host_may_carry_a_password() {
+    let mut config = MpdConfig::default();
+    config.set_host("music.local");
+    assert_eq!(
+        (config.host.as_str(), &config.password),
+        ("music.local", &None)
+    );
+
+    config.set_host("p@ss@music.local");
+    assert_eq!(config.host, "music.local");
+    assert_eq!(config.password.as_deref(), Some("p@ss"));
+
+    // an abstract socket, not a password
+    let mut config = MpdConfig::default();
+    config.set_host("@mpd");
+    assert_eq!((config.host.as_str(), &config.password), ("@mpd", &None));
+}
@@ -0,0 +1,311 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{dbus::Bus, server, FakeSong, Playback};
+use mpcursive::{
+    mpd_util::idle::Observer,
+    mpris::{Mpris, BUS_NAME, PATH},
+};
+use zbus::{
+    blocking::{fdo::DBusProxy, proxy, Connection, Proxy},
+    proxy::CacheProperties,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue},
+};
+
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const TRACK_LIST: &str = "org.mpris.MediaPlayer2.TrackList";
+
+fn songs() -> Vec<FakeSong> {
+    vec![
+        FakeSong::new(
+            "a/one.flac",
+            &[("Artist", "A"), ("Title", "One"), ("Track", "1")],
+            200.0,
+        ),
+        FakeSong::new("a/two.flac", &[("Artist", "A"), ("Title", "Two")], 300.0),
+        FakeSong::new("a/three.flac", &[("Title", "Three")], 100.0),
+    ]
+}
+
+// Reading properties afresh, as nothing announces changes here
+fn proxy<'a>(conn: &Connection, iface: &'a str) -> Proxy<'a> {
+    proxy::Builder::new(conn)
+        .destination(BUS_NAME)
+        .unwrap()
+        .path(PATH)
+        .unwrap()
+        .interface(iface)
+        .unwrap()
+        .cache_properties(CacheProperties::No)
+        .build()
+        .unwrap()
+}
+
+fn text(m: &HashMap<String, OwnedValue>, key: &str) -> String {
+    String::try_from(m[key].try_clone().unwrap()).unwrap()
+}
+
+fn track(id: u32) -> ObjectPath<'static> {
+    ObjectPath::try_from(format!("/org/mpcursive/Track/{}", id)).unwrap()
+}
+
+#[test]
+fn player_controls_mpd() {
+    let (fake, app) = server();
+    let Some(bus) = Bus::start(&fake.dir().join("bus")) else {
+        return;
+    };
+    {
+        let mut s = fake.state();
+        s.set_queue(songs());
+        s.current = Some(0);
+        s.play = Playback::Play;
+        s.elapsed = 20.0;
+        s.volume = 40;
+    }
+    let _mpris = Mpris::start(app.clone(), Some(&bus.address)).unwrap();
+    let conn = bus.connect();
+    let root = proxy(&conn, "org.mpris.MediaPlayer2");
+    assert_eq!(
+        root.get_property::<String>("Identity").unwrap(),
+        "mpcursive"
+    );
+    assert!(root.get_property::<bool>("HasTrackList").unwrap());
+    let player = proxy(&conn, PLAYER);
+    let status = || player.get_property::<String>("PlaybackStatus").unwrap();
+    assert_eq!(status(), "Playing");
+    assert_eq!(player.get_property::<f64>("Volume").unwrap(), 0.4);
+    assert!(player.get_property::<i64>("Position").unwrap() >= 20_000_000);
+
+    let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").unwrap();
+    assert_eq!(text(&metadata, "xesam:title"), "One");
+    assert_eq!(
+        Vec::<String>::try_from(metadata["xesam:artist"].try_clone().unwrap()).unwrap(),
+        ["A"]
+    );
+    assert_eq!(
+        i64::try_from(&metadata["mpris:length"]).unwrap(),
+        200_000_000
+    );
+    assert_eq!(i32::try_from(&metadata["xesam:trackNumber"]).unwrap(), 1);
+    assert_eq!(
+        OwnedObjectPath::try_from(metadata["mpris:trackid"].try_clone().unwrap()).unwrap(),
+        track(1).into()
+    );
+
+    let call = |method: &str| player.call_method(method, &()).unwrap();
+    call("PlayPause");
+    assert_eq!(fake.state().play, Playback::Pause);
+    assert_eq!(status(), "Paused");
+    call("Play");
+    assert_eq!(fake.state().play, Playback::Play);
+    call("Next");
+    assert_eq!(fake.state().current, Some(1));
+    call("Previous");
+    assert_eq!(fake.state().current, Some(0));
+    call("Stop");
+    assert_eq!(status(), "Stopped");
+    call("PlayPause");
+    assert_eq!(fake.state().play, Playback::Play);
+    call("Pause");
+
+    // positions in microseconds, and only for the song named
+    player.call_method("Seek", &(30_000_000i64)).unwrap();
+    assert_eq!(fake.state().elapsed, 30.0);
+    player
+        .call_method("SetPosition", &(track(2), 50_000_000i64))
+        .unwrap();
+    assert_eq!(fake.state().elapsed, 30.0);
+    player
+        .call_method("SetPosition", &(track(1), 150_000_000i64))
+        .unwrap();
+    assert_eq!(fake.state().elapsed, 150.0);
+    player.call_method("Seek", &(60_000_000i64)).unwrap();
+    assert_eq!(fake.state().current, Some(1));
+
+    player.set_property("LoopStatus", "Track").unwrap();
+    assert!(fake.state().repeat && fake.state().single);
+    player.set_property("LoopStatus", "Playlist").unwrap();
+    assert!(fake.state().repeat && !fake.state().single);
+    assert_eq!(
+        player.get_property::<String>("LoopStatus").unwrap(),
+        "Playlist"
+    );
+    player.set_property("Shuffle", true).unwrap();
+    assert!(fake.state().random);
+    player.set_property("Volume", 0.75).unwrap();
+    assert_eq!(fake.state().volume, 75);
+
+    // a second client gets a name of its own
+    let _second = Mpris::start(app, Some(&bus.address)).unwrap();
+    let names = DBusProxy::new(&conn).unwrap().list_names().unwrap();
+    let ours: Vec<String> = names
+        .iter()
+        .map(|n| n.to_string())
+        .filter(|n| n.starts_with(BUS_NAME))
+        .collect();
+    assert_eq!(ours.len(), 2);
+    assert!(ours.contains(&format!("{}.instance{}", BUS_NAME, std::process::id())));
+}
+
+#[test]
+fn changes_are_signalled() {
+    let (fake, app) = server();
+    let Some(bus) = Bus::start(&fake.dir().join("bus")) else {
+        return;
+    };
+    {
+        let mut s = fake.state();
+        s.set_queue(songs());
+        s.current = Some(0);
+        s.play = Playback::Pause;
+        s.elapsed = 20.0;
+    }
+    let mut mpris = Mpris::start(app.clone(), Some(&bus.address)).unwrap();
+    let signals = bus.signals("type='signal',path='/org/mpris/MediaPlayer2'");
+    let mut change = |f: &dyn Fn(&mut common::State)| {
+        f(&mut fake.state());
+        app.mpd.invalidate();
+        mpris.observe(&app.mpd);
+    };
+    change(&|_| {});
+    signals.next("PropertiesChanged");
+
+    // only what changed is sent
+    change(&|s| s.play = Playback::Play);
+    let m = signals.next("PropertiesChanged");
+    let (iface, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+        m.body().deserialize().unwrap();
+    assert_eq!(iface, PLAYER);
+    assert_eq!(changed.keys().collect::<Vec<_>>(), ["PlaybackStatus"]);
+    assert_eq!(
+        String::try_from(changed["PlaybackStatus"].try_clone().unwrap()).unwrap(),
+        "Playing"
+    );
+    change(&|_| {});
+    assert!(signals.quiet());
+
+    change(&|s| {
+        s.current = Some(1);
+        s.elapsed = 0.0;
+    });
+    let m = signals.next("PropertiesChanged");
+    let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+        m.body().deserialize().unwrap();
+    let metadata =
+        HashMap::<String, OwnedValue>::try_from(changed["Metadata"].try_clone().unwrap()).unwrap();
+    assert_eq!(text(&metadata, "xesam:title"), "Two");
+
+    // jumps within a song are seeks
+    change(&|s| {
+        s.play = Playback::Pause;
+        s.elapsed = 0.0;
+    });
+    signals.next("PropertiesChanged");
+    change(&|s| s.elapsed = 120.0);
+    let m = signals.next("Seeked");
+    assert_eq!(m.body().deserialize::<i64>().unwrap(), 120_000_000);
+
+    // and queue edits replace the track list
+    change(&|s| {
+        s.insert(3, vec![FakeSong::new("b.flac", &[], 60.0)]);
+    });
+    let m = signals.next("TrackListReplaced");
+    let (tracks, current): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+        m.body().deserialize().unwrap();
+    assert_eq!(tracks.len(), 4);
+    assert_eq!(current, track(2).into());
+}
+
+#[test]
+fn track_list_is_the_queue() {
+    let (fake, app) = server();
+    let Some(bus) = Bus::start(&fake.dir().join("bus")) else {
+        return;
+    };
+    {
+        let mut s = fake.state();
+        s.db = songs();
+        s.set_queue(songs());
+        s.current = Some(0);
+        s.play = Playback::Play;
+    }
+    let _mpris = Mpris::start(app, Some(&bus.address)).unwrap();
+    let conn = bus.connect();
+    let list = proxy(&conn, TRACK_LIST);
+    let tracks = || list.get_property::<Vec<OwnedObjectPath>>("Tracks").unwrap();
+    assert_eq!(
+        tracks(),
+        [track(1).into(), track(2).into(), track(3).into()]
+    );
+
+    let metadata: Vec<HashMap<String, OwnedValue>> = list
+        .call("GetTracksMetadata", &(vec![track(3), track(9), track(1)],))
+        .unwrap();
+    assert_eq!(metadata.len(), 2);
+    assert_eq!(text(&metadata[0], "xesam:title"), "Three");
+    assert_eq!(text(&metadata[1], "xesam:title"), "One");
+
+    list.call_method("GoTo", &(track(3),)).unwrap();
+    assert_eq!(fake.state().current, Some(2));
+
+    list.call_method("RemoveTrack", &(track(2),)).unwrap();
+    assert_eq!(fake.state().queue_files(), ["a/one.flac", "a/three.flac"]);
+    list.call_method("AddTrack", &("a/two.flac", track(1), true))
+        .unwrap();
+    assert_eq!(
+        fake.state().queue_files(),
+        ["a/one.flac", "a/two.flac", "a/three.flac"]
+    );
+    assert_eq!(fake.state().current, Some(1));
+    assert_eq!(tracks()[1], track(4).into());
+}
+
+#[test]
+fn tracks_are_looked_up_by_id() {
+    let (fake, app) = server();
+    let Some(bus) = Bus::start(&fake.dir().join("bus")) else {
+        return;
+    };
+    {
+        let mut s = fake.state();
+        let queue = (0..1000)
+            .map(|i| {
+                let title = format!("Q{}", i);
+                FakeSong::new(&format!("q/{:03}.flac", i), &[("Title", &title)], 60.0)
+            })
+            .collect();
+        s.set_queue(queue);
+        s.db = songs();
+        s.current = Some(0);
+        s.play = Playback::Play;
+    }
+    let _mpris = Mpris::start(app, Some(&bus.address)).unwrap();
+    let conn = bus.connect();
+    let list = proxy(&conn, TRACK_LIST);
+    assert_eq!(
+        list.get_property::<Vec<OwnedObjectPath>>("Tracks")
+            .unwrap()
+            .len(),
+        100
+    );
+    fake.state().log.clear();
+
+    // in the window around the current song, then far from it
+    let metadata: Vec<HashMap<String, OwnedValue>> = list
+        .call("GetTracksMetadata", &(vec![track(50), track(901)],))
+        .unwrap();
+    assert_eq!(text(&metadata[0], "xesam:title"), "Q49");
+    assert_eq!(text(&metadata[1], "xesam:title"), "Q900");
+    list.call_method("AddTrack", &("a/one.flac", track(801), false))
+        .unwrap();
+    assert_eq!(fake.state().queue_files()[801], "a/one.flac");
+
+    let s = fake.state();
+    assert!(s.commands("playlistinfo").is_empty());
+    assert_eq!(
+        s.commands("playlistid"),
+        ["playlistid \"901\"", "playlistid \"801\""]
+    );
+}
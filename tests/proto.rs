@@ -0,0 +1,186 @@
+// The raw protocol layer against canned transcripts: each test scripts what
+// the server replies to each line, and checks what the client sent.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    thread::{self, JoinHandle},
+};
+
+use mpcursive::mpd_util::proto::{Ack, Chunk, Connection};
+
+// A server that greets as the given version, then answers each line it reads
+// with the next reply. Yields the lines once the client hangs up.
+fn replay(version: &str, replies: Vec<&'static [u8]>) -> (Connection, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let banner = format!("OK MPD {}\n", version);
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(banner.as_bytes()).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut received = vec![];
+        for reply in replies {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            received.push(line.trim_end().to_string());
+            stream.write_all(reply).unwrap();
+        }
+        received
+    });
+    (Connection::connect(address).unwrap(), server)
+}
+
+fn sent(conn: Connection, server: JoinHandle<Vec<String>>) -> Vec<String> {
+    drop(conn);
+    server.join().unwrap()
+}
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn binary_payloads_are_read_from_offsets() {
+    let (mut conn, server) = replay(
+        "0.23.5",
+        vec![
+            b"size: 10\nbinary: 4\n\n\xff\x00\n\nOK\n",
+            b"size: 10\nbinary: 4\nOK\n\n\nOK\n",
+            b"size: 10\nbinary: 2\nab\nOK\n",
+        ],
+    );
+    let cover = conn.read_binary("albumart", "a/b c.flac").unwrap();
+    assert_eq!(
+        cover,
+        Some(Chunk {
+            pairs: pairs(&[("size", "10")]),
+            data: b"\n\xff\x00\nOK\n\nab".to_vec(),
+        })
+    );
+    assert_eq!(
+        sent(conn, server),
+        [
+            "albumart \"a/b c.flac\" \"0\"",
+            "albumart \"a/b c.flac\" \"4\"",
+            "albumart \"a/b c.flac\" \"8\"",
+        ]
+    );
+}
+
+#[test]
+fn pairs_describe_the_payload() {
+    let (mut conn, server) = replay(
+        "0.23.5",
+        vec![b"size: 3\ntype: image/jpeg\nbinary: 3\nabc\nOK\n"],
+    );
+    let picture = conn.read_binary("readpicture", "x.flac").unwrap().unwrap();
+    assert_eq!(
+        picture.pairs,
+        pairs(&[("size", "3"), ("type", "image/jpeg")])
+    );
+    assert_eq!(picture.data, b"abc");
+    assert_eq!(sent(conn, server), ["readpicture \"x.flac\" \"0\""]);
+}
+
+#[test]
+fn missing_payloads() {
+    let (mut conn, server) = replay(
+        "0.23.5",
+        vec![b"OK\n", b"ACK [50@0] {albumart} No file exists\n"],
+    );
+    // readpicture on a file without a picture has no size
+    assert_eq!(conn.read_binary("readpicture", "x.flac").unwrap(), None);
+    // albumart without a cover file is an error
+    let e = conn.read_binary("albumart", "x.flac").unwrap_err();
+    let ack = e.downcast_ref::<Ack>().unwrap();
+    assert_eq!((ack.code, ack.message.as_str()), (50, "No file exists"));
+    assert_eq!(sent(conn, server).len(), 2);
+}
+
+#[test]
+fn broken_payloads() {
+    let (mut conn, _server) = replay(
+        "0.23.5",
+        vec![
+            b"size: 8\nbinary: 4\nabcd\nOK\n",
+            b"size: 8\nbinary: 0\n\nOK\n",
+        ],
+    );
+    let e = conn.read_binary("albumart", "x.flac").unwrap_err();
+    assert_eq!(e.to_string(), "albumart stopped at 4 of 8 bytes");
+
+    // more bytes than announced
+    let (mut conn, _server) = replay("0.23.5", vec![b"size: 2\nbinary: 2\nabc\nOK\n"]);
+    assert!(conn.read_binary("albumart", "x.flac").is_err());
+}
+
+#[test]
+fn binary_limit() {
+    let (mut conn, server) = replay("0.23.5", vec![b"OK\n"]);
+    conn.binary_limit(65536).unwrap();
+    assert_eq!(sent(conn, server), ["binarylimit \"65536\""]);
+
+    // older servers don't know the command, and keep their default
+    let (mut conn, server) = replay("0.22.3", vec![]);
+    conn.binary_limit(65536).unwrap();
+    assert!(sent(conn, server).is_empty());
+}
+
+#[test]
+fn fingerprints() {
+    let (mut conn, server) = replay(
+        "0.23.5",
+        vec![
+            b"chromaprint: AQAAT0mUaEkSRZEGAA\nOK\n",
+            b"ACK [5@0] {} unknown command \"getfingerprint\"\n",
+        ],
+    );
+    assert_eq!(
+        conn.fingerprint("x.flac").unwrap().as_deref(),
+        Some("AQAAT0mUaEkSRZEGAA")
+    );
+    // a server built without chromaprint
+    let e = conn.fingerprint("x.flac").unwrap_err();
+    assert_eq!(e.downcast_ref::<Ack>().unwrap().code, 5);
+    assert_eq!(
+        sent(conn, server),
+        ["getfingerprint \"x.flac\"", "getfingerprint \"x.flac\""]
+    );
+}
+
+#[test]
+fn comments_keep_every_value() {
+    let (mut conn, server) = replay(
+        "0.23.5",
+        vec![
+            b"ARTIST: A\nARTIST: B\n\
+            LYRICS: [00:01.00]Verse: one\n\
+            replaygain_track_gain: -6.2 dB\nOK\n",
+        ],
+    );
+    assert_eq!(
+        conn.comments("x.flac").unwrap(),
+        pairs(&[
+            ("ARTIST", "A"),
+            ("ARTIST", "B"),
+            ("LYRICS", "[00:01.00]Verse: one"),
+            ("replaygain_track_gain", "-6.2 dB"),
+        ])
+    );
+    assert_eq!(sent(conn, server), ["readcomments \"x.flac\""]);
+}
+
+#[test]
+fn protocol_versions() {
+    let (conn, _server) = replay("0.22.4", vec![]);
+    assert_eq!(conn.version, "0.22.4");
+    assert!(conn.at_least((0, 22, 4)));
+    assert!(conn.at_least((0, 21, 30)));
+    assert!(!conn.at_least((0, 23, 0)));
+}
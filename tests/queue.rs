@@ -0,0 +1,156 @@
+use std::ops::Range;
+
+use anyhow::Result;
+use mpcursive::mpd_util::queue::{Queue, QueueSource};
+use mpd::{
+    song::{Id, QueuePlace},
+    Song,
+};
+
+// A queue in memory, remembering what it was asked for
+#[derive(Default)]
+struct Source {
+    songs: Vec<Song>,
+    changes: Vec<usize>,
+    windows: Vec<Range<usize>>,
+}
+
+impl Source {
+    fn new(titles: &[&str]) -> Self {
+        let mut source = Self::default();
+        for (i, title) in titles.iter().enumerate() {
+            source.songs.push(song(i, i as u32, title));
+        }
+        source
+    }
+}
+
+fn song(pos: usize, id: u32, title: &str) -> Song {
+    Song {
+        file: format!("{}.flac", title),
+        title: Some(title.into()),
+        place: Some(QueuePlace {
+            id: Id(id),
+            pos: pos as u32,
+            prio: 0,
+        }),
+        ..Default::default()
+    }
+}
+
+impl QueueSource for Source {
+    fn window(&mut self, range: Range<usize>) -> Result<Vec<Song>> {
+        self.windows.push(range.clone());
+        Ok(self.songs[range.start.min(self.songs.len())..range.end.min(self.songs.len())].to_vec())
+    }
+
+    fn changes(&mut self, _version: u32) -> Result<Vec<usize>> {
+        Ok(self.changes.clone())
+    }
+}
+
+fn titles(queue: &mut Queue, range: Range<usize>, source: &mut Source) -> Vec<String> {
+    queue
+        .window(range, source)
+        .unwrap()
+        .iter()
+        .map(|s| s.display_title())
+        .collect()
+}
+
+#[test]
+fn window_fetches_only_what_is_missing() {
+    let mut source = Source::new(&["a", "b", "c", "d", "e", "f"]);
+    let mut queue = Queue::new();
+    queue.sync(1, 6, &mut source).unwrap();
+    assert_eq!(queue.len(), 6);
+    assert!(queue.get(0).is_none());
+    assert!(source.windows.is_empty());
+
+    assert_eq!(titles(&mut queue, 1..3, &mut source), ["b", "c"]);
+    assert_eq!(titles(&mut queue, 4..5, &mut source), ["e"]);
+    // one request per gap
+    assert_eq!(
+        titles(&mut queue, 0..6, &mut source),
+        ["a", "b", "c", "d", "e", "f"]
+    );
+    assert_eq!(source.windows, [1..3, 4..5, 0..1, 3..4, 5..6]);
+    assert_eq!(queue.get(3).unwrap().display_title(), "d");
+
+    // nothing left to ask for
+    assert_eq!(titles(&mut queue, 2..5, &mut source), ["c", "d", "e"]);
+    assert_eq!(source.windows.len(), 5);
+}
+
+#[test]
+fn window_stops_at_the_end() {
+    let mut source = Source::new(&["a", "b", "c"]);
+    let mut queue = Queue::new();
+    queue.sync(1, 3, &mut source).unwrap();
+    assert_eq!(titles(&mut queue, 1..50, &mut source), ["b", "c"]);
+    assert_eq!(source.windows, vec![1..3]);
+    assert!(titles(&mut queue, 10..20, &mut source).is_empty());
+    assert_eq!(source.windows.len(), 1);
+}
+
+#[test]
+fn window_starts_over_when_the_queue_changed_underneath() {
+    let mut source = Source::new(&["a", "b", "c", "d"]);
+    let mut queue = Queue::new();
+    queue.sync(1, 4, &mut source).unwrap();
+    assert_eq!(titles(&mut queue, 0..1, &mut source), ["a"]);
+
+    // songs removed between status and playlistinfo
+    source.songs.truncate(2);
+    assert!(queue.window(0..4, &mut source).is_err());
+    assert_eq!(queue.version(), None);
+    assert!(queue.is_empty());
+
+    queue.sync(2, 2, &mut source).unwrap();
+    assert_eq!(titles(&mut queue, 0..2, &mut source), ["a", "b"]);
+}
+
+#[test]
+fn sync_empties_every_changed_position() {
+    let mut source = Source::new(&["a", "b", "c", "d"]);
+    let mut queue = Queue::new();
+    queue.sync(1, 4, &mut source).unwrap();
+    assert_eq!(titles(&mut queue, 0..4, &mut source), ["a", "b", "c", "d"]);
+
+    // "b" retagged in place and "d" moved up
+    source.songs[1] = song(1, 1, "b2");
+    source.songs.swap(2, 3);
+    source.changes = vec![1, 2, 3];
+    source.windows.clear();
+    queue.sync(2, 4, &mut source).unwrap();
+    assert_eq!(queue.version(), Some(2));
+    assert!(queue.get(0).is_some());
+    assert!((1..4).all(|pos| queue.get(pos).is_none()));
+    assert_eq!(titles(&mut queue, 0..4, &mut source), ["a", "b2", "d", "c"]);
+    assert_eq!(source.windows, vec![1..4]);
+
+    // a shorter queue loses its tail
+    source.songs.truncate(2);
+    source.changes = vec![];
+    queue.sync(3, 2, &mut source).unwrap();
+    assert_eq!(titles(&mut queue, 0..4, &mut source), ["a", "b2"]);
+}
+
+#[test]
+fn sync_starts_over_when_changes_dont_add_up() {
+    let mut source = Source::new(&["a", "b"]);
+    let mut queue = Queue::new();
+    queue.sync(5, 2, &mut source).unwrap();
+    assert_eq!(titles(&mut queue, 0..2, &mut source), ["a", "b"]);
+
+    // a change past the end
+    source.changes = vec![7];
+    queue.sync(6, 2, &mut source).unwrap();
+    assert!(queue.get(0).is_none() && queue.get(1).is_none());
+    assert_eq!(titles(&mut queue, 0..2, &mut source), ["a", "b"]);
+
+    // a restarted server counts versions from the start again
+    queue.sync(1, 2, &mut source).unwrap();
+    assert_eq!(queue.version(), Some(1));
+    assert!(queue.get(0).is_none());
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use common::{screen::Screen, server, FakeMpd, FakeSong, Playback};
+use mpcursive::{
+    app::App,
+    config::ResumeConfig,
+    meta::SongMeta,
+    mpd_util::{idle::Observer, proto::songs_from_pairs},
+    resume::{self, Resumer, POSITION},
+};
+
+fn setup() -> (FakeMpd, App) {
+    let (fake, _) = server();
+    let mut config = fake.config();
+    config.resume.directories = vec!["Books/".into()];
+    let app = fake.app_with(config);
+    fake.state().set_queue(vec![
+        FakeSong::new("Books/Long.mp3", &[("Title", "Long")], 600.0),
+        FakeSong::new("Music/Short.flac", &[("Title", "Short")], 60.0),
+    ]);
+    (fake, app)
+}
+
+// Moves the fake to a state and lets the resumer see it, as an idle event
+// or tick would
+fn at(fake: &FakeMpd, app: &App, resumer: &mut Resumer, song: usize, play: Playback, secs: f64) {
+    {
+        let mut s = fake.state();
+        s.current = Some(song);
+        s.play = play;
+        s.elapsed = secs;
+    }
+    app.mpd.invalidate();
+    resumer.observe(&app.mpd);
+}
+
+fn saved(fake: &FakeMpd) -> Option<f64> {
+    let s = fake.state();
+    let stickers = s.stickers.get("Books/Long.mp3")?;
+    stickers
+        .iter()
+        .find(|(n, _)| n == POSITION)
+        .map(|(_, v)| v.parse().unwrap())
+}
+
+#[test]
+fn resumable_songs() {
+    let config = ResumeConfig {
+        directories: vec!["Books".into()],
+        genres: vec!["audiobook".into()],
+    };
+    let song = |pairs: &str| {
+        let pairs = pairs
+            .split(';')
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        SongMeta::new(songs_from_pairs(pairs).remove(0))
+    };
+    assert!(resume::resumable(&config, &song("file=Books/a/1.mp3")));
+    assert!(!resume::resumable(&config, &song("file=Bookshelf/1.mp3")));
+    assert!(!resume::resumable(&config, &song("file=Books")));
+    assert!(resume::resumable(
+        &config,
+        &song("file=Other/1.mp3;Genre=Audiobook")
+    ));
+    assert!(config.enabled());
+    assert!(!ResumeConfig::default().enabled());
+}
+
+#[test]
+fn positions_are_saved_and_restored() {
+    let (fake, app) = setup();
+    let mut resumer = Resumer::new(app.clone());
+
+    // paused
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 0.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 125.0);
+    assert_eq!(saved(&fake), None);
+    at(&fake, &app, &mut resumer, 0, Playback::Pause, 125.0);
+    assert_eq!(saved(&fake), Some(125.0));
+
+    // skipped, at the last position seen
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 200.0);
+    at(&fake, &app, &mut resumer, 1, Playback::Play, 0.0);
+    assert_eq!(saved(&fake), Some(200.0));
+
+    // played again: back where it was, with a way to start over
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 0.0);
+    assert_eq!(fake.state().elapsed, 200.0);
+    let mut screen = Screen::new(app.clone(), 100, 6);
+    assert!(screen
+        .render()
+        .contains("Resumed at 3:20, press R to start over"));
+    screen.keys("R");
+    assert_eq!(fake.state().elapsed, 0.0);
+    assert_eq!(saved(&fake), None);
+    assert!(!screen.render().contains("Resumed"));
+
+    // finishing forgets the position
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 300.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Pause, 300.0);
+    assert_eq!(saved(&fake), Some(300.0));
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 595.0);
+    at(&fake, &app, &mut resumer, 1, Playback::Play, 0.0);
+    assert_eq!(saved(&fake), None);
+
+    // other songs are left alone
+    at(&fake, &app, &mut resumer, 1, Playback::Play, 30.0);
+    at(&fake, &app, &mut resumer, 1, Playback::Pause, 30.0);
+    assert!(!fake.state().stickers.contains_key("Music/Short.flac"));
+}
+
+#[test]
+fn exit_saves_the_position() {
+    let (fake, app) = setup();
+    let mut resumer = Resumer::new(app.clone());
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 0.0);
+    fake.state().elapsed = 400.0;
+    app.mpd.invalidate();
+    resumer.close(&app.mpd);
+    assert!(saved(&fake).is_some_and(|s| (400.0..401.0).contains(&s)));
+
+    // stopping keeps the last position seen
+    let mut resumer = Resumer::new(app.clone());
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 0.0);
+    assert_eq!(fake.state().elapsed, 400.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 450.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Stop, 0.0);
+    assert_eq!(saved(&fake), Some(450.0));
+}
+
+#[test]
+fn songs_already_playing_are_left_alone() {
+    let (fake, app) = setup();
+    let mut resumer = Resumer::new(app.clone());
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 0.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Pause, 200.0);
+    assert_eq!(saved(&fake), Some(200.0));
+
+    // a client started while the song plays, e.g. from the start again
+    let mut resumer = Resumer::new(app.clone());
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 50.0);
+    assert_eq!(fake.state().elapsed, 50.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 51.0);
+    assert_eq!(fake.state().elapsed, 51.0);
+
+    // the same after reconnecting
+    at(&fake, &app, &mut resumer, 0, Playback::Pause, 200.0);
+    at(&fake, &app, &mut resumer, 1, Playback::Play, 5.0);
+    resumer.connected(&app.mpd);
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 20.0);
+    assert_eq!(fake.state().elapsed, 20.0);
+
+    // but a change seen happen is resumed, even past the start
+    at(&fake, &app, &mut resumer, 1, Playback::Play, 5.0);
+    at(&fake, &app, &mut resumer, 0, Playback::Play, 4.0);
+    assert_eq!(fake.state().elapsed, 20.0);
+}
@@ -0,0 +1,281 @@
+mod common;
+
+use std::{
+    fs,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use common::{eventually, http::StandIn, server};
+use mpcursive::{
+    config::Config,
+    meta::SongMeta,
+    mpd_util::proto::songs_from_pairs,
+    scrobble::{
+        self,
+        backend::{self, FileSink, LastFm, ListenBrainz},
+        Queue, Scrobbler, Submission, Track,
+    },
+};
+use serde_json::Value;
+
+fn song(id: u32, title: &str, seconds: u64) -> SongMeta {
+    let pairs = [
+        ("file", format!("{}.flac", title)),
+        ("Artist", String::from("Band")),
+        ("Title", title.to_string()),
+        ("Album", String::from("Record")),
+        ("Track", String::from("3/9")),
+        ("Time", seconds.to_string()),
+        ("Pos", id.to_string()),
+        ("Id", id.to_string()),
+    ];
+    SongMeta::new(songs_from_pairs(pairs.map(|(k, v)| (k.into(), v)).to_vec()).remove(0))
+}
+
+fn listen(title: &str, at: u64) -> Submission {
+    Submission::Listen {
+        track: Track::new(&song(1, title, 200), "; ").unwrap(),
+        listened_at: at,
+    }
+}
+
+fn kinds(queue: &Queue) -> Vec<String> {
+    queue
+        .pending()
+        .iter()
+        .map(|s| match s {
+            Submission::NowPlaying { track } => format!("now {}", track.title),
+            Submission::Listen { track, .. } => format!("listen {}", track.title),
+        })
+        .collect()
+}
+
+#[test]
+fn scrobble_rules() {
+    let queue = Queue::open(None);
+    let mut scrobbler = Scrobbler::new(queue.clone(), "; ");
+    let start = Instant::now();
+    let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    // listens to song from second first to last, having started it at began
+    let mut play = |song: &SongMeta, began: u64, first: u64, last: u64| {
+        for s in first..=last {
+            scrobbler.listen(
+                Some(song),
+                Duration::from_secs(s - began),
+                start + Duration::from_secs(s),
+                at + Duration::from_secs(s),
+            );
+        }
+    };
+
+    // half of a short song
+    let a = song(1, "A", 100);
+    play(&a, 0, 0, 49);
+    assert_eq!(kinds(&queue), ["now A"]);
+    play(&a, 0, 50, 50);
+    assert_eq!(kinds(&queue), ["listen A"]);
+
+    // long songs count after four minutes
+    let b = song(2, "B", 3600);
+    play(&b, 100, 100, 339);
+    assert_eq!(kinds(&queue), ["listen A", "now B"]);
+    play(&b, 100, 340, 340);
+    assert_eq!(kinds(&queue), ["listen A", "listen B"]);
+
+    // too short to count at all
+    let c = song(3, "C", 30);
+    play(&c, 400, 400, 430);
+    assert_eq!(kinds(&queue), ["listen A", "listen B", "now C"]);
+
+    // the start of listening is what's submitted
+    let pending = queue.pending();
+    let Submission::Listen { track, listened_at } = &pending[1] else {
+        panic!("{:?}", pending);
+    };
+    assert_eq!(*listened_at, 1_700_000_100);
+    assert_eq!(track.artist, "Band");
+    assert_eq!(track.album.as_deref(), Some("Record"));
+    assert_eq!(track.track_number, Some(3));
+    assert_eq!(track.duration, Some(3600));
+
+    // stopped, then the same song again is announced again
+    let mut scrobbler = Scrobbler::new(queue.clone(), "; ");
+    scrobbler.listen(Some(&c), Duration::ZERO, start, at);
+    scrobbler.listen(None, Duration::ZERO, start, at);
+    queue.push(listen("D", 1));
+    scrobbler.listen(Some(&c), Duration::ZERO, start, at);
+    assert_eq!(kinds(&queue), ["listen A", "listen B", "listen D", "now C"]);
+}
+
+#[test]
+fn listenbrainz_submissions() {
+    let api = StandIn::start();
+    let backend = ListenBrainz::new(&api.url, "secret-token");
+    let queue = Queue::open(None);
+    queue.push(listen("A", 1_700_000_000));
+    queue.push(Submission::NowPlaying {
+        track: Track::new(&song(2, "B", 200), "; ").unwrap(),
+    });
+
+    // the server is down: nothing is lost
+    api.respond(503, "{}");
+    assert!(queue.flush(&backend).is_err());
+    assert_eq!(queue.pending().len(), 2);
+    assert_eq!(queue.flush(&backend).unwrap(), 2);
+    assert!(queue.pending().is_empty());
+
+    let requests = api.requests();
+    assert_eq!(requests.len(), 3);
+    let request = &requests[1];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/1/submit-listens");
+    assert_eq!(request.header("Authorization"), Some("Token secret-token"));
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["listen_type"], "single");
+    let sent = &body["payload"][0];
+    assert_eq!(sent["listened_at"], 1_700_000_000);
+    assert_eq!(sent["track_metadata"]["artist_name"], "Band");
+    assert_eq!(sent["track_metadata"]["track_name"], "A");
+    assert_eq!(sent["track_metadata"]["release_name"], "Record");
+    assert_eq!(
+        sent["track_metadata"]["additional_info"]["duration_ms"],
+        200_000
+    );
+    let body: Value = serde_json::from_str(&requests[2].body).unwrap();
+    assert_eq!(body["listen_type"], "playing_now");
+    assert!(body["payload"][0].get("listened_at").is_none());
+
+    // a malformed one is dropped, the rest go on
+    queue.push(listen("C", 1));
+    queue.push(listen("D", 2));
+    api.respond(400, r#"{"code": 400, "error": "bad"}"#);
+    assert_eq!(queue.flush(&backend).unwrap(), 1);
+    assert!(queue.pending().is_empty());
+}
+
+#[test]
+fn lastfm_submissions() {
+    let api = StandIn::start();
+    let backend = LastFm::new(&format!("{}/2.0/", api.url), "k", "secret", "session");
+    assert_eq!(
+        backend.sign(&[
+            ("method", String::from("m")),
+            ("api_key", String::from("k")),
+            ("format", String::from("json")),
+        ]),
+        "8cfd4d1ea06d010ab9a2fed05c068c67"
+    );
+
+    let queue = Queue::open(None);
+    queue.push(listen("A", 1_700_000_000));
+    api.respond(200, r#"{"error": 11, "message": "Service Offline"}"#);
+    assert!(queue.flush(&backend).is_err());
+    api.respond(200, r#"{"scrobbles": {}}"#);
+    assert_eq!(queue.flush(&backend).unwrap(), 1);
+
+    let requests = api.requests();
+    assert_eq!(requests[1].path, "/2.0/");
+    let form = requests[1].form();
+    let field = |name: &str| {
+        form.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+    assert_eq!(field("method"), Some("track.scrobble"));
+    assert_eq!(field("artist"), Some("Band"));
+    assert_eq!(field("track"), Some("A"));
+    assert_eq!(field("trackNumber"), Some("3"));
+    assert_eq!(field("timestamp"), Some("1700000000"));
+    assert_eq!(field("sk"), Some("session"));
+    let signed: Vec<(&str, String)> = form
+        .iter()
+        .filter(|(k, _)| k != "api_sig")
+        .map(|(k, v)| (k.as_str(), v.clone()))
+        .collect();
+    assert_eq!(field("api_sig"), Some(backend.sign(&signed).as_str()));
+
+    // an invalid parameter won't get better
+    queue.push(Submission::NowPlaying {
+        track: Track::new(&song(2, "B", 200), "; ").unwrap(),
+    });
+    api.respond(400, r#"{"error": 6, "message": "Invalid parameters"}"#);
+    assert_eq!(queue.flush(&backend).unwrap(), 0);
+    assert!(queue.pending().is_empty());
+    assert_eq!(
+        api.requests()[2].form()[0],
+        ("method".into(), "track.updateNowPlaying".into())
+    );
+}
+
+#[test]
+fn queue_is_kept_on_disk() {
+    let (fake, _) = server();
+    let dir = fake.dir().join("scrobble");
+    let _ = fs::remove_dir_all(&dir);
+    let queue = Queue::open(Some(dir.join("queue.json")));
+    queue.push(listen("A", 1));
+    queue.push(Submission::NowPlaying {
+        track: Track::new(&song(2, "B", 200), "; ").unwrap(),
+    });
+    // a newer one makes the now playing stale
+    queue.push(listen("B", 2));
+    let reopened = Queue::open(Some(dir.join("queue.json")));
+    assert_eq!(kinds(&reopened), ["listen A", "listen B"]);
+
+    // listens exported for importing later
+    let sink = FileSink::new(dir.join("listens.jsonl"));
+    reopened.push(Submission::NowPlaying {
+        track: Track::new(&song(3, "C", 200), "; ").unwrap(),
+    });
+    assert_eq!(reopened.flush(&sink).unwrap(), 3);
+    let exported = fs::read_to_string(dir.join("listens.jsonl")).unwrap();
+    let lines: Vec<Value> = exported
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["listened_at"], 2);
+    assert_eq!(lines[1]["track_metadata"]["track_name"], "B");
+    assert!(Queue::open(Some(dir.join("queue.json")))
+        .pending()
+        .is_empty());
+}
+
+#[test]
+fn submitted_in_the_background_with_retries() {
+    let api = StandIn::start();
+    let queue = Queue::open(None);
+    api.respond(500, "{}");
+    api.respond(502, "{}");
+    scrobble::spawn(
+        queue.clone(),
+        Box::new(ListenBrainz::new(&api.url, "t")),
+        Duration::from_millis(20),
+    );
+    queue.push(listen("A", 1));
+    eventually("the listen to go through", || queue.pending().is_empty());
+    assert_eq!(api.requests().len(), 3);
+}
+
+#[test]
+fn backends_from_config() {
+    let backend = |toml: &str| {
+        let config = Config::parse(toml).unwrap();
+        backend::from_config(&config.scrobble).map(|b| b.map(|b| b.name().to_string()))
+    };
+    assert!(backend("").unwrap().is_none());
+    assert!(backend("[scrobble]\nbackend = \"listenbrainz\"").is_err());
+    assert_eq!(
+        backend("[scrobble]\nbackend = \"listenbrainz\"\ntoken = \"t\"")
+            .unwrap()
+            .as_deref(),
+        Some("ListenBrainz")
+    );
+    assert!(backend("[scrobble]\nbackend = \"lastfm\"\napi_key = \"k\"").is_err());
+    assert_eq!(
+        backend("[scrobble]\nbackend = \"file\"\nfile = \"/tmp/x\"")
+            .unwrap()
+            .as_deref(),
+        Some("File")
+    );
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use std::io::Cursor;
+
+use common::{eventually, library, screen::Screen, server, FakeSong, Playback};
+use cursive::{event::Key, event::MouseEvent, Cursive};
+use image::{ImageFormat, Rgba, RgbaImage};
+use mpcursive::art::graphics::{Graphics, Protocol};
+use mpcursive::mpd_util::library_cache;
+use mpcursive::view::art::AlbumArt;
+
+fn album(n: usize) -> Vec<FakeSong> {
+    (0..n)
+        .map(|i| {
+            FakeSong::new(
+                &format!("Band/Record/{:02}.flac", i + 1),
+                &[
+                    ("Artist", "Band"),
+                    ("Album", "Record"),
+                    ("Title", &format!("Track number {}", i + 1)),
+                    ("Track", &(i + 1).to_string()),
+                    ("Date", "1999"),
+                ],
+                200.0 + i as f64,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn queue_page() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.set_queue(album(12));
+        s.current = Some(2);
+        s.play = Playback::Pause;
+        s.elapsed = 61.0;
+        s.repeat = true;
+    }
+
+    let mut screen = Screen::new(app, 80, 12);
+    screen.keys("2");
+    screen.assert_snapshot("queue_page");
+}
+
+#[test]
+fn queue_page_narrow() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.set_queue(album(12));
+        s.current = Some(0);
+        s.play = Playback::Pause;
+        s.elapsed = 5.0;
+    }
+
+    let mut screen = Screen::new(app, 36, 10);
+    screen.keys("2");
+    screen.assert_snapshot("queue_page_narrow");
+}
+
+#[test]
+fn queue_scroll_and_select() {
+    let (fake, app) = server();
+    fake.state().set_queue(album(30));
+
+    let mut screen = Screen::new(app, 60, 12);
+    screen.keys("2");
+    screen.mouse(10, 6, MouseEvent::WheelDown);
+    screen.click(10, 6);
+    screen.assert_snapshot("queue_scroll_and_select");
+}
+
+#[test]
+fn library_filter() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.db = library(3, 2, 2);
+        s.db_update += 1;
+    }
+    library_cache::refresh(&app.mpd, Cursive::new().cb_sink().clone());
+    eventually("library", || {
+        app.mpd.library().is_some_and(|l| l.len() == 12)
+    });
+
+    let mut screen = Screen::new(app, 70, 12);
+    screen.keys("3/album:1");
+    screen.key(Key::Enter);
+    screen.key(Key::Down);
+    screen.assert_snapshot("library_filter");
+}
+
+#[test]
+fn command_line_takes_the_title() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.set_queue(album(3));
+        s.current = Some(1);
+        s.play = Playback::Pause;
+        s.elapsed = 10.0;
+    }
+
+    let mut screen = Screen::new(app, 50, 8);
+    screen.keys("2:vol");
+    screen.assert_snapshot("command_line");
+}
+
+#[test]
+fn album_art() {
+    let (fake, app) = server();
+    // quarters of red, green, blue and white
+    let cover = RgbaImage::from_fn(8, 8, |x, y| match (x < 4, y < 4) {
+        (true, true) => Rgba([255, 0, 0, 255]),
+        (false, true) => Rgba([0, 255, 0, 255]),
+        (true, false) => Rgba([0, 0, 255, 255]),
+        (false, false) => Rgba([255, 255, 255, 255]),
+    });
+    let mut png = Cursor::new(vec![]);
+    cover.write_to(&mut png, ImageFormat::Png).unwrap();
+    {
+        let mut s = fake.state();
+        s.set_queue(album(3));
+        s.current = Some(0);
+        s.play = Playback::Pause;
+        s.covers.insert("Band/Record".into(), png.into_inner());
+    }
+
+    let mut screen = Screen::with(app.clone(), 30, 10, |root, sink| {
+        let graphics = Graphics::new(Protocol::HalfBlock);
+        root.register("Art", '4', "▣", AlbumArt::new(app, sink, graphics));
+    });
+    screen.keys("4");
+    eventually("the cover to load", || !screen.render().contains("Loading"));
+    screen.assert_snapshot("album_art");
+}
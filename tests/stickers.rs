@@ -0,0 +1,237 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use common::{screen::Screen, server, FakeSong, Playback};
+use mpcursive::{
+    library::Filter,
+    meta::SongMeta,
+    mpd_util::{
+        idle::Observer,
+        proto::songs_from_pairs,
+        stickers::{stars, PlayCounter, Ratings},
+    },
+    plays::Plays,
+};
+
+fn song(file: &str, pos: u32, id: u32, duration: u64) -> SongMeta {
+    let pairs = [
+        ("file", file.to_string()),
+        ("Time", duration.to_string()),
+        ("Pos", pos.to_string()),
+        ("Id", id.to_string()),
+    ];
+    SongMeta::new(songs_from_pairs(pairs.map(|(k, v)| (k.into(), v)).to_vec()).remove(0))
+}
+
+#[test]
+fn sticker_commands() {
+    let (fake, app) = server();
+    let result = app.mpd.with_raw(|raw| {
+        assert_eq!(raw.sticker("a.flac", "rating")?, None);
+        raw.set_sticker("a.flac", "rating", "4")?;
+        raw.set_sticker("a.flac", "mood", "x=y")?;
+        raw.set_sticker("dir/b.flac", "rating", "2")?;
+        assert_eq!(raw.sticker("a.flac", "rating")?.as_deref(), Some("4"));
+        assert_eq!(
+            raw.stickers("a.flac")?,
+            [("rating".into(), "4".into()), ("mood".into(), "x=y".into())]
+        );
+        assert_eq!(
+            raw.find_stickers("", "rating")?,
+            [
+                ("a.flac".into(), "4".into()),
+                ("dir/b.flac".into(), "2".into())
+            ]
+        );
+        assert_eq!(
+            raw.find_stickers("dir", "rating")?,
+            [("dir/b.flac".into(), "2".into())]
+        );
+        raw.delete_sticker("a.flac", "rating")?;
+        // gone already, which is fine
+        raw.delete_sticker("a.flac", "rating")?;
+        assert_eq!(raw.sticker("a.flac", "rating")?, None);
+        Ok(())
+    });
+    result.unwrap();
+    assert_eq!(
+        fake.state().stickers["a.flac"],
+        [("mood".into(), "x=y".into())]
+    );
+}
+
+#[test]
+fn ratings_are_cached_until_changed() {
+    let (fake, app) = server();
+    fake.state()
+        .stickers
+        .insert("a.flac".into(), vec![("rating".into(), "9".into())]);
+
+    let ratings = app.mpd.ratings();
+    // out of range values are clamped
+    assert_eq!(ratings.get("a.flac"), Some(5));
+    assert_eq!(ratings.get("b.flac"), None);
+    assert!(Arc::ptr_eq(&ratings, &app.mpd.ratings()));
+    assert_eq!(fake.state().commands("sticker").len(), 1);
+
+    app.mpd.set_rating("b.flac", 3).unwrap();
+    app.mpd.set_rating("a.flac", 0).unwrap();
+    let updated = app.mpd.ratings();
+    assert_eq!(updated.get("a.flac"), None);
+    assert_eq!(updated.get("b.flac"), Some(3));
+    assert!(updated.generation > ratings.generation);
+
+    // a server without a sticker database just has no ratings
+    fake.state()
+        .fail("sticker", 5, "unknown command \"sticker\"");
+    app.mpd.invalidate_stickers();
+    assert_eq!(app.mpd.ratings().len(), 1);
+    assert_eq!(stars(3), "★★★☆☆");
+}
+
+#[test]
+fn rating_filters() {
+    let songs: Vec<SongMeta> = ["a.flac", "b.flac", "c.flac"]
+        .iter()
+        .map(|f| song(f, 0, 1, 60))
+        .collect();
+    let ratings = Arc::new(Ratings::new(
+        0,
+        HashMap::from([("a.flac".into(), 5), ("b.flac".into(), 3)]),
+    ));
+    let files = |filter: &str| -> Vec<&str> {
+        let filter = Filter::parse(filter).with_ratings(ratings.clone());
+        songs
+            .iter()
+            .filter(|s| filter.matches(s))
+            .map(|s| s.file())
+            .collect()
+    };
+    assert_eq!(files("rating>=4"), ["a.flac"]);
+    assert_eq!(files("rating<4"), ["b.flac", "c.flac"]);
+    assert_eq!(files("Rating:3"), ["b.flac"]);
+    assert_eq!(files("rating=0"), ["c.flac"]);
+    assert_eq!(files("rating>0 file:b"), ["b.flac"]);
+    // not a rating term, so it's searched for as text
+    assert!(files("rating>=9").is_empty());
+}
+
+#[test]
+fn plays_count_after_listening_long_enough() {
+    let mut counter = PlayCounter::new(50);
+    let start = Instant::now();
+    let at = |s: u64| start + Duration::from_secs(s);
+    let a = song("a.flac", 0, 1, 10);
+
+    // seeking ahead doesn't count as listening
+    assert_eq!(counter.listen(Some(&a), Duration::ZERO, at(0)), None);
+    assert_eq!(
+        counter.listen(Some(&a), Duration::from_secs(8), at(1)),
+        None
+    );
+    for s in 2..5 {
+        assert_eq!(
+            counter.listen(Some(&a), Duration::from_secs(8), at(s)),
+            None
+        );
+    }
+    // a long gap is a pause, not listening
+    assert_eq!(
+        counter.listen(Some(&a), Duration::from_secs(9), at(60)),
+        None
+    );
+    assert_eq!(
+        counter.listen(Some(&a), Duration::from_secs(9), at(61)),
+        Some("a.flac".into())
+    );
+    // once per play
+    assert_eq!(
+        counter.listen(Some(&a), Duration::from_secs(9), at(62)),
+        None
+    );
+
+    // starting over counts again
+    assert_eq!(counter.listen(Some(&a), Duration::ZERO, at(63)), None);
+    let counted = (64..70).filter_map(|s| counter.listen(Some(&a), Duration::ZERO, at(s)));
+    assert_eq!(counted.count(), 1);
+
+    // another song starts from nothing
+    let b = song("b.flac", 1, 2, 4);
+    assert_eq!(counter.listen(Some(&b), Duration::ZERO, at(70)), None);
+    assert_eq!(
+        counter.listen(Some(&b), Duration::ZERO, at(72)),
+        Some("b.flac".into())
+    );
+    assert_eq!(counter.listen(None, Duration::ZERO, at(73)), None);
+}
+
+#[test]
+fn plays_are_recorded() {
+    let (fake, app) = server();
+    let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(app.mpd.record_play("a.flac", at).unwrap(), 1);
+    assert_eq!(app.mpd.record_play("a.flac", SystemTime::now()).unwrap(), 2);
+    let stickers = fake.state().stickers["a.flac"].clone();
+    assert_eq!(stickers[0], ("playCount".into(), "2".into()));
+    assert_eq!(stickers[1].0, "lastPlayed");
+    assert!(stickers[1].1.parse::<u64>().unwrap() > 1_700_000_000);
+}
+
+#[test]
+fn counted_plays_reach_the_sticker() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.set_queue(vec![FakeSong::new("a.flac", &[], 10.0)]);
+        s.current = Some(0);
+        s.play = Playback::Play;
+    }
+    let mut plays = Plays::new(1).with_stickers();
+    plays.observe(&app.mpd);
+    thread::sleep(Duration::from_millis(150));
+    app.mpd.invalidate();
+    plays.observe(&app.mpd);
+    app.mpd.invalidate();
+    plays.observe(&app.mpd);
+    let stickers = fake.state().stickers["a.flac"].clone();
+    assert_eq!(stickers[0], ("playCount".into(), "1".into()));
+}
+
+#[test]
+fn queue_rating_column_and_keys() {
+    let (fake, _) = server();
+    let mut config = fake.config();
+    config.stickers.rating_column = true;
+    let app = fake.app_with(config);
+    {
+        let mut s = fake.state();
+        s.set_queue(
+            ["One", "Two"]
+                .iter()
+                .enumerate()
+                .map(|(i, t)| {
+                    FakeSong::new(
+                        &format!("{}.flac", i),
+                        &[("Artist", "Band"), ("Title", t), ("Track", "1")],
+                        60.0,
+                    )
+                })
+                .collect(),
+        );
+        s.stickers
+            .insert("1.flac".into(), vec![("rating".into(), "2".into())]);
+    }
+    let mut screen = Screen::new(app, 60, 8);
+    screen.keys("2++-+");
+    assert_eq!(
+        fake.state().stickers["0.flac"],
+        [("rating".into(), "2".into())]
+    );
+    screen.assert_snapshot("queue_ratings");
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use common::{eventually, library, server, FakeSong};
+use cursive::{
+    backends::puppet,
+    event::{Event, Key, MouseButton, MouseEvent},
+    Cursive, Printer, View, XY,
+};
+use mpcursive::{
+    mpd_util::{idle, library_cache},
+    view::{library::LibraryView, playlist::Playlist},
+};
+
+fn click(view: &mut impl View, x: usize, y: usize) {
+    view.on_event(Event::Mouse {
+        offset: XY::zero(),
+        position: XY::new(x, y),
+        event: MouseEvent::Press(MouseButton::Left),
+    });
+}
+
+fn chars(view: &mut impl View, s: &str) {
+    for c in s.chars() {
+        view.on_event(Event::Char(c));
+    }
+}
+
+#[test]
+fn double_click_plays_a_queue_row() {
+    let (fake, app) = server();
+    let songs = (0..20)
+        .map(|i| FakeSong::new(&format!("{}.flac", i), &[("Title", "T")], 60.0))
+        .collect();
+    fake.state().set_queue(songs);
+
+    let mut playlist = Playlist::new(app.clone());
+    playlist.layout(XY::new(80, 12));
+    // rows start below the two header lines
+    click(&mut playlist, 10, 5);
+    assert_eq!(fake.state().current, None);
+    click(&mut playlist, 10, 5);
+    assert_eq!(fake.state().current, Some(3));
+
+    // scrolling moves the rows under the pointer
+    playlist.on_event(Event::Mouse {
+        offset: XY::zero(),
+        position: XY::new(0, 5),
+        event: MouseEvent::WheelDown,
+    });
+    playlist.layout(XY::new(80, 12));
+    click(&mut playlist, 10, 3);
+    click(&mut playlist, 10, 3);
+    assert_eq!(fake.state().current, Some(4));
+}
+
+#[test]
+fn library_adds_filtered_songs() {
+    let (fake, app) = server();
+    {
+        let mut s = fake.state();
+        s.db = library(2, 2, 2);
+        s.db_update += 1;
+    }
+    let siv = Cursive::new();
+    library_cache::refresh(&app.mpd, siv.cb_sink().clone());
+    eventually("library", || {
+        app.mpd.library().is_some_and(|l| l.len() == 8)
+    });
+
+    let mut view = LibraryView::new(app.clone());
+    view.layout(XY::new(80, 20));
+    view.on_event(Event::Key(Key::Down));
+    chars(&mut view, "/album:1");
+    view.on_event(Event::Key(Key::Enter));
+    view.on_event(Event::Key(Key::Enter));
+    assert_eq!(
+        fake.state().queue_files(),
+        ["Artist 1/Album 1/01.flac", "Artist 1/Album 1/02.flac"]
+    );
+}
+
+// Counts the refreshes cursive hands down
+struct Refreshes(Arc<AtomicUsize>);
+
+impl View for Refreshes {
+    fn draw(&self, _: &Printer) {}
+
+    fn on_event(&mut self, e: Event) -> cursive::event::EventResult {
+        if e == Event::Refresh {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+        cursive::event::EventResult::Ignored
+    }
+}
+
+#[test]
+fn idle_events_trigger_a_redraw() {
+    let (fake, app) = server();
+    fake.state().volume = 10;
+    assert_eq!(app.mpd.status().unwrap().volume, 10);
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let mut siv = Cursive::new();
+    siv.add_layer(Refreshes(count.clone()));
+    idle::spawn(app.mpd.clone(), siv.cb_sink().clone(), vec![]);
+    let mut runner = siv.into_runner(puppet::Backend::init(Some(XY::new(40, 10))));
+
+    // the idle thread redraws once when it connects
+    eventually("connect", || {
+        runner.step();
+        count.load(Ordering::SeqCst) > 0 && !fake.state().commands("idle").is_empty()
+    });
+    let before = count.load(Ordering::SeqCst);
+    {
+        let mut s = fake.state();
+        s.volume = 90;
+        s.emit("mixer");
+    }
+    eventually("redraw", || {
+        runner.step();
+        count.load(Ordering::SeqCst) > before
+    });
+    // the cached status was dropped rather than waiting out its lifetime
+    assert_eq!(app.mpd.status().unwrap().volume, 90);
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::{f32::consts::PI, fs, sync::Arc, time::Duration};
+
+use common::{eventually, screen::Screen, server};
+use cursive::reexports::crossbeam_channel::unbounded;
+use mpcursive::{
+    config::VisualizerMode,
+    view::visualizer::VisualizerView,
+    visualizer::{self, Format, Pcm, Sample, Spectrum, FFT_SIZE},
+};
+
+const RATE: u32 = 44100;
+
+fn sine(hz: f32, amplitude: f32) -> Vec<f32> {
+    (0..FFT_SIZE)
+        .map(|i| amplitude * (2.0 * PI * hz * i as f32 / RATE as f32).sin())
+        .collect()
+}
+
+#[test]
+fn formats() {
+    let format = Format::parse("44100:16:2").unwrap();
+    assert_eq!(
+        format,
+        Format {
+            rate: 44100,
+            sample: Sample::I16,
+            channels: 2
+        }
+    );
+    assert_eq!(format.frame_bytes(), 4);
+    assert_eq!(Format::parse("48000:f:1").unwrap().sample, Sample::F32);
+    assert!(Format::parse("44100:24:2").is_err());
+    assert!(Format::parse("44100:16").is_err());
+    assert!(Format::parse("0:16:2").is_err());
+
+    // stereo is mixed down, and a trailing partial frame ignored
+    let mut bytes = vec![];
+    for s in [16384i16, -16384, 32767, 32767] {
+        bytes.extend(s.to_ne_bytes());
+    }
+    bytes.push(1);
+    assert_eq!(format.decode(&bytes), [0.0, 32767.0 / 32768.0]);
+
+    let float = Format::parse("48000:f:1").unwrap();
+    assert_eq!(float.decode(&0.25f32.to_ne_bytes()), [0.25]);
+}
+
+#[test]
+fn tones_land_in_their_bar() {
+    // falling instantly, so each update stands alone
+    let mut spectrum = Spectrum::new(0.0, 100.0);
+    let second = Duration::from_secs(1);
+    let levels = spectrum
+        .update(&sine(1000.0, 1.0), RATE, 16, Duration::ZERO)
+        .to_vec();
+    // bars span 40Hz to 16kHz logarithmically, so 1kHz is in the ninth
+    let loudest = (0..16)
+        .max_by(|a, b| levels[*a].total_cmp(&levels[*b]))
+        .unwrap();
+    assert_eq!(loudest, 8);
+    assert!(levels[8] > 0.95, "{:?}", levels);
+    assert!(levels[0] < 0.2 && levels[15] < 0.2, "{:?}", levels);
+
+    // -30dB is half way down a 60dB range
+    let quiet = spectrum.update(&sine(1000.0, 0.0316), RATE, 16, second)[8];
+    assert!((quiet - 0.5).abs() < 0.05, "{}", quiet);
+
+    let silent = spectrum.update(&[0.0; FFT_SIZE], RATE, 16, second);
+    assert!(silent.iter().all(|l| *l == 0.0));
+}
+
+#[test]
+fn smoothing_and_falloff() {
+    let tone = sine(1000.0, 1.0);
+    let silence = [0.0; FFT_SIZE];
+
+    let mut smooth = Spectrum::new(0.5, 100.0);
+    let full = Spectrum::new(0.0, 0.0).update(&tone, RATE, 16, Duration::ZERO)[8];
+    let first = smooth.update(&tone, RATE, 16, Duration::ZERO)[8];
+    assert!((first - full / 2.0).abs() < 1e-4);
+    let second = smooth.update(&tone, RATE, 16, Duration::ZERO)[8];
+    assert!((second - full * 0.75).abs() < 1e-4);
+
+    // bars drop no faster than the falloff allows
+    let mut falling = Spectrum::new(0.0, 2.0);
+    falling.update(&tone, RATE, 16, Duration::ZERO);
+    let dropped = falling.update(&silence, RATE, 16, Duration::from_millis(100))[8];
+    assert!((dropped - (full - 0.2)).abs() < 1e-4);
+    let gone = falling.update(&silence, RATE, 16, Duration::from_secs(1))[8];
+    assert_eq!(gone, 0.0);
+}
+
+#[test]
+fn latest_samples() {
+    let pcm = Pcm::new(RATE);
+    assert_eq!(pcm.latest(4), [0.0; 4]);
+    pcm.push(&[0.1, 0.2, 0.3]);
+    assert_eq!(pcm.latest(4), [0.0, 0.1, 0.2, 0.3]);
+    pcm.push(&vec![0.5; FFT_SIZE]);
+    assert_eq!(pcm.latest(2), [0.5, 0.5]);
+}
+
+#[test]
+fn reads_from_the_fifo_path() {
+    let (fake, _) = server();
+    fs::create_dir_all(fake.dir()).unwrap();
+    // a plain file stands in for the fifo: read to the end, then reopened
+    let path = fake.dir().join("mpd.fifo");
+    let bytes: Vec<u8> = sine(440.0, 0.5)
+        .iter()
+        .flat_map(|s| {
+            let s = ((s * 32767.0) as i16).to_ne_bytes();
+            [s, s].concat()
+        })
+        .collect();
+    fs::write(&path, bytes).unwrap();
+
+    let (sink, _events) = unbounded();
+    let pcm = visualizer::spawn(path, Format::parse("44100:16:2").unwrap(), sink);
+    eventually("samples from the fifo", || {
+        let latest = pcm.latest(FFT_SIZE);
+        (latest[FFT_SIZE - 1] - sine(440.0, 0.5)[FFT_SIZE - 1]).abs() < 1e-3
+    });
+}
+
+fn screen(mode: VisualizerMode, pcm: Option<Arc<Pcm>>) -> Screen {
+    let (fake, _) = server();
+    let mut config = fake.config();
+    config.visualizer.mode = mode;
+    config.visualizer.smoothing = 0.0;
+    config.visualizer.bars = 8;
+    let app = fake.app_with(config);
+    let mut screen = Screen::with(app.clone(), 40, 12, |root, _| {
+        root.register("Visualizer", '6', "▆", VisualizerView::new(app, pcm))
+    });
+    screen.keys("6");
+    screen
+}
+
+#[test]
+fn visualizer_views() {
+    // a loud low tone and a quiet high one
+    let pcm = Arc::new(Pcm::new(RATE));
+    let mixed: Vec<f32> = sine(100.0, 0.5)
+        .iter()
+        .zip(sine(3000.0, 0.1))
+        .map(|(a, b)| a + b)
+        .collect();
+
+    let mut spectrum = screen(VisualizerMode::Spectrum, Some(pcm.clone()));
+    pcm.push(&mixed);
+    spectrum.assert_snapshot("visualizer_spectrum");
+
+    let mut both = screen(VisualizerMode::Both, Some(pcm.clone()));
+    pcm.push(&mixed);
+    both.assert_snapshot("visualizer_both");
+
+    screen(VisualizerMode::Spectrum, None).assert_snapshot("visualizer_unconfigured");
+}